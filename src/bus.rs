@@ -0,0 +1,144 @@
+use std::fmt;
+
+// An access the hardware behind the bus couldn't handle, e.g. a register that doesn't exist
+// or a FIFO that overflowed. The CPU stops with it after the instruction that caused it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BusFault {
+    pub address: u32,
+    pub reason: String,
+}
+
+impl fmt::Display for BusFault {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "bus fault at ${:04x}: {}", self.address, self.reason)
+    }
+}
+
+// Everything the CPU reads or writes goes through a bus. What sits behind it is up to the
+// machine being emulated: plain RAM, ROM, mirrored regions or memory mapped hardware.
+// Addresses are 24 bits wide to fit the 65816, the other variants only drive the low 16 bits.
+pub trait Bus {
+    // A read done by the CPU. Hardware registers are allowed to react to it,
+    // e.g. by clearing a status flag or popping a FIFO.
+    fn read(&mut self, address: u32) -> u8;
+
+    fn write(&mut self, address: u32, data: u8);
+
+    // Look at what a read would return without any side effect, for debuggers and tests
+    fn peek(&self, address: u32) -> u8;
+
+    // Let time pass for the hardware behind the bus, called with the cycles each instruction
+    // (or interrupt) took
+    fn tick(&mut self, _cycles: u64) {}
+
+    // Interrupt lines pulled by the hardware behind the bus
+    fn irq(&self) -> bool {
+        false
+    }
+
+    fn nmi(&self) -> bool {
+        false
+    }
+
+    // The fault raised since the last call, if any. Polled after every instruction
+    fn fault(&mut self) -> Option<BusFault> {
+        None
+    }
+}
+
+// Lets a Processor hold a bus picked at runtime: Processor<Box<dyn Bus>>
+impl<B: Bus + ?Sized> Bus for Box<B> {
+    fn read(&mut self, address: u32) -> u8 {
+        (**self).read(address)
+    }
+
+    fn write(&mut self, address: u32, data: u8) {
+        (**self).write(address, data)
+    }
+
+    fn peek(&self, address: u32) -> u8 {
+        (**self).peek(address)
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        (**self).tick(cycles)
+    }
+
+    fn irq(&self) -> bool {
+        (**self).irq()
+    }
+
+    fn nmi(&self) -> bool {
+        (**self).nmi()
+    }
+
+    fn fault(&mut self) -> Option<BusFault> {
+        (**self).fault()
+    }
+}
+
+// Plain RAM answering for every address, the default bus of a Processor.
+// The size must be a power of two, addresses past the end wrap around.
+pub struct Ram {
+    bytes: Vec<u8>,
+}
+
+impl Ram {
+    pub fn new(size: usize) -> Self {
+        assert!(size.is_power_of_two(), "RAM size {:#x} isn't a power of two", size);
+        Self {
+            bytes: vec![0; size],
+        }
+    }
+
+    pub fn bytes(&self) -> &[u8] {
+        &self.bytes
+    }
+
+    pub fn bytes_mut(&mut self) -> &mut [u8] {
+        &mut self.bytes
+    }
+
+    fn offset(&self, address: u32) -> usize {
+        address as usize & (self.bytes.len() - 1)
+    }
+}
+
+impl Bus for Ram {
+    fn read(&mut self, address: u32) -> u8 {
+        self.peek(address)
+    }
+
+    fn write(&mut self, address: u32, data: u8) {
+        let offset = self.offset(address);
+        self.bytes[offset] = data;
+    }
+
+    fn peek(&self, address: u32) -> u8 {
+        self.bytes[self.offset(address)]
+    }
+}
+
+mod test {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    pub fn ram() {
+        let mut ram = Ram::new(0x800);
+        ram.write(0x0010, 0x42);
+        assert_eq!(ram.read(0x0010), 0x42);
+
+        // Addresses past the end land on the same bytes
+        assert_eq!(ram.peek(0x0810), 0x42);
+        ram.write(0x1fff, 0x24);
+        assert_eq!(ram.bytes()[0x07ff], 0x24);
+    }
+
+    #[test]
+    pub fn boxed() {
+        let mut bus: Box<dyn Bus> = Box::new(Ram::new(0x100));
+        bus.write(0x01, 0x99);
+        assert_eq!(bus.read(0x101), 0x99);
+    }
+}
@@ -0,0 +1,269 @@
+// Memory mapped hardware: timers, UARTs, video chips...
+// A device answers for the range of addresses it is mapped at in the memory map, is told how
+// many cycles went by after every instruction, and can pull the IRQ and NMI lines of the CPU.
+// Addresses are the ones the CPU puts on the bus, not offsets into the range.
+use crate::bus::BusFault;
+
+pub trait Device {
+    // Shown by debuggers next to the region
+    fn name(&self) -> &'static str;
+
+    // A read done by the CPU, which may acknowledge an interrupt, pop a FIFO...
+    fn read(&mut self, address: u32) -> u8;
+
+    fn write(&mut self, address: u32, data: u8);
+
+    // What read() would return, without side effects
+    fn peek(&self, address: u32) -> u8;
+
+    // Let `cycles` CPU cycles go by
+    fn tick(&mut self, _cycles: u64) {}
+
+    // Whether the device is pulling the IRQ line. The CPU keeps taking the interrupt for as long
+    // as it is held, so devices hold it until the handler acknowledges it.
+    fn irq(&self) -> bool {
+        false
+    }
+
+    // Whether the device is pulling the NMI line. Only going from released to pulled triggers
+    fn nmi(&self) -> bool {
+        false
+    }
+
+    // An access the device couldn't handle since the last call. It stops the CPU, which hands
+    // it to the host.
+    fn fault(&mut self) -> Option<BusFault> {
+        None
+    }
+}
+
+mod test {
+    #[allow(unused)]
+    use super::*;
+    #[allow(unused)]
+    use crate::bus::Bus;
+    #[allow(unused)]
+    use crate::memory_map::{MemoryMap, RegionKind};
+    #[allow(unused)]
+    use crate::processor::{CpuError, Processor};
+    #[allow(unused)]
+    use crate::variant::Variant;
+
+    // Fires an IRQ every `period` cycles, acknowledged by reading its status register
+    #[allow(unused)]
+    struct Timer {
+        period: u64,
+        left: u64,
+        fired: bool,
+    }
+
+    impl Device for Timer {
+        fn name(&self) -> &'static str {
+            "timer"
+        }
+
+        fn read(&mut self, address: u32) -> u8 {
+            let status = self.peek(address);
+            self.fired = false;
+            status
+        }
+
+        fn write(&mut self, _address: u32, data: u8) {
+            self.period = data as u64;
+            self.left = self.period;
+        }
+
+        fn peek(&self, _address: u32) -> u8 {
+            (self.fired as u8) << 7
+        }
+
+        fn tick(&mut self, cycles: u64) {
+            if self.period == 0 {
+                return;
+            }
+
+            if cycles >= self.left {
+                self.fired = true;
+                self.left += self.period;
+            }
+            self.left -= cycles;
+        }
+
+        fn irq(&self) -> bool {
+            self.fired
+        }
+    }
+
+    // Holds NMI for the first 100 cycles of every 1000, like a video chip in vertical blank
+    #[allow(unused)]
+    struct Blanking {
+        cycles: u64,
+    }
+
+    impl Device for Blanking {
+        fn name(&self) -> &'static str {
+            "blanking"
+        }
+
+        fn read(&mut self, address: u32) -> u8 {
+            self.peek(address)
+        }
+
+        fn write(&mut self, _address: u32, _data: u8) {}
+
+        fn peek(&self, _address: u32) -> u8 {
+            self.nmi() as u8
+        }
+
+        fn tick(&mut self, cycles: u64) {
+            self.cycles += cycles;
+        }
+
+        fn nmi(&self) -> bool {
+            self.cycles % 1000 < 100
+        }
+    }
+
+    // A one byte transmit buffer, writing a second byte before it is sent is an error
+    #[allow(unused)]
+    struct Uart {
+        pending: Option<u8>,
+        fault: Option<BusFault>,
+    }
+
+    impl Device for Uart {
+        fn name(&self) -> &'static str {
+            "UART"
+        }
+
+        fn read(&mut self, address: u32) -> u8 {
+            self.peek(address)
+        }
+
+        fn write(&mut self, address: u32, data: u8) {
+            if self.pending.replace(data).is_some() {
+                self.fault = Some(BusFault {
+                    address,
+                    reason: "transmit buffer overflow".to_string(),
+                });
+            }
+        }
+
+        fn peek(&self, _address: u32) -> u8 {
+            self.pending.is_some() as u8
+        }
+
+        fn fault(&mut self) -> Option<BusFault> {
+            self.fault.take()
+        }
+    }
+
+    // ROM at $e000 with a main loop and one handler for both interrupts:
+    //   $e000: CLI; NOP; JMP $e001
+    //   $e010: LDA $4000; INC $10; RTI
+    #[allow(unused)]
+    fn machine(device: impl Device + 'static) -> Processor<MemoryMap> {
+        let mut rom = vec![0xea; 0x2000];
+        rom[..5].copy_from_slice(&[0x58, 0xea, 0x4c, 0x01, 0xe0]);
+        rom[0x10..0x16].copy_from_slice(&[0xad, 0x00, 0x40, 0xe6, 0x10, 0x40]);
+        for vector in [0x1ffa, 0x1ffe] {
+            rom[vector..vector + 2].copy_from_slice(&[0x10, 0xe0]);
+        }
+        rom[0x1ffc..0x1ffe].copy_from_slice(&[0x00, 0xe0]);
+
+        let map = MemoryMap::builder()
+            .ram(0x0000..=0x07ff)
+            .device(0x4000..=0x4000, device)
+            .mirror(0x4001..=0x4fff, 0x4000..=0x4000)
+            .rom(0xe000..=0xffff, &rom)
+            .build()
+            .unwrap();
+        Processor::with_bus(Variant::Nmos6502, map)
+    }
+
+    #[test]
+    pub fn irq() {
+        let timer = Timer {
+            period: 0,
+            left: 0,
+            fired: false,
+        };
+        let mut processor = machine(timer);
+        processor.reset();
+        assert_eq!(
+            processor.bus().region_at(0x4000).map(|region| region.kind),
+            Some(RegionKind::Device { name: "timer" })
+        );
+
+        // Start the timer through a mirror of its register
+        processor.write_byte(0x4800, 100);
+        let result = processor.execute(1050);
+        assert!(matches!(result, Err(CpuError::CycleLimit { .. })));
+
+        // Fired every 100 cycles, each time acknowledged by the handler
+        assert_eq!(processor.peek(0x0010), 10);
+        assert_eq!(processor.peek(0x4000), 0x00);
+    }
+
+    #[test]
+    pub fn nmi() {
+        let mut processor = machine(Blanking { cycles: 500 });
+        processor.reset();
+        let result = processor.execute(5000);
+        assert!(matches!(result, Err(CpuError::CycleLimit { .. })));
+
+        // The line stays pulled for a while but only its edges count
+        assert_eq!(processor.peek(0x0010), 5);
+    }
+
+    #[test]
+    pub fn fault() {
+        // LDA #$41; STA $4000; STA $4000; JMP $e008
+        let mut rom = vec![0xea; 0x2000];
+        rom[..11].copy_from_slice(&[
+            0xa9, 0x41, 0x8d, 0x00, 0x40, 0x8d, 0x00, 0x40, 0x4c, 0x08, 0xe0,
+        ]);
+        rom[0x1ffc..0x1ffe].copy_from_slice(&[0x00, 0xe0]);
+        let machine = || {
+            let uart = Uart {
+                pending: None,
+                fault: None,
+            };
+            let map = MemoryMap::builder()
+                .ram(0x0000..=0x07ff)
+                .device(0x4000..=0x4000, uart)
+                .rom(0xe000..=0xffff, &rom)
+                .build()
+                .unwrap();
+            let mut processor = Processor::with_bus(Variant::Nmos6502, map);
+            processor.reset();
+            processor
+        };
+
+        // Stops right after the second store, the host can carry on from there
+        let fault = BusFault {
+            address: 0x4000,
+            reason: "transmit buffer overflow".to_string(),
+        };
+        let mut processor = machine();
+        assert_eq!(
+            processor.execute(0xffff),
+            Err(CpuError::BusFault(fault.clone()))
+        );
+        assert_eq!(processor.execute(0xffff), Ok(3));
+
+        // tick() reports it on the last cycle of the second store
+        let mut processor = machine();
+        let mut cycles = 1;
+        let error = loop {
+            match processor.tick() {
+                Ok(_) => cycles += 1,
+                Err(error) => break error,
+            }
+        };
+        assert_eq!(error, CpuError::BusFault(fault));
+        assert_eq!(cycles, 2 + 4 + 4);
+        assert_eq!(processor.pc(), 0xe008);
+        assert_eq!(processor.tick(), Ok(false));
+    }
+}
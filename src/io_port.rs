@@ -0,0 +1,199 @@
+// On-chip I/O port of the MOS 6510, mapped over the first two bytes of memory
+//   - $0000: Data direction register, a set bit makes the matching pin an output
+//   - $0001: Data register, the level driven on output pins
+// Only pins 0 to 5 exist. Bits 6 and 7 of $0001 read back whatever charge is left on the
+// internal lines, which is the last value driven on them until it leaks away.
+pub struct IoPort {
+    direction: u8,
+    data: u8,
+
+    // Levels the outside world puts on the pins that are inputs
+    input: u8,
+
+    // Charge left on the unconnected bits and the cycles until it has leaked away
+    floating: u8,
+    falloff: u64,
+
+    // Called with the pin levels every time they change
+    hook: Option<Box<dyn FnMut(u8)>>,
+}
+
+impl IoPort {
+    pub const DIRECTION: u16 = 0x0000;
+    pub const DATA: u16 = 0x0001;
+
+    // Banking bits of the C64
+    pub const LORAM: u8 = 0b0000_0001;
+    pub const HIRAM: u8 = 0b0000_0010;
+    pub const CHAREN: u8 = 0b0000_0100;
+
+    // Pins that exist on the package
+    pub const CONNECTED: u8 = 0b0011_1111;
+
+    // Roughly how long the unconnected bits hold their charge
+    pub const FALLOFF_CYCLES: u64 = 350_000;
+
+    // Power on state: every pin is an input. The inputs are pulled up, like on the C64,
+    // until set_input() says otherwise.
+    pub fn new() -> Self {
+        Self {
+            direction: 0x00,
+            data: 0x00,
+            input: Self::CONNECTED,
+            floating: 0x00,
+            falloff: 0,
+            hook: None,
+        }
+    }
+
+    // Register the function called with the new pin levels whenever they change
+    pub fn set_hook(&mut self, hook: impl FnMut(u8) + 'static) {
+        self.hook = Some(Box::new(hook));
+    }
+
+    // Drive the pins from outside (cassette sense, pull-ups...). Only inputs are affected.
+    pub fn set_input(&mut self, input: u8) {
+        self.update(|port| port.input = input & Self::CONNECTED);
+    }
+
+    pub fn direction(&self) -> u8 {
+        self.direction
+    }
+
+    pub fn data(&self) -> u8 {
+        self.data
+    }
+
+    // Levels on the pins: outputs come from the data register, inputs from outside
+    pub fn pins(&self) -> u8 {
+        ((self.data & self.direction) | (self.input & !self.direction)) & Self::CONNECTED
+    }
+
+    // Read $0000 or $0001
+    pub fn read(&self, address: u16) -> u8 {
+        match address {
+            Self::DIRECTION => self.direction,
+            Self::DATA => self.pins() | self.unconnected(),
+            _ => unreachable!("${:04x} isn't part of the I/O port", address),
+        }
+    }
+
+    // Write $0000 or $0001
+    pub fn write(&mut self, address: u16, value: u8) {
+        self.update(|port| match address {
+            Self::DIRECTION => port.direction = value,
+            Self::DATA => port.data = value,
+            _ => unreachable!("${:04x} isn't part of the I/O port", address),
+        });
+
+        // Unconnected bits set as outputs charge up to the data register
+        let driven = self.direction & !Self::CONNECTED;
+        if driven != 0 {
+            self.floating = (self.floating & !driven) | (self.data & driven);
+            self.falloff = Self::FALLOFF_CYCLES;
+        }
+    }
+
+    // Let time pass for the charge on the unconnected bits
+    pub fn tick(&mut self, cycles: u64) {
+        if self.falloff == 0 {
+            return;
+        }
+
+        self.falloff = self.falloff.saturating_sub(cycles);
+        if self.falloff == 0 {
+            self.floating &= self.direction;
+        }
+    }
+
+    // Reset makes every pin an input again
+    pub fn reset(&mut self) {
+        self.update(|port| port.direction = 0x00);
+    }
+
+    // Bits 6 and 7: the data register when they are outputs, the leftover charge otherwise
+    fn unconnected(&self) -> u8 {
+        ((self.data & self.direction) | (self.floating & !self.direction)) & !Self::CONNECTED
+    }
+
+    // Apply a change and tell the hook if the pins changed
+    fn update(&mut self, change: impl FnOnce(&mut Self)) {
+        let before = self.pins();
+        change(self);
+
+        let pins = self.pins();
+        if pins != before {
+            if let Some(hook) = &mut self.hook {
+                hook(pins);
+            }
+        }
+    }
+}
+
+impl Default for IoPort {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+mod test {
+    #[allow(unused)]
+    use super::*;
+    #[allow(unused)]
+    use std::cell::RefCell;
+    #[allow(unused)]
+    use std::rc::Rc;
+
+    #[test]
+    pub fn pins() {
+        let mut port = IoPort::new();
+
+        // Everything is pulled up at power on
+        assert_eq!(port.read(IoPort::DATA), 0b0011_1111);
+
+        // Outputs drive the data register, inputs still read the outside world
+        port.write(IoPort::DIRECTION, 0b0010_1111);
+        port.write(IoPort::DATA, 0b0000_0101);
+        port.set_input(0b0000_0000);
+        assert_eq!(port.read(IoPort::DIRECTION), 0b0010_1111);
+        assert_eq!(port.read(IoPort::DATA), 0b0000_0101);
+        assert_eq!(port.pins() & (IoPort::LORAM | IoPort::HIRAM), IoPort::LORAM);
+    }
+
+    #[test]
+    pub fn hook() {
+        let mut port = IoPort::new();
+        let seen = Rc::new(RefCell::new(Vec::new()));
+        let log = seen.clone();
+        port.set_hook(move |pins| log.borrow_mut().push(pins));
+
+        // Making pulled up pins outputs driving 1 doesn't change anything
+        port.write(IoPort::DATA, 0b0000_0111);
+        port.write(IoPort::DIRECTION, 0b0000_0111);
+        port.write(IoPort::DATA, 0b0000_0110);
+        port.write(IoPort::DATA, 0b0000_0110);
+        port.reset();
+
+        assert_eq!(*seen.borrow(), vec![0b0011_1110, 0b0011_1111]);
+    }
+
+    #[test]
+    pub fn floating_bits() {
+        let mut port = IoPort::new();
+
+        // Bits 6 and 7 read back as 0 until they have been driven
+        port.write(IoPort::DIRECTION, 0b0000_0000);
+        assert_eq!(port.read(IoPort::DATA) & 0b1100_0000, 0b0000_0000);
+
+        port.write(IoPort::DATA, 0b1100_0000);
+        port.write(IoPort::DIRECTION, 0b1100_0000);
+        assert_eq!(port.read(IoPort::DATA) & 0b1100_0000, 0b1100_0000);
+
+        // Once they are inputs again the charge holds for a while, then leaks away
+        port.write(IoPort::DIRECTION, 0b0000_0000);
+        port.tick(IoPort::FALLOFF_CYCLES - 1);
+        assert_eq!(port.read(IoPort::DATA) & 0b1100_0000, 0b1100_0000);
+        port.tick(1);
+        assert_eq!(port.read(IoPort::DATA) & 0b1100_0000, 0b0000_0000);
+    }
+}
@@ -0,0 +1,9 @@
+pub mod bus;
+pub mod device;
+pub mod io_port;
+pub mod mapper;
+pub mod memory_map;
+pub mod operators;
+pub mod processor;
+pub mod status;
+pub mod variant;
@@ -0,0 +1,345 @@
+// Bank switching for ROM images bigger than the window the CPU sees them through.
+// A mapper sits on a banked region of the memory map: it picks the part of the image a read
+// lands on, and writes to the region go to its registers instead of the (read only) ROM.
+// Addresses are the ones the CPU puts on the bus, not offsets into the window.
+use std::ops::RangeInclusive;
+
+pub trait Mapper {
+    // Shown by debuggers next to the region
+    fn name(&self) -> &'static str;
+
+    // Offset into the ROM image of `size` bytes where a read at `address` lands
+    fn map(&self, address: u32, size: usize) -> usize;
+
+    // A write to the region
+    fn write(&mut self, address: u32, data: u8);
+
+    // Cartridges with a second image for the video chip (CHR ROM on the NES) switch it too.
+    // Gives the offset into that image for a read the video chip does at `address`.
+    fn map_chr(&self, address: u16, _size: usize) -> usize {
+        address as usize
+    }
+}
+
+// The simplest board: a latch holding the number of the bank seen through the whole window,
+// loaded with whatever is written to the register range. The window must be aligned on the
+// bank size, and shows the bank again and again when it is bigger.
+pub struct DiscreteLatch {
+    bank_size: usize,
+    registers: RangeInclusive<u32>,
+    bank: usize,
+}
+
+impl DiscreteLatch {
+    pub fn new(bank_size: usize, registers: RangeInclusive<u32>) -> Self {
+        assert!(bank_size.is_power_of_two(), "bank size {:#x} isn't a power of two", bank_size);
+        Self {
+            bank_size,
+            registers,
+            bank: 0,
+        }
+    }
+
+    pub fn bank(&self) -> usize {
+        self.bank
+    }
+}
+
+impl Mapper for DiscreteLatch {
+    fn name(&self) -> &'static str {
+        "latch"
+    }
+
+    fn map(&self, address: u32, _size: usize) -> usize {
+        self.bank * self.bank_size + (address as usize & (self.bank_size - 1))
+    }
+
+    fn write(&mut self, address: u32, data: u8) {
+        if self.registers.contains(&address) {
+            self.bank = data as usize;
+        }
+    }
+}
+
+// NES UxROM: 16 KiB switchable at $8000, the last 16 KiB of the image fixed at $c000.
+// Any write to $8000-$ffff selects the bank.
+pub struct Uxrom {
+    bank: usize,
+}
+
+impl Uxrom {
+    pub fn new() -> Self {
+        Self { bank: 0 }
+    }
+}
+
+impl Default for Uxrom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mapper for Uxrom {
+    fn name(&self) -> &'static str {
+        "UxROM"
+    }
+
+    fn map(&self, address: u32, size: usize) -> usize {
+        let offset = address as usize & 0x3fff;
+        if address & 0x4000 == 0 {
+            self.bank * 0x4000 + offset
+        } else {
+            size.saturating_sub(0x4000) + offset
+        }
+    }
+
+    fn write(&mut self, _address: u32, data: u8) {
+        self.bank = data as usize;
+    }
+}
+
+// NES CNROM: fixed program ROM, any write to $8000-$ffff picks the 8 KiB CHR bank
+pub struct Cnrom {
+    chr_bank: usize,
+}
+
+impl Cnrom {
+    pub fn new() -> Self {
+        Self { chr_bank: 0 }
+    }
+}
+
+impl Default for Cnrom {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mapper for Cnrom {
+    fn name(&self) -> &'static str {
+        "CNROM"
+    }
+
+    // 16 KiB images show up twice
+    fn map(&self, address: u32, _size: usize) -> usize {
+        address as usize & 0x7fff
+    }
+
+    fn write(&mut self, _address: u32, data: u8) {
+        self.chr_bank = data as usize;
+    }
+
+    fn map_chr(&self, address: u16, _size: usize) -> usize {
+        self.chr_bank * 0x2000 + (address as usize & 0x1fff)
+    }
+}
+
+// NES MMC1. Registers are loaded serially, one bit per write to $8000-$ffff:
+//   - bit 7 set: clear the shift register and go back to PRG mode 3
+//   - otherwise bit 0 is shifted in, and the fifth write stores the 5 bits in the register
+//     picked by the address: $8000 control, $a000 CHR bank 0, $c000 CHR bank 1, $e000 PRG bank
+// Control bits:
+//   - 0-1: nametable mirroring, left to the video chip
+//   - 2-3: PRG mode. 0/1: 32 KiB at $8000, 2: first bank fixed at $8000 and 16 KiB switchable
+//          at $c000, 3: 16 KiB switchable at $8000 and the last bank fixed at $c000
+//   - 4: CHR mode. 0: one 8 KiB bank, 1: two 4 KiB banks
+pub struct Mmc1 {
+    shift: u8,
+    writes: u8,
+    control: u8,
+    chr_banks: [u8; 2],
+    prg_bank: u8,
+}
+
+impl Mmc1 {
+    pub fn new() -> Self {
+        Self {
+            shift: 0,
+            writes: 0,
+            control: 0x0c,
+            chr_banks: [0; 2],
+            prg_bank: 0,
+        }
+    }
+
+    pub fn control(&self) -> u8 {
+        self.control
+    }
+}
+
+impl Default for Mmc1 {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Mapper for Mmc1 {
+    fn name(&self) -> &'static str {
+        "MMC1"
+    }
+
+    fn map(&self, address: u32, size: usize) -> usize {
+        let bank = (self.prg_bank & 0x0f) as usize;
+        let offset = address as usize & 0x3fff;
+        let high = address & 0x4000 != 0;
+
+        let bank = match (self.control >> 2) & 0b11 {
+            0 | 1 => (bank & !1) + high as usize,
+            2 if high => bank,
+            2 => 0,
+            _ if high => (size / 0x4000).saturating_sub(1),
+            _ => bank,
+        };
+        bank * 0x4000 + offset
+    }
+
+    fn write(&mut self, address: u32, data: u8) {
+        if data & 0x80 != 0 {
+            self.shift = 0;
+            self.writes = 0;
+            self.control |= 0x0c;
+            return;
+        }
+
+        self.shift |= (data & 1) << self.writes;
+        self.writes += 1;
+        if self.writes < 5 {
+            return;
+        }
+
+        match (address >> 13) & 0b11 {
+            0 => self.control = self.shift,
+            1 => self.chr_banks[0] = self.shift,
+            2 => self.chr_banks[1] = self.shift,
+            _ => self.prg_bank = self.shift,
+        }
+        self.shift = 0;
+        self.writes = 0;
+    }
+
+    fn map_chr(&self, address: u16, _size: usize) -> usize {
+        let address = address as usize & 0x1fff;
+        if self.control & 0x10 == 0 {
+            (self.chr_banks[0] & !1) as usize * 0x1000 + address
+        } else {
+            self.chr_banks[address >> 12] as usize * 0x1000 + (address & 0x0fff)
+        }
+    }
+}
+
+mod test {
+    #[allow(unused)]
+    use super::*;
+    #[allow(unused)]
+    use crate::bus::Bus;
+    #[allow(unused)]
+    use crate::memory_map::{MemoryMap, RegionKind};
+
+    // An image made of `banks` banks of `size` bytes, each filled with its own number
+    #[allow(unused)]
+    fn image(banks: usize, size: usize) -> Vec<u8> {
+        (0..banks * size).map(|i| (i / size) as u8).collect()
+    }
+
+    #[allow(unused)]
+    fn cartridge(banks: usize, size: usize, mapper: impl Mapper + 'static) -> MemoryMap {
+        MemoryMap::builder()
+            .ram(0x0000..=0x07ff)
+            .banked(0x8000..=0xffff, &image(banks, size), mapper)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    pub fn discrete_latch() {
+        let mut map = cartridge(4, 0x8000, DiscreteLatch::new(0x8000, 0x8000..=0xffff));
+        assert_eq!((map.read(0x8000), map.read(0xffff)), (0, 0));
+
+        map.write(0x9000, 2);
+        assert_eq!((map.read(0x8000), map.read(0xffff)), (2, 2));
+
+        // Bank numbers past the end of the image wrap around
+        map.write(0x9000, 5);
+        assert_eq!(map.read(0x8000), 1);
+
+        // Writes outside the register range are ignored
+        let mut map = cartridge(4, 0x4000, DiscreteLatch::new(0x4000, 0xf000..=0xffff));
+        map.write(0x8000, 3);
+        assert_eq!((map.read(0x8000), map.read(0xc000)), (0, 0));
+        map.write(0xf000, 3);
+        assert_eq!((map.read(0x8000), map.read(0xc000)), (3, 3));
+    }
+
+    #[test]
+    pub fn uxrom() {
+        let mut map = cartridge(8, 0x4000, Uxrom::new());
+        assert_eq!((map.read(0x8000), map.read(0xc000)), (0, 7));
+
+        map.write(0x8000, 3);
+        assert_eq!((map.read(0xbfff), map.read(0xffff)), (3, 7));
+        assert_eq!(
+            map.region_at(0x8000).map(|region| region.kind),
+            Some(RegionKind::Banked { mapper: "UxROM" })
+        );
+    }
+
+    #[test]
+    pub fn cnrom() {
+        let mut map = cartridge(1, 0x4000, Cnrom::new());
+        assert_eq!((map.read(0x8000), map.read(0xc000)), (0, 0));
+
+        map.write(0x8000, 2);
+        let mapper = map.mapper_at(0x8000).unwrap();
+        assert_eq!(mapper.map_chr(0x0123, 0x8000), 0x4123);
+    }
+
+    #[test]
+    pub fn mmc1() {
+        // Load a register the way games do: five writes, LSB first
+        #[allow(unused)]
+        fn load(map: &mut MemoryMap, address: u32, value: u8) {
+            for bit in 0..5 {
+                map.write(address, value >> bit & 1);
+            }
+        }
+
+        let mut map = cartridge(8, 0x4000, Mmc1::new());
+
+        // Power on: 16 KiB switchable at $8000, last bank fixed at $c000
+        assert_eq!((map.read(0x8000), map.read(0xc000)), (0, 7));
+        load(&mut map, 0xe000, 5);
+        assert_eq!((map.read(0x8000), map.read(0xc000)), (5, 7));
+
+        // Mode 2: first bank fixed at $8000
+        load(&mut map, 0x8000, 0b0_1000);
+        assert_eq!((map.read(0x8000), map.read(0xc000)), (0, 5));
+
+        // 32 KiB mode ignores the low bit of the bank
+        load(&mut map, 0x8000, 0b0_0000);
+        assert_eq!((map.read(0x8000), map.read(0xc000)), (4, 5));
+
+        // Bit 7 resets the shift register half way through a load
+        map.write(0xe000, 1);
+        map.write(0xe000, 0x80);
+        assert_eq!((map.read(0x8000), map.read(0xc000)), (5, 7));
+
+        // Two 4 KiB CHR banks
+        load(&mut map, 0x8000, 0b1_1100);
+        load(&mut map, 0xa000, 3);
+        load(&mut map, 0xc000, 6);
+        let mapper = map.mapper_at(0xc000).unwrap();
+        assert_eq!(mapper.map_chr(0x0010, 0x10000), 0x3010);
+        assert_eq!(mapper.map_chr(0x1010, 0x10000), 0x6010);
+    }
+
+    #[test]
+    pub fn mmc1_small_image() {
+        // Under 16 KiB there is only bank 0 to fix at $c000, mirrored like the rest of the image
+        let mut map = cartridge(2, 0x1000, Mmc1::new());
+        assert_eq!((map.read(0x8000), map.read(0x9000)), (0, 1));
+        assert_eq!(
+            (map.read(0xc000), map.read(0xd000), map.read(0xe000)),
+            (0, 1, 0)
+        );
+    }
+}
@@ -0,0 +1,485 @@
+use crate::bus::{Bus, BusFault};
+use crate::device::Device;
+use crate::mapper::Mapper;
+use std::fmt;
+use std::ops::RangeInclusive;
+
+// What answers for a range of addresses
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RegionKind {
+    Ram,
+    // Read only, writes are ignored
+    Rom,
+    // The `size` bytes starting at `source`, seen again over and over
+    Mirror { source: u32, size: u32 },
+    // ROM switched in and out by a mapper
+    Banked { mapper: &'static str },
+    // Memory mapped hardware
+    Device { name: &'static str },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Region {
+    pub range: RangeInclusive<u32>,
+    pub kind: RegionKind,
+}
+
+impl fmt::Display for Region {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "${:04x}-${:04x} ", self.range.start(), self.range.end())?;
+        match self.kind {
+            RegionKind::Ram => write!(f, "RAM"),
+            RegionKind::Rom => write!(f, "ROM"),
+            RegionKind::Banked { mapper } => write!(f, "ROM banked by {}", mapper),
+            RegionKind::Device { name } => write!(f, "{}", name),
+            RegionKind::Mirror { source, size } => {
+                write!(f, "mirror of ${:04x}-${:04x}", source, source + size - 1)
+            }
+        }
+    }
+}
+
+// Everything build() can reject
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MapError {
+    // A range ending before it starts
+    EmptyRange(RangeInclusive<u32>),
+    // Two regions claim the same addresses
+    Overlap(Region, Region),
+    // A ROM image that is empty or bigger than its range. Banked images only have to be
+    // non empty
+    RomSize { range: RangeInclusive<u32>, size: usize },
+    // A mirror whose source isn't entirely inside one region that isn't a mirror itself
+    MirrorSource(Region),
+}
+
+impl fmt::Display for MapError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MapError::EmptyRange(range) => {
+                write!(f, "${:04x}-${:04x} is empty", range.start(), range.end())
+            }
+            MapError::Overlap(first, second) => write!(f, "{} overlaps {}", first, second),
+            MapError::RomSize { range, size } => write!(
+                f,
+                "a ROM of {} bytes doesn't fit ${:04x}-${:04x}",
+                size,
+                range.start(),
+                range.end()
+            ),
+            MapError::MirrorSource(region) => {
+                write!(f, "{} doesn't mirror a single region", region)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MapError {}
+
+// Declares what sits where on the bus, then checks it all fits together
+pub struct MemoryMapBuilder {
+    mappings: Vec<Mapping>,
+}
+
+impl MemoryMapBuilder {
+    pub fn ram(self, range: RangeInclusive<u32>) -> Self {
+        self.region(range, RegionKind::Ram, Backing::Bytes(Vec::new()))
+    }
+
+    // A ROM image shorter than the range repeats to fill it, like a 16 KiB cartridge
+    // answering for a 32 KiB window
+    pub fn rom(self, range: RangeInclusive<u32>, image: &[u8]) -> Self {
+        self.region(range, RegionKind::Rom, Backing::Bytes(image.to_vec()))
+    }
+
+    // A ROM image seen through a window whose banks the mapper switches
+    pub fn banked(
+        self,
+        range: RangeInclusive<u32>,
+        image: &[u8],
+        mapper: impl Mapper + 'static,
+    ) -> Self {
+        let kind = RegionKind::Banked {
+            mapper: mapper.name(),
+        };
+        self.region(range, kind, Backing::Banked(image.to_vec(), Box::new(mapper)))
+    }
+
+    // Hardware answering for the range
+    pub fn device(self, range: RangeInclusive<u32>, device: impl Device + 'static) -> Self {
+        let kind = RegionKind::Device {
+            name: device.name(),
+        };
+        self.region(range, kind, Backing::Device(Box::new(device)))
+    }
+
+    // Make `range` show the contents of `source` again, e.g. the registers of a device
+    // repeated over a bigger range. The source must already be mapped.
+    pub fn mirror(self, range: RangeInclusive<u32>, source: RangeInclusive<u32>) -> Self {
+        let size = source.end().wrapping_sub(*source.start()).wrapping_add(1);
+        let kind = RegionKind::Mirror {
+            source: *source.start(),
+            size,
+        };
+        self.region(range, kind, Backing::Mirror(0))
+    }
+
+    fn region(mut self, range: RangeInclusive<u32>, kind: RegionKind, backing: Backing) -> Self {
+        self.mappings.push(Mapping {
+            region: Region { range, kind },
+            backing,
+        });
+        self
+    }
+
+    pub fn build(mut self) -> Result<MemoryMap, MapError> {
+        for Mapping { region, backing } in &mut self.mappings {
+            if region.range.is_empty() {
+                return Err(MapError::EmptyRange(region.range.clone()));
+            }
+
+            let length = (region.range.end() - region.range.start()) as usize + 1;
+            match backing {
+                Backing::Bytes(data) if region.kind == RegionKind::Ram => *data = vec![0; length],
+                Backing::Bytes(data) if data.is_empty() || data.len() > length => {
+                    return Err(MapError::RomSize {
+                        range: region.range.clone(),
+                        size: data.len(),
+                    });
+                }
+                // Banked images are meant to be bigger than their window
+                Backing::Banked(data, _) if data.is_empty() => {
+                    return Err(MapError::RomSize {
+                        range: region.range.clone(),
+                        size: 0,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        // Once sorted, a region can only overlap the one right before it
+        self.mappings.sort_by_key(|mapping| *mapping.region.range.start());
+        for pair in self.mappings.windows(2) {
+            let (first, second) = (&pair[0].region, &pair[1].region);
+            if second.range.start() <= first.range.end() {
+                return Err(MapError::Overlap(first.clone(), second.clone()));
+            }
+        }
+
+        for index in 0..self.mappings.len() {
+            let region = &self.mappings[index].region;
+            let RegionKind::Mirror { source, size } = region.kind else {
+                continue;
+            };
+
+            let end = source.checked_add(size - 1);
+            let target = self.mappings.iter().position(|target| {
+                target.region.range.contains(&source)
+                    && !matches!(target.region.kind, RegionKind::Mirror { .. })
+                    && end.is_some_and(|end| end <= *target.region.range.end())
+            });
+            match target {
+                Some(target) => self.mappings[index].backing = Backing::Mirror(target),
+                None => return Err(MapError::MirrorSource(region.clone())),
+            }
+        }
+
+        Ok(MemoryMap {
+            mappings: self.mappings,
+            open_bus: 0x00,
+        })
+    }
+}
+
+struct Mapping {
+    region: Region,
+    backing: Backing,
+}
+
+enum Backing {
+    Bytes(Vec<u8>),
+    // ROM image and the mapper picking which part of it is seen
+    Banked(Vec<u8>, Box<dyn Mapper>),
+    Device(Box<dyn Device>),
+    // Index of the mapping being mirrored
+    Mirror(usize),
+}
+
+// A bus made of RAM, ROM, banked, device and mirrored regions. Reads from unmapped addresses
+// return the last value that was on the data bus, like the floating lines of the real thing.
+pub struct MemoryMap {
+    // Sorted by address
+    mappings: Vec<Mapping>,
+    open_bus: u8,
+}
+
+impl MemoryMap {
+    pub fn builder() -> MemoryMapBuilder {
+        MemoryMapBuilder {
+            mappings: Vec::new(),
+        }
+    }
+
+    pub fn regions(&self) -> impl Iterator<Item = &Region> {
+        self.mappings.iter().map(|mapping| &mapping.region)
+    }
+
+    pub fn region_at(&self, address: u32) -> Option<&Region> {
+        Some(&self.mappings[self.find(address)?].region)
+    }
+
+    // The mapper of the banked region at the address, for the video chip and debuggers
+    pub fn mapper_at(&self, address: u32) -> Option<&dyn Mapper> {
+        match &self.mappings[self.find(address)?].backing {
+            Backing::Banked(_, mapper) => Some(mapper.as_ref()),
+            _ => None,
+        }
+    }
+
+    // The last value seen on the data bus
+    pub fn open_bus(&self) -> u8 {
+        self.open_bus
+    }
+
+    fn devices(&self) -> impl Iterator<Item = &dyn Device> {
+        self.mappings.iter().filter_map(|mapping| match &mapping.backing {
+            Backing::Device(device) => Some(device.as_ref()),
+            _ => None,
+        })
+    }
+
+    fn find(&self, address: u32) -> Option<usize> {
+        self.mappings
+            .iter()
+            .position(|mapping| mapping.region.range.contains(&address))
+    }
+
+    // The mapping and address an access lands on, after following mirrors
+    fn locate(&self, address: u32) -> Option<(usize, u32)> {
+        let index = self.find(address)?;
+        let mapping = &self.mappings[index];
+
+        match (&mapping.backing, mapping.region.kind) {
+            (Backing::Mirror(target), RegionKind::Mirror { source, size }) => {
+                let offset = (address - mapping.region.range.start()) % size;
+                Some((*target, source + offset))
+            }
+            _ => Some((index, address)),
+        }
+    }
+}
+
+impl Bus for MemoryMap {
+    fn read(&mut self, address: u32) -> u8 {
+        let Some((index, address)) = self.locate(address) else {
+            return self.open_bus;
+        };
+
+        self.open_bus = match &mut self.mappings[index].backing {
+            Backing::Device(device) => device.read(address),
+            _ => self.peek(address),
+        };
+        self.open_bus
+    }
+
+    fn write(&mut self, address: u32, data: u8) {
+        self.open_bus = data;
+
+        let Some((index, address)) = self.locate(address) else {
+            return;
+        };
+        let mapping = &mut self.mappings[index];
+        let offset = (address - mapping.region.range.start()) as usize;
+        match &mut mapping.backing {
+            Backing::Bytes(bytes) if mapping.region.kind == RegionKind::Ram => {
+                bytes[offset] = data
+            }
+            Backing::Banked(_, mapper) => mapper.write(address, data),
+            Backing::Device(device) => device.write(address, data),
+            _ => {}
+        }
+    }
+
+    fn peek(&self, address: u32) -> u8 {
+        let Some((index, address)) = self.locate(address) else {
+            return self.open_bus;
+        };
+        let mapping = &self.mappings[index];
+        match &mapping.backing {
+            Backing::Bytes(bytes) => {
+                let offset = (address - mapping.region.range.start()) as usize;
+                bytes[offset % bytes.len()]
+            }
+            Backing::Banked(image, mapper) => image[mapper.map(address, image.len()) % image.len()],
+            Backing::Device(device) => device.peek(address),
+            Backing::Mirror(_) => unreachable!("mirrors don't point to other mirrors"),
+        }
+    }
+
+    fn tick(&mut self, cycles: u64) {
+        for mapping in &mut self.mappings {
+            if let Backing::Device(device) = &mut mapping.backing {
+                device.tick(cycles);
+            }
+        }
+    }
+
+    // The lines are wired OR: any device can pull them
+    fn irq(&self) -> bool {
+        self.devices().any(|device| device.irq())
+    }
+
+    fn nmi(&self) -> bool {
+        self.devices().any(|device| device.nmi())
+    }
+
+    // The first device that faulted. The others keep theirs for the next call
+    fn fault(&mut self) -> Option<BusFault> {
+        self.mappings
+            .iter_mut()
+            .find_map(|mapping| match &mut mapping.backing {
+                Backing::Device(device) => device.fault(),
+                _ => None,
+            })
+    }
+}
+
+// One region per line, for debuggers
+impl fmt::Display for MemoryMap {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for region in self.regions() {
+            writeln!(f, "{}", region)?;
+        }
+        Ok(())
+    }
+}
+
+mod test {
+    #[allow(unused)]
+    use super::*;
+    #[allow(unused)]
+    use crate::processor::Processor;
+    #[allow(unused)]
+    use crate::variant::Variant;
+
+    // RAM mirrored up to $1fff and an 8 KiB ROM at the top, with the RESET vector at $e000
+    #[allow(unused)]
+    fn small_machine() -> MemoryMap {
+        let mut rom = vec![0xea; 0x2000];
+        rom[0x1ffc] = 0x00;
+        rom[0x1ffd] = 0xe0;
+
+        MemoryMap::builder()
+            .rom(0xe000..=0xffff, &rom)
+            .ram(0x0000..=0x07ff)
+            .mirror(0x0800..=0x1fff, 0x0000..=0x07ff)
+            .build()
+            .unwrap()
+    }
+
+    #[test]
+    pub fn ram_rom_and_mirrors() {
+        let mut map = small_machine();
+
+        map.write(0x0010, 0x42);
+        assert_eq!(map.read(0x0810), 0x42);
+        assert_eq!(map.read(0x1810), 0x42);
+        map.write(0x1fff, 0x24);
+        assert_eq!(map.peek(0x07ff), 0x24);
+
+        // ROM ignores writes
+        map.write(0xe000, 0x00);
+        assert_eq!(map.read(0xe000), 0xea);
+    }
+
+    #[test]
+    pub fn open_bus() {
+        let mut map = small_machine();
+
+        map.write(0x0000, 0x55);
+        assert_eq!(map.read(0x4000), 0x55);
+        assert_eq!(map.read(0xfffd), 0xe0);
+        assert_eq!(map.read(0x4000), 0xe0);
+        assert_eq!(map.peek(0x6000), 0xe0);
+        assert_eq!(map.open_bus(), 0xe0);
+    }
+
+    #[test]
+    pub fn short_rom_repeats() {
+        let map = MemoryMap::builder()
+            .rom(0x8000..=0xffff, &[0x01, 0x02])
+            .build()
+            .unwrap();
+        assert_eq!((map.peek(0x8000), map.peek(0xffff)), (0x01, 0x02));
+    }
+
+    #[test]
+    pub fn rejected_maps() {
+        let error = MemoryMap::builder()
+            .ram(0x0000..=0x07ff)
+            .rom(0xe000..=0xffff, &[0; 0x2000])
+            .ram(0x0400..=0x0fff)
+            .build()
+            .err();
+        assert_eq!(
+            error.map(|error| error.to_string()),
+            Some("$0000-$07ff RAM overlaps $0400-$0fff RAM".to_string())
+        );
+
+        let error = MemoryMap::builder().rom(0xf000..=0xffff, &[0; 0x2000]).build();
+        assert!(matches!(error, Err(MapError::RomSize { size: 0x2000, .. })));
+
+        let error = MemoryMap::builder()
+            .ram(0x0000..=0x07ff)
+            .mirror(0x0800..=0x1fff, 0x0400..=0x0bff)
+            .build();
+        assert!(matches!(error, Err(MapError::MirrorSource(_))));
+
+        #[allow(clippy::reversed_empty_ranges)]
+        let error = MemoryMap::builder().ram(0x0800..=0x07ff).build();
+        assert!(matches!(error, Err(MapError::EmptyRange(_))));
+    }
+
+    #[test]
+    pub fn inspect() {
+        let map = small_machine();
+        assert_eq!(
+            map.to_string(),
+            "$0000-$07ff RAM\n$0800-$1fff mirror of $0000-$07ff\n$e000-$ffff ROM\n"
+        );
+        let mirror = RegionKind::Mirror {
+            source: 0x0000,
+            size: 0x0800,
+        };
+        assert_eq!(map.region_at(0x1234).map(|region| region.kind), Some(mirror));
+        assert_eq!(map.region_at(0x4000), None);
+    }
+
+    #[test]
+    pub fn run_from_rom() {
+        let mut rom = vec![0x00; 0x2000];
+        // LDA #$42; STA $0810; LDA $4000; STA $11; JMP $e00a
+        rom[..13].copy_from_slice(&[
+            0xa9, 0x42, 0x8d, 0x10, 0x08, 0xad, 0x00, 0x40, 0x85, 0x11, 0x4c, 0x0a, 0xe0,
+        ]);
+        rom[0x1ffc] = 0x00;
+        rom[0x1ffd] = 0xe0;
+        let map = MemoryMap::builder()
+            .ram(0x0000..=0x07ff)
+            .mirror(0x0800..=0x1fff, 0x0000..=0x07ff)
+            .rom(0xe000..=0xffff, &rom)
+            .build()
+            .unwrap();
+
+        let mut processor = Processor::with_bus(Variant::Nmos6502, map);
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.bus().peek(0x0010), 0x42);
+
+        // The unmapped read returned the last byte fetched, the high byte of its address
+        assert_eq!(processor.peek(0x0011), 0x40);
+        assert_eq!(processor.peek(0x4000), 0xe0);
+    }
+}
@@ -1,388 +1,1319 @@
-// OPCODES for our processor
+use std::fmt;
+
+use self::AddressingMode::*;
+use self::Mnemonic::*;
+
+// Addressing modes of the 6502 family
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressingMode {
+    Implied,     // No operand
+    Accumulator, // A
+    Immediate,   // #$nn
+    ZeroPage,    // $nn
+    ZeroPageX,   // $nn,X
+    ZeroPageY,   // $nn,Y
+    Absolute,    // $nnnn
+    AbsoluteX,   // $nnnn,X
+    AbsoluteY,   // $nnnn,Y
+    Indirect,    // ($nnnn)
+    IndirectX,   // ($nn,X)
+    IndirectY,   // ($nn),Y
+    Relative,    // Signed 8 bit offset from the next instruction
+
+    // 65C02 and 65816
+    ZeroPageIndirect,        // ($nn)
+    AbsoluteIndexedIndirect, // ($nnnn,X)
+    ZeroPageRelative,        // $nn followed by a relative offset, for BBR/BBS
+
+    // 65816 only. The zero page is the direct page there
+    ImmediateWord,          // #$nnnn, immediate for a 16 bit register
+    AbsoluteLong,           // $nnnnnn
+    AbsoluteLongX,          // $nnnnnn,X
+    ZeroPageIndirectLong,   // [$nn]
+    ZeroPageIndirectLongY,  // [$nn],Y
+    StackRelative,          // $nn,S
+    StackRelativeIndirectY, // ($nn,S),Y
+    AbsoluteIndirectLong,   // [$nnnn]
+    BlockMove,              // Destination bank then source bank, written $ss,$dd
+    RelativeLong,           // Signed 16 bit offset from the next instruction
+}
+
+impl AddressingMode {
+    // Amount of bytes that follow the opcode
+    pub fn operand_bytes(&self) -> u16 {
+        match self {
+            Implied | Accumulator => 0,
+            Immediate | ZeroPage | ZeroPageX | ZeroPageY | IndirectX | IndirectY | Relative => 1,
+            ZeroPageIndirect | ZeroPageIndirectLong | ZeroPageIndirectLongY => 1,
+            StackRelative | StackRelativeIndirectY => 1,
+            Absolute | AbsoluteX | AbsoluteY | Indirect => 2,
+            AbsoluteIndexedIndirect | ZeroPageRelative => 2,
+            ImmediateWord | AbsoluteIndirectLong | BlockMove | RelativeLong => 2,
+            AbsoluteLong | AbsoluteLongX => 3,
+        }
+    }
+
+    // Modes going through the zero (direct) page
+    pub fn is_zero_page(&self) -> bool {
+        matches!(
+            self,
+            ZeroPage
+                | ZeroPageX
+                | ZeroPageY
+                | IndirectX
+                | IndirectY
+                | ZeroPageIndirect
+                | ZeroPageIndirectLong
+                | ZeroPageIndirectLongY
+        )
+    }
+}
+
+// Instruction mnemonics. The ones after JAM are undocumented NMOS instructions,
+// the ones after BRA are CMOS additions and the ones after BRL only exist on the 65816.
+// The bit operations carry the bit number.
 // Allow non rust approved naming for ease of reading
-#[allow(non_snake_case, non_camel_case_types)]
-#[derive(Debug, PartialEq, Eq)]
-pub enum OPCodes {
-    ORA_XIND(u8),
-    ORA_YIND(u8),
-    ORA_ZPG(u8),
-    ORA_XZPG(u8),
-    ORA_ABS(u16),
-    ORA_YABS(u16),
-    ORA_XABS(u16),
-    ORA_IMM(u8),
-    AND_XIND(u8),
-    AND_YIND(u8),
-    AND_ZPG(u8),
-    AND_XZPG(u8),
-    AND_ABS(u16),
-    AND_YABS(u16),
-    AND_XABS(u16),
-    AND_IMM(u8),
-    EOR_XIND(u8),
-    EOR_YIND(u8),
-    EOR_ZPG(u8),
-    EOR_XZPG(u8),
-    EOR_ABS(u16),
-    EOR_YABS(u16),
-    EOR_XABS(u16),
-    EOR_IMM(u8),
-    ADC_XIND(u8),
-    ADC_YIND(u8),
-    ADC_ZPG(u8),
-    ADC_XZPG(u8),
-    ADC_ABS(u16),
-    ADC_YABS(u16),
-    ADC_XABS(u16),
-    ADC_IMM(u8),
-    CMP_XIND(u8),
-    CMP_YIND(u8),
-    CMP_ZPG(u8),
-    CMP_XZPG(u8),
-    CMP_ABS(u16),
-    CMP_YABS(u16),
-    CMP_XABS(u16),
-    CMP_IMM(u8),
-    LDA_XIND(u8),
-    LDA_YIND(u8),
-    LDA_ZPG(u8),
-    LDA_XZPG(u8),
-    LDA_ABS(u16),
-    LDA_YABS(u16),
-    LDA_XABS(u16),
-    LDA_IMM(u8),
-    SBC_XIND(u8),
-    SBC_YIND(u8),
-    SBC_ZPG(u8),
-    SBC_XZPG(u8),
-    SBC_ABS(u16),
-    SBC_YABS(u16),
-    SBC_XABS(u16),
-    SBC_IMM(u8),
-    STA_XIND(u8),
-    STA_YIND(u8),
-    STA_ZPG(u8),
-    STA_XZPG(u8),
-    STA_ABS(u16),
-    STA_YABS(u16),
-    STA_XABS(u16),
-    BMI(i8),
-    BCC(i8),
-    BCS(i8),
-    BEQ(i8),
-    BNE(i8),
-    BPL(i8),
-    BVC(i8),
-    BVS(i8),
-    ASL_ZPG(u8),
-    ASL_XZPG(u8),
-    ASL_A,
-    ASL_ABS(u16),
-    ASL_XABS(u16),
-    ROL_ZPG(u8),
-    ROL_XZPG(u8),
-    ROL_A,
-    ROL_ABS(u16),
-    ROL_XABS(u16),
-    LSR_ZPG(u8),
-    LSR_XZPG(u8),
-    LSR_A,
-    LSR_ABS(u16),
-    LSR_XABS(u16),
-    ROR_ZPG(u8),
-    ROR_XZPG(u8),
-    ROR_A,
-    ROR_ABS(u16),
-    ROR_XABS(u16),
-    LDX_ZPG(u8),
-    LDX_YZPG(u8),
-    LDX_ABS(u16),
-    LDX_YABS(u16),
-    LDX_IMM(u8),
-    LDY_ZPG(u8),
-    LDY_XZPG(u8),
-    LDY_ABS(u16),
-    LDY_XABS(u16),
-    LDY_IMM(u8),
-    DEC_ZPG(u8),
-    DEC_XZPG(u8),
-    DEC_ABS(u16),
-    DEC_XABS(u16),
-    INC_ZPG(u8),
-    INC_XZPG(u8),
-    INC_ABS(u16),
-    INC_XABS(u16),
-    STX_ZPG(u8),
-    STX_YZPG(u8),
-    STX_ABS(u16),
-    STY_ZPG(u8),
-    STY_XZPG(u8),
-    STY_ABS(u16),
-    CPX_ZPG(u8),
-    CPX_ABS(u16),
-    CPX_IMM(u8),
-    CPY_ZPG(u8),
-    CPY_ABS(u16),
-    CPY_IMM(u8),
-    BIT_ZPG(u8),
-    BIT_ABS(u16),
-    JMP_IND(u16),
-    JMP_ABS(u16),
-    CLC,
-    CLD,
-    CLI,
-    CLV,
-    SEC,
-    SED,
-    SEI,
-    PHP,
-    PHA,
-    PLP,
-    PLA,
-    RTI,
-    RTS,
-    TAX,
-    TAY,
-    TSX,
-    TXA,
-    TXS,
-    TYA,
-    BRK,
-    JSR(u16),
-    NOP,
-    DEX,
-    DEY,
-    INX,
-    INY,
+#[allow(clippy::upper_case_acronyms)]
+#[rustfmt::skip]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mnemonic {
+    ADC, AND, ASL, BCC, BCS, BEQ, BIT, BMI, BNE, BPL, BRK, BVC, BVS, CLC,
+    CLD, CLI, CLV, CMP, CPX, CPY, DEC, DEX, DEY, EOR, INC, INX, INY, JMP,
+    JSR, LDA, LDX, LDY, LSR, NOP, ORA, PHA, PHP, PLA, PLP, ROL, ROR, RTI,
+    RTS, SBC, SEC, SED, SEI, STA, STX, STY, TAX, TAY, TSX, TXA, TXS, TYA,
+
+    JAM, SLO, RLA, SRE, RRA, SAX, LAX, DCP, ISC, ANC, ALR, ARR, XAA, LAS,
+    SBX, SHA, SHX, SHY, TAS,
+
+    BRA, PHX, PHY, PLX, PLY, STZ, TRB, TSB, WAI, STP,
+    RMB(u8), SMB(u8), BBR(u8), BBS(u8),
+
+    BRL, COP, JML, JSL, MVN, MVP, PEA, PEI, PER, PHB, PHD, PHK, PLB, PLD,
+    REP, RTL, SEP, TCD, TCS, TDC, TSC, TXY, TYX, WDM, XBA, XCE,
+}
+
+// Bit operations are written with the bit number glued on, e.g. SMB3
+impl fmt::Display for Mnemonic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RMB(bit) => write!(f, "RMB{}", bit),
+            SMB(bit) => write!(f, "SMB{}", bit),
+            BBR(bit) => write!(f, "BBR{}", bit),
+            BBS(bit) => write!(f, "BBS{}", bit),
+            _ => write!(f, "{:?}", self),
+        }
+    }
+}
+
+// One entry of an opcode table
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Opcode {
+    pub mnemonic: Mnemonic,
+    pub mode: AddressingMode,
+
+    // Cycles taken without page crossing or branching penalties.
+    // JAM counts the opcode fetch and the read after it, the CPU is locked up from then on.
+    pub cycles: u8,
+
+    // Not part of the official instruction set of the variant
+    pub undocumented: bool,
+}
+
+impl Opcode {
+    // Length of the whole instruction in bytes, opcode included
+    pub fn length(&self) -> u16 {
+        1 + self.mode.operand_bytes()
+    }
+}
+
+const fn op(mnemonic: Mnemonic, mode: AddressingMode, cycles: u8) -> Opcode {
+    Opcode {
+        mnemonic,
+        mode,
+        cycles,
+        undocumented: false,
+    }
+}
+
+const fn undocumented(mnemonic: Mnemonic, mode: AddressingMode, cycles: u8) -> Opcode {
+    Opcode {
+        mnemonic,
+        mode,
+        cycles,
+        undocumented: true,
+    }
+}
+
+// Every opcode of the NMOS 6502, indexed by its byte.
+// Decoding, instruction length, disassembly and timing all come from here.
+#[rustfmt::skip]
+pub static NMOS_6502: [Opcode; 256] = [
+    // 0x00
+    op(BRK, Implied, 7),
+    op(ORA, IndirectX, 6),
+    undocumented(JAM, Implied, 2),
+    undocumented(SLO, IndirectX, 8),
+    undocumented(NOP, ZeroPage, 3),
+    op(ORA, ZeroPage, 3),
+    op(ASL, ZeroPage, 5),
+    undocumented(SLO, ZeroPage, 5),
+    op(PHP, Implied, 3),
+    op(ORA, Immediate, 2),
+    op(ASL, Accumulator, 2),
+    undocumented(ANC, Immediate, 2),
+    undocumented(NOP, Absolute, 4),
+    op(ORA, Absolute, 4),
+    op(ASL, Absolute, 6),
+    undocumented(SLO, Absolute, 6),
+    // 0x10
+    op(BPL, Relative, 2),
+    op(ORA, IndirectY, 5),
+    undocumented(JAM, Implied, 2),
+    undocumented(SLO, IndirectY, 8),
+    undocumented(NOP, ZeroPageX, 4),
+    op(ORA, ZeroPageX, 4),
+    op(ASL, ZeroPageX, 6),
+    undocumented(SLO, ZeroPageX, 6),
+    op(CLC, Implied, 2),
+    op(ORA, AbsoluteY, 4),
+    undocumented(NOP, Implied, 2),
+    undocumented(SLO, AbsoluteY, 7),
+    undocumented(NOP, AbsoluteX, 4),
+    op(ORA, AbsoluteX, 4),
+    op(ASL, AbsoluteX, 7),
+    undocumented(SLO, AbsoluteX, 7),
+    // 0x20
+    op(JSR, Absolute, 6),
+    op(AND, IndirectX, 6),
+    undocumented(JAM, Implied, 2),
+    undocumented(RLA, IndirectX, 8),
+    op(BIT, ZeroPage, 3),
+    op(AND, ZeroPage, 3),
+    op(ROL, ZeroPage, 5),
+    undocumented(RLA, ZeroPage, 5),
+    op(PLP, Implied, 4),
+    op(AND, Immediate, 2),
+    op(ROL, Accumulator, 2),
+    undocumented(ANC, Immediate, 2),
+    op(BIT, Absolute, 4),
+    op(AND, Absolute, 4),
+    op(ROL, Absolute, 6),
+    undocumented(RLA, Absolute, 6),
+    // 0x30
+    op(BMI, Relative, 2),
+    op(AND, IndirectY, 5),
+    undocumented(JAM, Implied, 2),
+    undocumented(RLA, IndirectY, 8),
+    undocumented(NOP, ZeroPageX, 4),
+    op(AND, ZeroPageX, 4),
+    op(ROL, ZeroPageX, 6),
+    undocumented(RLA, ZeroPageX, 6),
+    op(SEC, Implied, 2),
+    op(AND, AbsoluteY, 4),
+    undocumented(NOP, Implied, 2),
+    undocumented(RLA, AbsoluteY, 7),
+    undocumented(NOP, AbsoluteX, 4),
+    op(AND, AbsoluteX, 4),
+    op(ROL, AbsoluteX, 7),
+    undocumented(RLA, AbsoluteX, 7),
+    // 0x40
+    op(RTI, Implied, 6),
+    op(EOR, IndirectX, 6),
+    undocumented(JAM, Implied, 2),
+    undocumented(SRE, IndirectX, 8),
+    undocumented(NOP, ZeroPage, 3),
+    op(EOR, ZeroPage, 3),
+    op(LSR, ZeroPage, 5),
+    undocumented(SRE, ZeroPage, 5),
+    op(PHA, Implied, 3),
+    op(EOR, Immediate, 2),
+    op(LSR, Accumulator, 2),
+    undocumented(ALR, Immediate, 2),
+    op(JMP, Absolute, 3),
+    op(EOR, Absolute, 4),
+    op(LSR, Absolute, 6),
+    undocumented(SRE, Absolute, 6),
+    // 0x50
+    op(BVC, Relative, 2),
+    op(EOR, IndirectY, 5),
+    undocumented(JAM, Implied, 2),
+    undocumented(SRE, IndirectY, 8),
+    undocumented(NOP, ZeroPageX, 4),
+    op(EOR, ZeroPageX, 4),
+    op(LSR, ZeroPageX, 6),
+    undocumented(SRE, ZeroPageX, 6),
+    op(CLI, Implied, 2),
+    op(EOR, AbsoluteY, 4),
+    undocumented(NOP, Implied, 2),
+    undocumented(SRE, AbsoluteY, 7),
+    undocumented(NOP, AbsoluteX, 4),
+    op(EOR, AbsoluteX, 4),
+    op(LSR, AbsoluteX, 7),
+    undocumented(SRE, AbsoluteX, 7),
+    // 0x60
+    op(RTS, Implied, 6),
+    op(ADC, IndirectX, 6),
+    undocumented(JAM, Implied, 2),
+    undocumented(RRA, IndirectX, 8),
+    undocumented(NOP, ZeroPage, 3),
+    op(ADC, ZeroPage, 3),
+    op(ROR, ZeroPage, 5),
+    undocumented(RRA, ZeroPage, 5),
+    op(PLA, Implied, 4),
+    op(ADC, Immediate, 2),
+    op(ROR, Accumulator, 2),
+    undocumented(ARR, Immediate, 2),
+    op(JMP, Indirect, 5),
+    op(ADC, Absolute, 4),
+    op(ROR, Absolute, 6),
+    undocumented(RRA, Absolute, 6),
+    // 0x70
+    op(BVS, Relative, 2),
+    op(ADC, IndirectY, 5),
+    undocumented(JAM, Implied, 2),
+    undocumented(RRA, IndirectY, 8),
+    undocumented(NOP, ZeroPageX, 4),
+    op(ADC, ZeroPageX, 4),
+    op(ROR, ZeroPageX, 6),
+    undocumented(RRA, ZeroPageX, 6),
+    op(SEI, Implied, 2),
+    op(ADC, AbsoluteY, 4),
+    undocumented(NOP, Implied, 2),
+    undocumented(RRA, AbsoluteY, 7),
+    undocumented(NOP, AbsoluteX, 4),
+    op(ADC, AbsoluteX, 4),
+    op(ROR, AbsoluteX, 7),
+    undocumented(RRA, AbsoluteX, 7),
+    // 0x80
+    undocumented(NOP, Immediate, 2),
+    op(STA, IndirectX, 6),
+    undocumented(NOP, Immediate, 2),
+    undocumented(SAX, IndirectX, 6),
+    op(STY, ZeroPage, 3),
+    op(STA, ZeroPage, 3),
+    op(STX, ZeroPage, 3),
+    undocumented(SAX, ZeroPage, 3),
+    op(DEY, Implied, 2),
+    undocumented(NOP, Immediate, 2),
+    op(TXA, Implied, 2),
+    undocumented(XAA, Immediate, 2),
+    op(STY, Absolute, 4),
+    op(STA, Absolute, 4),
+    op(STX, Absolute, 4),
+    undocumented(SAX, Absolute, 4),
+    // 0x90
+    op(BCC, Relative, 2),
+    op(STA, IndirectY, 6),
+    undocumented(JAM, Implied, 2),
+    undocumented(SHA, IndirectY, 6),
+    op(STY, ZeroPageX, 4),
+    op(STA, ZeroPageX, 4),
+    op(STX, ZeroPageY, 4),
+    undocumented(SAX, ZeroPageY, 4),
+    op(TYA, Implied, 2),
+    op(STA, AbsoluteY, 5),
+    op(TXS, Implied, 2),
+    undocumented(TAS, AbsoluteY, 5),
+    undocumented(SHY, AbsoluteX, 5),
+    op(STA, AbsoluteX, 5),
+    undocumented(SHX, AbsoluteY, 5),
+    undocumented(SHA, AbsoluteY, 5),
+    // 0xa0
+    op(LDY, Immediate, 2),
+    op(LDA, IndirectX, 6),
+    op(LDX, Immediate, 2),
+    undocumented(LAX, IndirectX, 6),
+    op(LDY, ZeroPage, 3),
+    op(LDA, ZeroPage, 3),
+    op(LDX, ZeroPage, 3),
+    undocumented(LAX, ZeroPage, 3),
+    op(TAY, Implied, 2),
+    op(LDA, Immediate, 2),
+    op(TAX, Implied, 2),
+    undocumented(LAX, Immediate, 2),
+    op(LDY, Absolute, 4),
+    op(LDA, Absolute, 4),
+    op(LDX, Absolute, 4),
+    undocumented(LAX, Absolute, 4),
+    // 0xb0
+    op(BCS, Relative, 2),
+    op(LDA, IndirectY, 5),
+    undocumented(JAM, Implied, 2),
+    undocumented(LAX, IndirectY, 5),
+    op(LDY, ZeroPageX, 4),
+    op(LDA, ZeroPageX, 4),
+    op(LDX, ZeroPageY, 4),
+    undocumented(LAX, ZeroPageY, 4),
+    op(CLV, Implied, 2),
+    op(LDA, AbsoluteY, 4),
+    op(TSX, Implied, 2),
+    undocumented(LAS, AbsoluteY, 4),
+    op(LDY, AbsoluteX, 4),
+    op(LDA, AbsoluteX, 4),
+    op(LDX, AbsoluteY, 4),
+    undocumented(LAX, AbsoluteY, 4),
+    // 0xc0
+    op(CPY, Immediate, 2),
+    op(CMP, IndirectX, 6),
+    undocumented(NOP, Immediate, 2),
+    undocumented(DCP, IndirectX, 8),
+    op(CPY, ZeroPage, 3),
+    op(CMP, ZeroPage, 3),
+    op(DEC, ZeroPage, 5),
+    undocumented(DCP, ZeroPage, 5),
+    op(INY, Implied, 2),
+    op(CMP, Immediate, 2),
+    op(DEX, Implied, 2),
+    undocumented(SBX, Immediate, 2),
+    op(CPY, Absolute, 4),
+    op(CMP, Absolute, 4),
+    op(DEC, Absolute, 6),
+    undocumented(DCP, Absolute, 6),
+    // 0xd0
+    op(BNE, Relative, 2),
+    op(CMP, IndirectY, 5),
+    undocumented(JAM, Implied, 2),
+    undocumented(DCP, IndirectY, 8),
+    undocumented(NOP, ZeroPageX, 4),
+    op(CMP, ZeroPageX, 4),
+    op(DEC, ZeroPageX, 6),
+    undocumented(DCP, ZeroPageX, 6),
+    op(CLD, Implied, 2),
+    op(CMP, AbsoluteY, 4),
+    undocumented(NOP, Implied, 2),
+    undocumented(DCP, AbsoluteY, 7),
+    undocumented(NOP, AbsoluteX, 4),
+    op(CMP, AbsoluteX, 4),
+    op(DEC, AbsoluteX, 7),
+    undocumented(DCP, AbsoluteX, 7),
+    // 0xe0
+    op(CPX, Immediate, 2),
+    op(SBC, IndirectX, 6),
+    undocumented(NOP, Immediate, 2),
+    undocumented(ISC, IndirectX, 8),
+    op(CPX, ZeroPage, 3),
+    op(SBC, ZeroPage, 3),
+    op(INC, ZeroPage, 5),
+    undocumented(ISC, ZeroPage, 5),
+    op(INX, Implied, 2),
+    op(SBC, Immediate, 2),
+    op(NOP, Implied, 2),
+    undocumented(SBC, Immediate, 2),
+    op(CPX, Absolute, 4),
+    op(SBC, Absolute, 4),
+    op(INC, Absolute, 6),
+    undocumented(ISC, Absolute, 6),
+    // 0xf0
+    op(BEQ, Relative, 2),
+    op(SBC, IndirectY, 5),
+    undocumented(JAM, Implied, 2),
+    undocumented(ISC, IndirectY, 8),
+    undocumented(NOP, ZeroPageX, 4),
+    op(SBC, ZeroPageX, 4),
+    op(INC, ZeroPageX, 6),
+    undocumented(ISC, ZeroPageX, 6),
+    op(SED, Implied, 2),
+    op(SBC, AbsoluteY, 4),
+    undocumented(NOP, Implied, 2),
+    undocumented(ISC, AbsoluteY, 7),
+    undocumented(NOP, AbsoluteX, 4),
+    op(SBC, AbsoluteX, 4),
+    op(INC, AbsoluteX, 7),
+    undocumented(ISC, AbsoluteX, 7),
+];
+
+// Every opcode of the WDC 65C02, including the Rockwell bit operations.
+// The opcodes left undefined by WDC are NOPs of various lengths and timings,
+// they are marked undocumented so the assembler prefers 0xEA.
+#[rustfmt::skip]
+pub static WDC_65C02: [Opcode; 256] = [
+    // 0x00
+    op(BRK, Implied, 7),
+    op(ORA, IndirectX, 6),
+    undocumented(NOP, Immediate, 2),
+    undocumented(NOP, Implied, 1),
+    op(TSB, ZeroPage, 5),
+    op(ORA, ZeroPage, 3),
+    op(ASL, ZeroPage, 5),
+    op(RMB(0), ZeroPage, 5),
+    op(PHP, Implied, 3),
+    op(ORA, Immediate, 2),
+    op(ASL, Accumulator, 2),
+    undocumented(NOP, Implied, 1),
+    op(TSB, Absolute, 6),
+    op(ORA, Absolute, 4),
+    op(ASL, Absolute, 6),
+    op(BBR(0), ZeroPageRelative, 5),
+    // 0x10
+    op(BPL, Relative, 2),
+    op(ORA, IndirectY, 5),
+    op(ORA, ZeroPageIndirect, 5),
+    undocumented(NOP, Implied, 1),
+    op(TRB, ZeroPage, 5),
+    op(ORA, ZeroPageX, 4),
+    op(ASL, ZeroPageX, 6),
+    op(RMB(1), ZeroPage, 5),
+    op(CLC, Implied, 2),
+    op(ORA, AbsoluteY, 4),
+    op(INC, Accumulator, 2),
+    undocumented(NOP, Implied, 1),
+    op(TRB, Absolute, 6),
+    op(ORA, AbsoluteX, 4),
+    op(ASL, AbsoluteX, 6),
+    op(BBR(1), ZeroPageRelative, 5),
+    // 0x20
+    op(JSR, Absolute, 6),
+    op(AND, IndirectX, 6),
+    undocumented(NOP, Immediate, 2),
+    undocumented(NOP, Implied, 1),
+    op(BIT, ZeroPage, 3),
+    op(AND, ZeroPage, 3),
+    op(ROL, ZeroPage, 5),
+    op(RMB(2), ZeroPage, 5),
+    op(PLP, Implied, 4),
+    op(AND, Immediate, 2),
+    op(ROL, Accumulator, 2),
+    undocumented(NOP, Implied, 1),
+    op(BIT, Absolute, 4),
+    op(AND, Absolute, 4),
+    op(ROL, Absolute, 6),
+    op(BBR(2), ZeroPageRelative, 5),
+    // 0x30
+    op(BMI, Relative, 2),
+    op(AND, IndirectY, 5),
+    op(AND, ZeroPageIndirect, 5),
+    undocumented(NOP, Implied, 1),
+    op(BIT, ZeroPageX, 4),
+    op(AND, ZeroPageX, 4),
+    op(ROL, ZeroPageX, 6),
+    op(RMB(3), ZeroPage, 5),
+    op(SEC, Implied, 2),
+    op(AND, AbsoluteY, 4),
+    op(DEC, Accumulator, 2),
+    undocumented(NOP, Implied, 1),
+    op(BIT, AbsoluteX, 4),
+    op(AND, AbsoluteX, 4),
+    op(ROL, AbsoluteX, 6),
+    op(BBR(3), ZeroPageRelative, 5),
+    // 0x40
+    op(RTI, Implied, 6),
+    op(EOR, IndirectX, 6),
+    undocumented(NOP, Immediate, 2),
+    undocumented(NOP, Implied, 1),
+    undocumented(NOP, ZeroPage, 3),
+    op(EOR, ZeroPage, 3),
+    op(LSR, ZeroPage, 5),
+    op(RMB(4), ZeroPage, 5),
+    op(PHA, Implied, 3),
+    op(EOR, Immediate, 2),
+    op(LSR, Accumulator, 2),
+    undocumented(NOP, Implied, 1),
+    op(JMP, Absolute, 3),
+    op(EOR, Absolute, 4),
+    op(LSR, Absolute, 6),
+    op(BBR(4), ZeroPageRelative, 5),
+    // 0x50
+    op(BVC, Relative, 2),
+    op(EOR, IndirectY, 5),
+    op(EOR, ZeroPageIndirect, 5),
+    undocumented(NOP, Implied, 1),
+    undocumented(NOP, ZeroPageX, 4),
+    op(EOR, ZeroPageX, 4),
+    op(LSR, ZeroPageX, 6),
+    op(RMB(5), ZeroPage, 5),
+    op(CLI, Implied, 2),
+    op(EOR, AbsoluteY, 4),
+    op(PHY, Implied, 3),
+    undocumented(NOP, Implied, 1),
+    undocumented(NOP, Absolute, 8),
+    op(EOR, AbsoluteX, 4),
+    op(LSR, AbsoluteX, 6),
+    op(BBR(5), ZeroPageRelative, 5),
+    // 0x60
+    op(RTS, Implied, 6),
+    op(ADC, IndirectX, 6),
+    undocumented(NOP, Immediate, 2),
+    undocumented(NOP, Implied, 1),
+    op(STZ, ZeroPage, 3),
+    op(ADC, ZeroPage, 3),
+    op(ROR, ZeroPage, 5),
+    op(RMB(6), ZeroPage, 5),
+    op(PLA, Implied, 4),
+    op(ADC, Immediate, 2),
+    op(ROR, Accumulator, 2),
+    undocumented(NOP, Implied, 1),
+    op(JMP, Indirect, 6),
+    op(ADC, Absolute, 4),
+    op(ROR, Absolute, 6),
+    op(BBR(6), ZeroPageRelative, 5),
+    // 0x70
+    op(BVS, Relative, 2),
+    op(ADC, IndirectY, 5),
+    op(ADC, ZeroPageIndirect, 5),
+    undocumented(NOP, Implied, 1),
+    op(STZ, ZeroPageX, 4),
+    op(ADC, ZeroPageX, 4),
+    op(ROR, ZeroPageX, 6),
+    op(RMB(7), ZeroPage, 5),
+    op(SEI, Implied, 2),
+    op(ADC, AbsoluteY, 4),
+    op(PLY, Implied, 4),
+    undocumented(NOP, Implied, 1),
+    op(JMP, AbsoluteIndexedIndirect, 6),
+    op(ADC, AbsoluteX, 4),
+    op(ROR, AbsoluteX, 6),
+    op(BBR(7), ZeroPageRelative, 5),
+    // 0x80
+    op(BRA, Relative, 2),
+    op(STA, IndirectX, 6),
+    undocumented(NOP, Immediate, 2),
+    undocumented(NOP, Implied, 1),
+    op(STY, ZeroPage, 3),
+    op(STA, ZeroPage, 3),
+    op(STX, ZeroPage, 3),
+    op(SMB(0), ZeroPage, 5),
+    op(DEY, Implied, 2),
+    op(BIT, Immediate, 2),
+    op(TXA, Implied, 2),
+    undocumented(NOP, Implied, 1),
+    op(STY, Absolute, 4),
+    op(STA, Absolute, 4),
+    op(STX, Absolute, 4),
+    op(BBS(0), ZeroPageRelative, 5),
+    // 0x90
+    op(BCC, Relative, 2),
+    op(STA, IndirectY, 6),
+    op(STA, ZeroPageIndirect, 5),
+    undocumented(NOP, Implied, 1),
+    op(STY, ZeroPageX, 4),
+    op(STA, ZeroPageX, 4),
+    op(STX, ZeroPageY, 4),
+    op(SMB(1), ZeroPage, 5),
+    op(TYA, Implied, 2),
+    op(STA, AbsoluteY, 5),
+    op(TXS, Implied, 2),
+    undocumented(NOP, Implied, 1),
+    op(STZ, Absolute, 4),
+    op(STA, AbsoluteX, 5),
+    op(STZ, AbsoluteX, 5),
+    op(BBS(1), ZeroPageRelative, 5),
+    // 0xa0
+    op(LDY, Immediate, 2),
+    op(LDA, IndirectX, 6),
+    op(LDX, Immediate, 2),
+    undocumented(NOP, Implied, 1),
+    op(LDY, ZeroPage, 3),
+    op(LDA, ZeroPage, 3),
+    op(LDX, ZeroPage, 3),
+    op(SMB(2), ZeroPage, 5),
+    op(TAY, Implied, 2),
+    op(LDA, Immediate, 2),
+    op(TAX, Implied, 2),
+    undocumented(NOP, Implied, 1),
+    op(LDY, Absolute, 4),
+    op(LDA, Absolute, 4),
+    op(LDX, Absolute, 4),
+    op(BBS(2), ZeroPageRelative, 5),
+    // 0xb0
+    op(BCS, Relative, 2),
+    op(LDA, IndirectY, 5),
+    op(LDA, ZeroPageIndirect, 5),
+    undocumented(NOP, Implied, 1),
+    op(LDY, ZeroPageX, 4),
+    op(LDA, ZeroPageX, 4),
+    op(LDX, ZeroPageY, 4),
+    op(SMB(3), ZeroPage, 5),
+    op(CLV, Implied, 2),
+    op(LDA, AbsoluteY, 4),
+    op(TSX, Implied, 2),
+    undocumented(NOP, Implied, 1),
+    op(LDY, AbsoluteX, 4),
+    op(LDA, AbsoluteX, 4),
+    op(LDX, AbsoluteY, 4),
+    op(BBS(3), ZeroPageRelative, 5),
+    // 0xc0
+    op(CPY, Immediate, 2),
+    op(CMP, IndirectX, 6),
+    undocumented(NOP, Immediate, 2),
+    undocumented(NOP, Implied, 1),
+    op(CPY, ZeroPage, 3),
+    op(CMP, ZeroPage, 3),
+    op(DEC, ZeroPage, 5),
+    op(SMB(4), ZeroPage, 5),
+    op(INY, Implied, 2),
+    op(CMP, Immediate, 2),
+    op(DEX, Implied, 2),
+    op(WAI, Implied, 3),
+    op(CPY, Absolute, 4),
+    op(CMP, Absolute, 4),
+    op(DEC, Absolute, 6),
+    op(BBS(4), ZeroPageRelative, 5),
+    // 0xd0
+    op(BNE, Relative, 2),
+    op(CMP, IndirectY, 5),
+    op(CMP, ZeroPageIndirect, 5),
+    undocumented(NOP, Implied, 1),
+    undocumented(NOP, ZeroPageX, 4),
+    op(CMP, ZeroPageX, 4),
+    op(DEC, ZeroPageX, 6),
+    op(SMB(5), ZeroPage, 5),
+    op(CLD, Implied, 2),
+    op(CMP, AbsoluteY, 4),
+    op(PHX, Implied, 3),
+    op(STP, Implied, 3),
+    undocumented(NOP, Absolute, 4),
+    op(CMP, AbsoluteX, 4),
+    op(DEC, AbsoluteX, 7),
+    op(BBS(5), ZeroPageRelative, 5),
+    // 0xe0
+    op(CPX, Immediate, 2),
+    op(SBC, IndirectX, 6),
+    undocumented(NOP, Immediate, 2),
+    undocumented(NOP, Implied, 1),
+    op(CPX, ZeroPage, 3),
+    op(SBC, ZeroPage, 3),
+    op(INC, ZeroPage, 5),
+    op(SMB(6), ZeroPage, 5),
+    op(INX, Implied, 2),
+    op(SBC, Immediate, 2),
+    op(NOP, Implied, 2),
+    undocumented(NOP, Implied, 1),
+    op(CPX, Absolute, 4),
+    op(SBC, Absolute, 4),
+    op(INC, Absolute, 6),
+    op(BBS(6), ZeroPageRelative, 5),
+    // 0xf0
+    op(BEQ, Relative, 2),
+    op(SBC, IndirectY, 5),
+    op(SBC, ZeroPageIndirect, 5),
+    undocumented(NOP, Implied, 1),
+    undocumented(NOP, ZeroPageX, 4),
+    op(SBC, ZeroPageX, 4),
+    op(INC, ZeroPageX, 6),
+    op(SMB(7), ZeroPage, 5),
+    op(SED, Implied, 2),
+    op(SBC, AbsoluteY, 4),
+    op(PLX, Implied, 4),
+    undocumented(NOP, Implied, 1),
+    undocumented(NOP, Absolute, 4),
+    op(SBC, AbsoluteX, 4),
+    op(INC, AbsoluteX, 7),
+    op(BBS(7), ZeroPageRelative, 5),
+];
+
+// Every opcode of the WDC 65C816. Cycles are the ones with 8 bit registers in emulation
+// mode, the processor adds the extra cycles of 16 bit registers and native mode.
+// Immediates of 16 bit registers are decoded as ImmediateWord by the processor.
+#[rustfmt::skip]
+pub static WDC_65C816: [Opcode; 256] = [
+    // 0x00
+    op(BRK, Implied, 7),
+    op(ORA, IndirectX, 6),
+    op(COP, Immediate, 7),
+    op(ORA, StackRelative, 4),
+    op(TSB, ZeroPage, 5),
+    op(ORA, ZeroPage, 3),
+    op(ASL, ZeroPage, 5),
+    op(ORA, ZeroPageIndirectLong, 6),
+    op(PHP, Implied, 3),
+    op(ORA, Immediate, 2),
+    op(ASL, Accumulator, 2),
+    op(PHD, Implied, 4),
+    op(TSB, Absolute, 6),
+    op(ORA, Absolute, 4),
+    op(ASL, Absolute, 6),
+    op(ORA, AbsoluteLong, 5),
+    // 0x10
+    op(BPL, Relative, 2),
+    op(ORA, IndirectY, 5),
+    op(ORA, ZeroPageIndirect, 5),
+    op(ORA, StackRelativeIndirectY, 7),
+    op(TRB, ZeroPage, 5),
+    op(ORA, ZeroPageX, 4),
+    op(ASL, ZeroPageX, 6),
+    op(ORA, ZeroPageIndirectLongY, 6),
+    op(CLC, Implied, 2),
+    op(ORA, AbsoluteY, 4),
+    op(INC, Accumulator, 2),
+    op(TCS, Implied, 2),
+    op(TRB, Absolute, 6),
+    op(ORA, AbsoluteX, 4),
+    op(ASL, AbsoluteX, 7),
+    op(ORA, AbsoluteLongX, 5),
+    // 0x20
+    op(JSR, Absolute, 6),
+    op(AND, IndirectX, 6),
+    op(JSL, AbsoluteLong, 8),
+    op(AND, StackRelative, 4),
+    op(BIT, ZeroPage, 3),
+    op(AND, ZeroPage, 3),
+    op(ROL, ZeroPage, 5),
+    op(AND, ZeroPageIndirectLong, 6),
+    op(PLP, Implied, 4),
+    op(AND, Immediate, 2),
+    op(ROL, Accumulator, 2),
+    op(PLD, Implied, 5),
+    op(BIT, Absolute, 4),
+    op(AND, Absolute, 4),
+    op(ROL, Absolute, 6),
+    op(AND, AbsoluteLong, 5),
+    // 0x30
+    op(BMI, Relative, 2),
+    op(AND, IndirectY, 5),
+    op(AND, ZeroPageIndirect, 5),
+    op(AND, StackRelativeIndirectY, 7),
+    op(BIT, ZeroPageX, 4),
+    op(AND, ZeroPageX, 4),
+    op(ROL, ZeroPageX, 6),
+    op(AND, ZeroPageIndirectLongY, 6),
+    op(SEC, Implied, 2),
+    op(AND, AbsoluteY, 4),
+    op(DEC, Accumulator, 2),
+    op(TSC, Implied, 2),
+    op(BIT, AbsoluteX, 4),
+    op(AND, AbsoluteX, 4),
+    op(ROL, AbsoluteX, 7),
+    op(AND, AbsoluteLongX, 5),
+    // 0x40
+    op(RTI, Implied, 6),
+    op(EOR, IndirectX, 6),
+    op(WDM, Immediate, 2),
+    op(EOR, StackRelative, 4),
+    op(MVP, BlockMove, 7),
+    op(EOR, ZeroPage, 3),
+    op(LSR, ZeroPage, 5),
+    op(EOR, ZeroPageIndirectLong, 6),
+    op(PHA, Implied, 3),
+    op(EOR, Immediate, 2),
+    op(LSR, Accumulator, 2),
+    op(PHK, Implied, 3),
+    op(JMP, Absolute, 3),
+    op(EOR, Absolute, 4),
+    op(LSR, Absolute, 6),
+    op(EOR, AbsoluteLong, 5),
+    // 0x50
+    op(BVC, Relative, 2),
+    op(EOR, IndirectY, 5),
+    op(EOR, ZeroPageIndirect, 5),
+    op(EOR, StackRelativeIndirectY, 7),
+    op(MVN, BlockMove, 7),
+    op(EOR, ZeroPageX, 4),
+    op(LSR, ZeroPageX, 6),
+    op(EOR, ZeroPageIndirectLongY, 6),
+    op(CLI, Implied, 2),
+    op(EOR, AbsoluteY, 4),
+    op(PHY, Implied, 3),
+    op(TCD, Implied, 2),
+    op(JML, AbsoluteLong, 4),
+    op(EOR, AbsoluteX, 4),
+    op(LSR, AbsoluteX, 7),
+    op(EOR, AbsoluteLongX, 5),
+    // 0x60
+    op(RTS, Implied, 6),
+    op(ADC, IndirectX, 6),
+    op(PER, RelativeLong, 6),
+    op(ADC, StackRelative, 4),
+    op(STZ, ZeroPage, 3),
+    op(ADC, ZeroPage, 3),
+    op(ROR, ZeroPage, 5),
+    op(ADC, ZeroPageIndirectLong, 6),
+    op(PLA, Implied, 4),
+    op(ADC, Immediate, 2),
+    op(ROR, Accumulator, 2),
+    op(RTL, Implied, 6),
+    op(JMP, Indirect, 5),
+    op(ADC, Absolute, 4),
+    op(ROR, Absolute, 6),
+    op(ADC, AbsoluteLong, 5),
+    // 0x70
+    op(BVS, Relative, 2),
+    op(ADC, IndirectY, 5),
+    op(ADC, ZeroPageIndirect, 5),
+    op(ADC, StackRelativeIndirectY, 7),
+    op(STZ, ZeroPageX, 4),
+    op(ADC, ZeroPageX, 4),
+    op(ROR, ZeroPageX, 6),
+    op(ADC, ZeroPageIndirectLongY, 6),
+    op(SEI, Implied, 2),
+    op(ADC, AbsoluteY, 4),
+    op(PLY, Implied, 4),
+    op(TDC, Implied, 2),
+    op(JMP, AbsoluteIndexedIndirect, 6),
+    op(ADC, AbsoluteX, 4),
+    op(ROR, AbsoluteX, 7),
+    op(ADC, AbsoluteLongX, 5),
+    // 0x80
+    op(BRA, Relative, 2),
+    op(STA, IndirectX, 6),
+    op(BRL, RelativeLong, 4),
+    op(STA, StackRelative, 4),
+    op(STY, ZeroPage, 3),
+    op(STA, ZeroPage, 3),
+    op(STX, ZeroPage, 3),
+    op(STA, ZeroPageIndirectLong, 6),
+    op(DEY, Implied, 2),
+    op(BIT, Immediate, 2),
+    op(TXA, Implied, 2),
+    op(PHB, Implied, 3),
+    op(STY, Absolute, 4),
+    op(STA, Absolute, 4),
+    op(STX, Absolute, 4),
+    op(STA, AbsoluteLong, 5),
+    // 0x90
+    op(BCC, Relative, 2),
+    op(STA, IndirectY, 6),
+    op(STA, ZeroPageIndirect, 5),
+    op(STA, StackRelativeIndirectY, 7),
+    op(STY, ZeroPageX, 4),
+    op(STA, ZeroPageX, 4),
+    op(STX, ZeroPageY, 4),
+    op(STA, ZeroPageIndirectLongY, 6),
+    op(TYA, Implied, 2),
+    op(STA, AbsoluteY, 5),
+    op(TXS, Implied, 2),
+    op(TXY, Implied, 2),
+    op(STZ, Absolute, 4),
+    op(STA, AbsoluteX, 5),
+    op(STZ, AbsoluteX, 5),
+    op(STA, AbsoluteLongX, 5),
+    // 0xa0
+    op(LDY, Immediate, 2),
+    op(LDA, IndirectX, 6),
+    op(LDX, Immediate, 2),
+    op(LDA, StackRelative, 4),
+    op(LDY, ZeroPage, 3),
+    op(LDA, ZeroPage, 3),
+    op(LDX, ZeroPage, 3),
+    op(LDA, ZeroPageIndirectLong, 6),
+    op(TAY, Implied, 2),
+    op(LDA, Immediate, 2),
+    op(TAX, Implied, 2),
+    op(PLB, Implied, 4),
+    op(LDY, Absolute, 4),
+    op(LDA, Absolute, 4),
+    op(LDX, Absolute, 4),
+    op(LDA, AbsoluteLong, 5),
+    // 0xb0
+    op(BCS, Relative, 2),
+    op(LDA, IndirectY, 5),
+    op(LDA, ZeroPageIndirect, 5),
+    op(LDA, StackRelativeIndirectY, 7),
+    op(LDY, ZeroPageX, 4),
+    op(LDA, ZeroPageX, 4),
+    op(LDX, ZeroPageY, 4),
+    op(LDA, ZeroPageIndirectLongY, 6),
+    op(CLV, Implied, 2),
+    op(LDA, AbsoluteY, 4),
+    op(TSX, Implied, 2),
+    op(TYX, Implied, 2),
+    op(LDY, AbsoluteX, 4),
+    op(LDA, AbsoluteX, 4),
+    op(LDX, AbsoluteY, 4),
+    op(LDA, AbsoluteLongX, 5),
+    // 0xc0
+    op(CPY, Immediate, 2),
+    op(CMP, IndirectX, 6),
+    op(REP, Immediate, 3),
+    op(CMP, StackRelative, 4),
+    op(CPY, ZeroPage, 3),
+    op(CMP, ZeroPage, 3),
+    op(DEC, ZeroPage, 5),
+    op(CMP, ZeroPageIndirectLong, 6),
+    op(INY, Implied, 2),
+    op(CMP, Immediate, 2),
+    op(DEX, Implied, 2),
+    op(WAI, Implied, 3),
+    op(CPY, Absolute, 4),
+    op(CMP, Absolute, 4),
+    op(DEC, Absolute, 6),
+    op(CMP, AbsoluteLong, 5),
+    // 0xd0
+    op(BNE, Relative, 2),
+    op(CMP, IndirectY, 5),
+    op(CMP, ZeroPageIndirect, 5),
+    op(CMP, StackRelativeIndirectY, 7),
+    op(PEI, ZeroPageIndirect, 6),
+    op(CMP, ZeroPageX, 4),
+    op(DEC, ZeroPageX, 6),
+    op(CMP, ZeroPageIndirectLongY, 6),
+    op(CLD, Implied, 2),
+    op(CMP, AbsoluteY, 4),
+    op(PHX, Implied, 3),
+    op(STP, Implied, 3),
+    op(JML, AbsoluteIndirectLong, 6),
+    op(CMP, AbsoluteX, 4),
+    op(DEC, AbsoluteX, 7),
+    op(CMP, AbsoluteLongX, 5),
+    // 0xe0
+    op(CPX, Immediate, 2),
+    op(SBC, IndirectX, 6),
+    op(SEP, Immediate, 3),
+    op(SBC, StackRelative, 4),
+    op(CPX, ZeroPage, 3),
+    op(SBC, ZeroPage, 3),
+    op(INC, ZeroPage, 5),
+    op(SBC, ZeroPageIndirectLong, 6),
+    op(INX, Implied, 2),
+    op(SBC, Immediate, 2),
+    op(NOP, Implied, 2),
+    op(XBA, Implied, 3),
+    op(CPX, Absolute, 4),
+    op(SBC, Absolute, 4),
+    op(INC, Absolute, 6),
+    op(SBC, AbsoluteLong, 5),
+    // 0xf0
+    op(BEQ, Relative, 2),
+    op(SBC, IndirectY, 5),
+    op(SBC, ZeroPageIndirect, 5),
+    op(SBC, StackRelativeIndirectY, 7),
+    op(PEA, Absolute, 5),
+    op(SBC, ZeroPageX, 4),
+    op(INC, ZeroPageX, 6),
+    op(SBC, ZeroPageIndirectLongY, 6),
+    op(SED, Implied, 2),
+    op(SBC, AbsoluteY, 4),
+    op(PLX, Implied, 4),
+    op(XCE, Implied, 2),
+    op(JSR, AbsoluteIndexedIndirect, 8),
+    op(SBC, AbsoluteX, 4),
+    op(INC, AbsoluteX, 7),
+    op(SBC, AbsoluteLongX, 5),
+];
+
+// A decoded instruction: the opcode table entry plus its operand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub opcode: u8,
+    pub mnemonic: Mnemonic,
+    pub mode: AddressingMode,
+    pub cycles: u8,
+    pub undocumented: bool,
+
+    // Little endian bytes following the opcode, 0 if there are none
+    pub operand: u32,
+}
+
+impl Instruction {
+    pub fn new(opcode: u8, entry: Opcode, operand: u32) -> Self {
+        Self {
+            opcode,
+            mnemonic: entry.mnemonic,
+            mode: entry.mode,
+            cycles: entry.cycles,
+            undocumented: entry.undocumented,
+            operand,
+        }
+    }
+
+    // Decode the instruction at the start of bytes.
+    // Returns None when there aren't enough bytes for the operand.
+    // 65816 immediates are taken as 8 bit, the width depends on the M and X flags.
+    pub fn decode(table: &[Opcode; 256], bytes: &[u8]) -> Option<Self> {
+        let opcode = *bytes.first()?;
+        let entry = table[opcode as usize];
+
+        let mut operand = 0;
+        for i in 0..entry.mode.operand_bytes() as usize {
+            operand |= (*bytes.get(1 + i)? as u32) << (8 * i);
+        }
+
+        Some(Self::new(opcode, entry, operand))
+    }
+
+    // Find the opcode for a mnemonic and addressing mode, preferring documented ones
+    pub fn assemble(
+        table: &[Opcode; 256],
+        mnemonic: Mnemonic,
+        mode: AddressingMode,
+        operand: u32,
+    ) -> Option<Self> {
+        let (opcode, entry) = table
+            .iter()
+            .enumerate()
+            .filter(|(_, entry)| entry.mnemonic == mnemonic && entry.mode == mode)
+            .min_by_key(|(_, entry)| entry.undocumented)?;
+
+        Some(Self::new(opcode as u8, *entry, operand))
+    }
+
+    pub fn length(&self) -> u16 {
+        1 + self.mode.operand_bytes()
+    }
+
+    // The raw bytes of the instruction
+    pub fn bytes(&self) -> Vec<u8> {
+        let operand = self.operand.to_le_bytes();
+        let mut bytes = vec![self.opcode];
+        bytes.extend_from_slice(&operand[..self.mode.operand_bytes() as usize]);
+        bytes
+    }
 }
 
-impl OPCodes {
-    pub fn instruction_to_opcode(opcode: u8, value: Option<isize>) -> OPCodes {
-        use self::OPCodes::*;
-
-        match opcode {
-            // 0
-            0x00 => BRK,
-            0x01 => ORA_XIND(value.unwrap() as u8),
-            0x05 => ORA_ZPG(value.unwrap() as u8),
-            0x06 => ASL_ZPG(value.unwrap() as u8),
-            0x08 => PHP,
-            0x09 => ORA_IMM(value.unwrap() as u8),
-            0x0a => ASL_A,
-            0x0d => ORA_ABS(value.unwrap() as u16),
-            0x0e => ASL_ABS(value.unwrap() as u16),
-
-            // 1
-            0x10 => BPL(value.unwrap() as i8),
-            0x11 => ORA_YIND(value.unwrap() as u8),
-            0x15 => ORA_XZPG(value.unwrap() as u8),
-            0x16 => ASL_XZPG(value.unwrap() as u8),
-            0x18 => CLC,
-            0x19 => ORA_YABS(value.unwrap() as u16),
-            0x1d => ORA_XABS(value.unwrap() as u16),
-            0x1e => ASL_XABS(value.unwrap() as u16),
-
-            // 2
-            0x20 => JSR(value.unwrap() as u16),
-            0x21 => AND_XIND(value.unwrap() as u8),
-            0x24 => BIT_ZPG(value.unwrap() as u8),
-            0x25 => AND_ZPG(value.unwrap() as u8),
-            0x26 => ROL_ZPG(value.unwrap() as u8),
-            0x28 => PLP,
-            0x29 => AND_IMM(value.unwrap() as u8),
-            0x2a => ROL_A,
-            0x2c => BIT_ABS(value.unwrap() as u16),
-            0x2d => AND_ABS(value.unwrap() as u16),
-            0x2e => ROL_ABS(value.unwrap() as u16),
-
-            // 3
-            0x30 => BMI(value.unwrap() as i8),
-            0x31 => AND_YIND(value.unwrap() as u8),
-            0x35 => AND_XZPG(value.unwrap() as u8),
-            0x36 => ROL_XZPG(value.unwrap() as u8),
-            0x38 => SEC,
-            0x39 => AND_YABS(value.unwrap() as u16),
-            0x3d => AND_XABS(value.unwrap() as u16),
-            0x3e => ROL_XABS(value.unwrap() as u16),
-
-            // 4
-            0x40 => RTI,
-            0x41 => EOR_XIND(value.unwrap() as u8),
-            0x45 => EOR_ZPG(value.unwrap() as u8),
-            0x46 => LSR_ZPG(value.unwrap() as u8),
-            0x48 => PHA,
-            0x49 => EOR_IMM(value.unwrap() as u8),
-            0x4a => LSR_A,
-            0x4c => JMP_ABS(value.unwrap() as u16),
-            0x4d => EOR_ABS(value.unwrap() as u16),
-            0x4e => LSR_ABS(value.unwrap() as u16),
-
-            // 5
-            0x51 => BVC(value.unwrap() as i8),
-            0x52 => EOR_YIND(value.unwrap() as u8),
-            0x55 => EOR_XZPG(value.unwrap() as u8),
-            0x56 => LSR_XZPG(value.unwrap() as u8),
-            0x58 => CLI,
-            0x59 => EOR_YABS(value.unwrap() as u16),
-            0x5d => EOR_XABS(value.unwrap() as u16),
-            0x5e => LSR_XABS(value.unwrap() as u16),
-
-            // 6
-            0x60 => RTS,
-            0x61 => ADC_XIND(value.unwrap() as u8),
-            0x65 => ADC_ZPG(value.unwrap() as u8),
-            0x66 => ROR_ZPG(value.unwrap() as u8),
-            0x68 => PLA,
-            0x69 => ADC_IMM(value.unwrap() as u8), // nice
-            0x6a => ROR_A,
-            0x6c => JMP_IND(value.unwrap() as u16),
-            0x6d => ADC_ABS(value.unwrap() as u16),
-            0x6e => ROR_ABS(value.unwrap() as u16),
-
-            // 7
-            0x70 => BVS(value.unwrap() as i8),
-            0x71 => ADC_YIND(value.unwrap() as u8),
-            0x75 => ADC_XZPG(value.unwrap() as u8),
-            0x76 => ROR_XZPG(value.unwrap() as u8),
-            0x78 => SEI,
-            0x79 => ADC_YABS(value.unwrap() as u16),
-            0x7d => ADC_XABS(value.unwrap() as u16),
-            0x7e => ROR_XABS(value.unwrap() as u16),
-
-            // 8
-            0x81 => STA_XIND(value.unwrap() as u8),
-            0x84 => STY_ZPG(value.unwrap() as u8),
-            0x85 => STA_ZPG(value.unwrap() as u8),
-            0x86 => STX_ZPG(value.unwrap() as u8),
-            0x88 => DEY,
-            0x8a => TXA,
-            0x8c => STY_ABS(value.unwrap() as u16),
-            0x8d => STA_ABS(value.unwrap() as u16),
-            0x8e => STX_ABS(value.unwrap() as u16),
-
-            // 9
-            0x90 => BCC(value.unwrap() as i8),
-            0x91 => STA_YIND(value.unwrap() as u8),
-            0x94 => STY_XZPG(value.unwrap() as u8),
-            0x95 => STA_XZPG(value.unwrap() as u8),
-            0x96 => STX_YZPG(value.unwrap() as u8),
-            0x98 => TYA,
-            0x99 => STA_YABS(value.unwrap() as u16),
-            0x9a => TXS,
-            0x9d => STA_XABS(value.unwrap() as u16),
-
-            // a
-            0xa0 => LDY_IMM(value.unwrap() as u8),
-            0xa1 => LDA_XIND(value.unwrap() as u8),
-            0xa2 => LDX_IMM(value.unwrap() as u8),
-            0xa4 => LDY_ZPG(value.unwrap() as u8),
-            0xa5 => LDA_ZPG(value.unwrap() as u8),
-            0xa6 => LDX_ZPG(value.unwrap() as u8),
-            0xa8 => TAY,
-            0xa9 => LDA_IMM(value.unwrap() as u8),
-            0xaa => TAX, // Make sure to pay your taxes!
-            0xac => LDY_ABS(value.unwrap() as u16),
-            0xad => LDA_ABS(value.unwrap() as u16),
-            0xae => LDX_ABS(value.unwrap() as u16),
-
-            // b
-            0xb0 => BCS(value.unwrap() as i8),
-            0xb1 => LDA_YIND(value.unwrap() as u8),
-            0xb4 => LDY_XZPG(value.unwrap() as u8),
-            0xb5 => LDA_XZPG(value.unwrap() as u8),
-            0xb6 => LDX_YZPG(value.unwrap() as u8),
-            0xb8 => CLV,
-            0xb9 => LDA_YABS(value.unwrap() as u16),
-            0xba => TSX,
-            0xbc => LDY_XABS(value.unwrap() as u16),
-            0xbd => LDA_XABS(value.unwrap() as u16),
-            0xbe => LDX_YABS(value.unwrap() as u16),
-
-            // c
-            0xc0 => CPY_IMM(value.unwrap() as u8),
-            0xc1 => CMP_XIND(value.unwrap() as u8),
-            0xc4 => CPY_ZPG(value.unwrap() as u8),
-            0xc5 => CMP_ZPG(value.unwrap() as u8),
-            0xc6 => DEC_ZPG(value.unwrap() as u8),
-            0xc8 => INY,
-            0xc9 => CMP_IMM(value.unwrap() as u8),
-            0xca => DEX,
-            0xcc => CPY_ABS(value.unwrap() as u16),
-            0xcd => CMP_ABS(value.unwrap() as u16),
-            0xce => DEC_ABS(value.unwrap() as u16),
-
-            // d
-            0xd0 => BNE(value.unwrap() as i8),
-            0xd1 => CMP_YIND(value.unwrap() as u8),
-            0xd5 => CMP_XZPG(value.unwrap() as u8),
-            0xd6 => DEC_XZPG(value.unwrap() as u8),
-            0xd8 => CLD,
-            0xd9 => CMP_YABS(value.unwrap() as u16),
-            0xdd => CMP_XABS(value.unwrap() as u16),
-            0xde => DEC_XABS(value.unwrap() as u16),
-
-            // e
-            0xe0 => CPX_IMM(value.unwrap() as u8),
-            0xe1 => SBC_XIND(value.unwrap() as u8),
-            0xe4 => CPX_ZPG(value.unwrap() as u8),
-            0xe5 => SBC_ZPG(value.unwrap() as u8),
-            0xe6 => INC_ZPG(value.unwrap() as u8),
-            0xe8 => INX,
-            0xe9 => SBC_IMM(value.unwrap() as u8),
-            0xea => NOP, // EA SPORTS
-            0xec => CPX_ABS(value.unwrap() as u16),
-            0xed => SBC_ABS(value.unwrap() as u16),
-            0xee => INC_ABS(value.unwrap() as u16), // E
-
-            // f
-            0xf0 => BEQ(value.unwrap() as i8),
-            0xf1 => SBC_YIND(value.unwrap() as u8),
-            0xf5 => SBC_XZPG(value.unwrap() as u8),
-            0xf6 => INC_XZPG(value.unwrap() as u8),
-            0xf8 => SED,
-            0xf9 => SBC_YABS(value.unwrap() as u16),
-            0xfd => SBC_XABS(value.unwrap() as u16),
-            0xfe => INC_XABS(value.unwrap() as u16),
-
-            _ => panic!("ERROR: Instruction not supported: {}", opcode),
+// Disassemble in the usual assembler syntax.
+// Relative branches are shown as an offset from the branch itself, e.g. BNE *-5
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.mnemonic)?;
+
+        let operand = self.operand;
+        match self.mode {
+            Implied => Ok(()),
+            Accumulator => write!(f, " A"),
+            Immediate => write!(f, " #${:02x}", operand),
+            ZeroPage => write!(f, " ${:02x}", operand),
+            ZeroPageX => write!(f, " ${:02x},X", operand),
+            ZeroPageY => write!(f, " ${:02x},Y", operand),
+            Absolute => write!(f, " ${:04x}", operand),
+            AbsoluteX => write!(f, " ${:04x},X", operand),
+            AbsoluteY => write!(f, " ${:04x},Y", operand),
+            Indirect => write!(f, " (${:04x})", operand),
+            IndirectX => write!(f, " (${:02x},X)", operand),
+            IndirectY => write!(f, " (${:02x}),Y", operand),
+            Relative => {
+                write!(f, " ")?;
+                write_branch_offset(f, operand as u8 as i8 as i32, 2)
+            }
+            ZeroPageIndirect => write!(f, " (${:02x})", operand),
+            AbsoluteIndexedIndirect => write!(f, " (${:04x},X)", operand),
+            ZeroPageRelative => {
+                write!(f, " ${:02x},", operand & 0xff)?;
+                write_branch_offset(f, (operand >> 8) as u8 as i8 as i32, 3)
+            }
+            ImmediateWord => write!(f, " #${:04x}", operand),
+            AbsoluteLong => write!(f, " ${:06x}", operand),
+            AbsoluteLongX => write!(f, " ${:06x},X", operand),
+            ZeroPageIndirectLong => write!(f, " [${:02x}]", operand),
+            ZeroPageIndirectLongY => write!(f, " [${:02x}],Y", operand),
+            StackRelative => write!(f, " ${:02x},S", operand),
+            StackRelativeIndirectY => write!(f, " (${:02x},S),Y", operand),
+            AbsoluteIndirectLong => write!(f, " [${:04x}]", operand),
+            BlockMove => write!(f, " ${:02x},${:02x}", operand >> 8, operand & 0xff),
+            RelativeLong => {
+                write!(f, " ")?;
+                write_branch_offset(f, operand as u16 as i16 as i32, 3)
+            }
+        }
+    }
+}
+
+// Branch targets relative to the start of the branch instruction of the given length
+fn write_branch_offset(f: &mut fmt::Formatter, offset: i32, length: i32) -> fmt::Result {
+    let offset = offset + length;
+    if offset < 0 {
+        write!(f, "*-{}", -offset)
+    } else {
+        write!(f, "*+{}", offset)
+    }
+}
+
+mod test {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    pub fn table_is_complete() {
+        // Every byte decodes to something, documented opcodes are the 151 official ones
+        let documented = NMOS_6502.iter().filter(|entry| !entry.undocumented).count();
+        assert_eq!(documented, 151);
+
+        for opcode in 0..=255u8 {
+            let instruction = Instruction::decode(&NMOS_6502, &[opcode, 0x00, 0x00]).unwrap();
+            assert_eq!(instruction.length(), NMOS_6502[opcode as usize].length());
+        }
+    }
+
+    #[test]
+    pub fn cmos_table_is_complete() {
+        // 212 opcodes, the remaining 44 are NOPs
+        let documented = WDC_65C02.iter().filter(|entry| !entry.undocumented).count();
+        assert_eq!(documented, 212);
+        assert!(WDC_65C02
+            .iter()
+            .filter(|entry| entry.undocumented)
+            .all(|entry| entry.mnemonic == NOP));
+
+        let instruction = Instruction::assemble(&WDC_65C02, NOP, Implied, 0).unwrap();
+        assert_eq!(instruction.opcode, 0xea);
+    }
+
+    #[test]
+    pub fn native_table_is_complete() {
+        // Every opcode of the 65816 is defined
+        assert!(WDC_65C816.iter().all(|entry| !entry.undocumented));
+
+        let instruction = Instruction::decode(&WDC_65C816, &[0x22, 0x56, 0x34, 0x12]).unwrap();
+        assert_eq!(instruction.mnemonic, JSL);
+        assert_eq!(instruction.operand, 0x123456);
+        assert_eq!(instruction.bytes(), vec![0x22, 0x56, 0x34, 0x12]);
+    }
+
+    // Base cycles of every opcode, 16 opcodes per row, transcribed from independent tables so the
+    // opcode tables above are not checked against themselves.
+    //
+    // NMOS 6502: the ticktable of Mike Chambers' fake6502 v1.1, which takes the documented opcodes
+    // from the MOS MCS6500 programming manual and the undocumented ones from "No More Secrets"
+    // (NMOS 6510 Unintended Opcodes). The jams never finish and are counted as 2 there.
+    #[allow(unused)]
+    #[rustfmt::skip]
+    const NMOS_6502_CYCLES: [u8; 256] = [
+    //  x0 x1 x2 x3 x4 x5 x6 x7 x8 x9 xa xb xc xd xe xf
+        7, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 4, 4, 6, 6, // 0x
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 1x
+        6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 4, 4, 6, 6, // 2x
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 3x
+        6, 6, 2, 8, 3, 3, 5, 5, 3, 2, 2, 2, 3, 4, 6, 6, // 4x
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 5x
+        6, 6, 2, 8, 3, 3, 5, 5, 4, 2, 2, 2, 5, 4, 6, 6, // 6x
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // 7x
+        2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // 8x
+        2, 6, 2, 6, 4, 4, 4, 4, 2, 5, 2, 5, 5, 5, 5, 5, // 9x
+        2, 6, 2, 6, 3, 3, 3, 3, 2, 2, 2, 2, 4, 4, 4, 4, // ax
+        2, 5, 2, 5, 4, 4, 4, 4, 2, 4, 2, 4, 4, 4, 4, 4, // bx
+        2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // cx
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // dx
+        2, 6, 2, 8, 3, 3, 5, 5, 2, 2, 2, 2, 4, 4, 6, 6, // ex
+        2, 5, 2, 8, 4, 4, 6, 6, 2, 4, 2, 7, 4, 4, 7, 7, // fx
+    ];
+
+    // W65C02S: the instruction tables of the WDC W65C02S data sheet for the defined opcodes, and
+    // Bruce Clark's "65C02 Opcodes" tutorial on 6502.org (tutorials/65c02opcodes.html) for the
+    // undefined ones, which are NOPs of 1 to 8 cycles.
+    #[allow(unused)]
+    #[rustfmt::skip]
+    const WDC_65C02_CYCLES: [u8; 256] = [
+    //  x0 x1 x2 x3 x4 x5 x6 x7 x8 x9 xa xb xc xd xe xf
+        7, 6, 2, 1, 5, 3, 5, 5, 3, 2, 2, 1, 6, 4, 6, 5, // 0x
+        2, 5, 5, 1, 5, 4, 6, 5, 2, 4, 2, 1, 6, 4, 6, 5, // 1x
+        6, 6, 2, 1, 3, 3, 5, 5, 4, 2, 2, 1, 4, 4, 6, 5, // 2x
+        2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 2, 1, 4, 4, 6, 5, // 3x
+        6, 6, 2, 1, 3, 3, 5, 5, 3, 2, 2, 1, 3, 4, 6, 5, // 4x
+        2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 3, 1, 8, 4, 6, 5, // 5x
+        6, 6, 2, 1, 3, 3, 5, 5, 4, 2, 2, 1, 6, 4, 6, 5, // 6x
+        2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 4, 1, 6, 4, 6, 5, // 7x
+        2, 6, 2, 1, 3, 3, 3, 5, 2, 2, 2, 1, 4, 4, 4, 5, // 8x
+        2, 6, 5, 1, 4, 4, 4, 5, 2, 5, 2, 1, 4, 5, 5, 5, // 9x
+        2, 6, 2, 1, 3, 3, 3, 5, 2, 2, 2, 1, 4, 4, 4, 5, // ax
+        2, 5, 5, 1, 4, 4, 4, 5, 2, 4, 2, 1, 4, 4, 4, 5, // bx
+        2, 6, 2, 1, 3, 3, 5, 5, 2, 2, 2, 3, 4, 4, 6, 5, // cx
+        2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 3, 3, 4, 4, 7, 5, // dx
+        2, 6, 2, 1, 3, 3, 5, 5, 2, 2, 2, 1, 4, 4, 6, 5, // ex
+        2, 5, 5, 1, 4, 4, 6, 5, 2, 4, 4, 1, 4, 4, 7, 5, // fx
+    ];
+
+    // W65C816S: the opcode matrix of the WDC W65C816S data sheet, with the table notes for 16 bit
+    // registers, DL != 0, page crossings and native mode BRK, COP and RTI left out.
+    #[allow(unused)]
+    #[rustfmt::skip]
+    const WDC_65C816_CYCLES: [u8; 256] = [
+    //  x0 x1 x2 x3 x4 x5 x6 x7 x8 x9 xa xb xc xd xe xf
+        7, 6, 7, 4, 5, 3, 5, 6, 3, 2, 2, 4, 6, 4, 6, 5, // 0x
+        2, 5, 5, 7, 5, 4, 6, 6, 2, 4, 2, 2, 6, 4, 7, 5, // 1x
+        6, 6, 8, 4, 3, 3, 5, 6, 4, 2, 2, 5, 4, 4, 6, 5, // 2x
+        2, 5, 5, 7, 4, 4, 6, 6, 2, 4, 2, 2, 4, 4, 7, 5, // 3x
+        6, 6, 2, 4, 7, 3, 5, 6, 3, 2, 2, 3, 3, 4, 6, 5, // 4x
+        2, 5, 5, 7, 7, 4, 6, 6, 2, 4, 3, 2, 4, 4, 7, 5, // 5x
+        6, 6, 6, 4, 3, 3, 5, 6, 4, 2, 2, 6, 5, 4, 6, 5, // 6x
+        2, 5, 5, 7, 4, 4, 6, 6, 2, 4, 4, 2, 6, 4, 7, 5, // 7x
+        2, 6, 4, 4, 3, 3, 3, 6, 2, 2, 2, 3, 4, 4, 4, 5, // 8x
+        2, 6, 5, 7, 4, 4, 4, 6, 2, 5, 2, 2, 4, 5, 5, 5, // 9x
+        2, 6, 2, 4, 3, 3, 3, 6, 2, 2, 2, 4, 4, 4, 4, 5, // ax
+        2, 5, 5, 7, 4, 4, 4, 6, 2, 4, 2, 2, 4, 4, 4, 5, // bx
+        2, 6, 3, 4, 3, 3, 5, 6, 2, 2, 2, 3, 4, 4, 6, 5, // cx
+        2, 5, 5, 7, 6, 4, 6, 6, 2, 4, 3, 3, 6, 4, 7, 5, // dx
+        2, 6, 3, 4, 3, 3, 5, 6, 2, 2, 2, 3, 4, 4, 6, 5, // ex
+        2, 5, 5, 7, 5, 4, 6, 6, 2, 4, 4, 2, 8, 4, 7, 5, // fx
+    ];
+
+    #[test]
+    pub fn cycles() {
+        let tables = [
+            ("6502", &NMOS_6502, &NMOS_6502_CYCLES),
+            ("65C02", &WDC_65C02, &WDC_65C02_CYCLES),
+            ("65C816", &WDC_65C816, &WDC_65C816_CYCLES),
+        ];
+
+        for (name, table, reference) in tables {
+            for (opcode, entry) in table.iter().enumerate() {
+                assert_eq!(
+                    entry.cycles, reference[opcode],
+                    "{} opcode {:02x} {} {:?}",
+                    name, opcode, entry.mnemonic, entry.mode
+                );
+            }
         }
     }
 
-    // Returns the amount of bytes that it will read after the instruction
-    pub fn param_count(opcode: u8) -> usize {
-        match opcode {
-            // Zero length parameters
-            0x00 | 0x08 | 0x0a | 0x18 | 0x28 | 0x2a | 0x38 | 0x40 | 0x48 | 0x4a | 0x58 | 0x60
-            | 0x68 | 0x6a | 0x78 | 0x88 | 0x8a | 0x98 | 0x9a | 0xa8 | 0xaa | 0xb8 | 0xba | 0xc8
-            | 0xca | 0xd8 | 0xe8 | 0xea | 0xf8 => 0,
-
-            // Byte parameters
-            0x01..=0x06
-            | 0x09
-            | 0x10..=0x16
-            | 0x21..=0x26
-            | 0x29
-            | 0x30..=0x36
-            | 0x41..=0x46
-            | 0x49
-            | 0x50..=0x56
-            | 0x61..=0x66
-            | 0x69
-            | 0x70..=0x76
-            | 0x81..=0x86
-            | 0x90..=0x96
-            | 0xa0..=0xa6
-            | 0xa9
-            | 0xb0..=0xb6
-            | 0xc0..=0xc6
-            | 0xc9
-            | 0xd0..=0xd6
-            | 0xe0..=0xe6
-            | 0xe9
-            | 0xf0..=0xf6 => 1,
-
-            // Word parameters
-            _ => 2,
+    #[test]
+    pub fn decode() {
+        let instruction = Instruction::decode(&NMOS_6502, &[0xbd, 0x34, 0x12]).unwrap();
+        assert_eq!(instruction.mnemonic, LDA);
+        assert_eq!(instruction.mode, AbsoluteX);
+        assert_eq!(instruction.operand, 0x1234);
+        assert_eq!(instruction.cycles, 4);
+        assert_eq!(instruction.bytes(), vec![0xbd, 0x34, 0x12]);
+
+        // BVC is 0x50, 0x51 is EOR ($nn),Y
+        assert_eq!(NMOS_6502[0x50].mnemonic, BVC);
+        assert_eq!(NMOS_6502[0x51].mnemonic, EOR);
+        assert_eq!(NMOS_6502[0x51].mode, IndirectY);
+
+        // Not enough bytes for the operand
+        assert_eq!(Instruction::decode(&NMOS_6502, &[0xad, 0x00]), None);
+    }
+
+    #[test]
+    pub fn assemble() {
+        let instruction = Instruction::assemble(&NMOS_6502, NOP, Implied, 0).unwrap();
+        assert_eq!(instruction.opcode, 0xea);
+
+        let instruction = Instruction::assemble(&NMOS_6502, SBC, Immediate, 0x10).unwrap();
+        assert_eq!(instruction.opcode, 0xe9);
+
+        assert_eq!(Instruction::assemble(&NMOS_6502, STA, Immediate, 0), None);
+    }
+
+    #[test]
+    pub fn disassemble() {
+        let cases: [(&[u8], &str); 8] = [
+            (&[0x0a], "ASL A"),
+            (&[0xa9, 0x10], "LDA #$10"),
+            (&[0xb6, 0x10], "LDX $10,Y"),
+            (&[0x9d, 0x00, 0x02], "STA $0200,X"),
+            (&[0x6c, 0xff, 0x02], "JMP ($02ff)"),
+            (&[0xa1, 0x20], "LDA ($20,X)"),
+            (&[0xd0, 0xfb], "BNE *-3"),
+            (&[0x60], "RTS"),
+        ];
+
+        for (bytes, text) in cases {
+            let instruction = Instruction::decode(&NMOS_6502, bytes).unwrap();
+            assert_eq!(instruction.to_string(), text);
+        }
+
+        let cases: [(&[u8], &str); 4] = [
+            (&[0xb2, 0x20], "LDA ($20)"),
+            (&[0x7c, 0x00, 0x03], "JMP ($0300,X)"),
+            (&[0xb7, 0x10], "SMB3 $10"),
+            (&[0x0f, 0x10, 0xfd], "BBR0 $10,*+0"),
+        ];
+
+        for (bytes, text) in cases {
+            let instruction = Instruction::decode(&WDC_65C02, bytes).unwrap();
+            assert_eq!(instruction.to_string(), text);
+        }
+
+        let cases: [(&[u8], &str); 6] = [
+            (&[0xbf, 0x00, 0x80, 0x7e], "LDA $7e8000,X"),
+            (&[0xb7, 0x10], "LDA [$10],Y"),
+            (&[0x93, 0x03], "STA ($03,S),Y"),
+            (&[0x54, 0x7f, 0x7e], "MVN $7e,$7f"),
+            (&[0x82, 0xfd, 0xff], "BRL *+0"),
+            (&[0xdc, 0x00, 0x03], "JML [$0300]"),
+        ];
+
+        for (bytes, text) in cases {
+            let instruction = Instruction::decode(&WDC_65C816, bytes).unwrap();
+            assert_eq!(instruction.to_string(), text);
         }
     }
 }
@@ -1,454 +1,2961 @@
-use crate::operators::OPCodes::{self, *};
+use crate::bus::{Bus, BusFault, Ram};
+use crate::io_port::IoPort;
+use crate::operators::AddressingMode::{self, *};
+use crate::operators::Instruction;
+use crate::operators::Mnemonic::*;
+use crate::status::Status;
+use crate::variant::Variant;
+use std::collections::HashSet;
+use std::fmt;
+
+mod cycle;
+mod native;
+mod step;
+
+pub use step::{MemoryAccess, StepReport};
+
+// Interrupt vectors at the top of memory. Each holds the address to jump to.
+const NMI_VECTOR: u16 = 0xfffa;
+const RESET_VECTOR: u16 = 0xfffc;
+const IRQ_VECTOR: u16 = 0xfffe;
+
+// 65816 vectors. COP has its own vector in both modes, native mode has its own set.
+const COP_VECTOR: u16 = 0xfff4;
+const NATIVE_COP_VECTOR: u16 = 0xffe4;
+const NATIVE_BRK_VECTOR: u16 = 0xffe6;
+const NATIVE_NMI_VECTOR: u16 = 0xffea;
+const NATIVE_IRQ_VECTOR: u16 = 0xffee;
+
+// Value the unstable undocumented opcodes (XAA, LAX #imm) OR into A by default.
+// It depends on the chip and on temperature, 0xee is what most NMOS parts show.
+const DEFAULT_UNSTABLE_MAGIC: u8 = 0xee;
+
+// Whether the CPU is running instructions
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum State {
+    Running,
+
+    // A JAM (KIL) opcode locked up the CPU. Only reset() gets it going again
+    Jammed { address: u16, opcode: u8 },
+
+    // WAI on the 65C02: sleeping until an interrupt line is asserted
+    Waiting,
+
+    // STP on the 65C02: the clock is stopped until reset()
+    Stopped,
+}
+
+// Why execute() or step() gave up
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CpuError {
+    // An undocumented opcode while they are disabled. PC is left on it
+    IllegalOpcode { address: u16, opcode: u8 },
+    // A JAM opcode locked up the CPU. Only reset() gets it going again
+    Jammed { address: u16, opcode: u8 },
+    // PC reached a breakpoint. The instruction there hasn't run yet
+    Breakpoint { address: u32 },
+    // execute() used up its cycles before the CPU stopped by itself
+    CycleLimit { cycles: u64 },
+    // Something behind the bus couldn't handle an access of the last instruction
+    BusFault(BusFault),
+    // write_program() was given more than fits between $0200 and the vectors
+    ProgramTooLarge { size: usize },
+}
+
+impl fmt::Display for CpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CpuError::IllegalOpcode { address, opcode } => {
+                write!(f, "illegal opcode ${:02x} at ${:04x}", opcode, address)
+            }
+            CpuError::Jammed { address, opcode } => {
+                write!(f, "jammed by opcode ${:02x} at ${:04x}", opcode, address)
+            }
+            CpuError::Breakpoint { address } => write!(f, "breakpoint at ${:04x}", address),
+            CpuError::CycleLimit { cycles } => write!(f, "still running after {} cycles", cycles),
+            CpuError::BusFault(fault) => write!(f, "{}", fault),
+            CpuError::ProgramTooLarge { size } => {
+                write!(f, "a program of {} bytes doesn't fit in memory", size)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CpuError {}
+
+// A copy of every register, see Processor for what they hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Registers {
+    pub a: u8,
+    pub x: u8,
+    pub y: u8,
+    pub pc: u16,
+    pub sp: u8,
+    pub sr: Status,
+
+    pub b: u8,
+    pub xh: u8,
+    pub yh: u8,
+    pub sh: u8,
+    pub dp: u16,
+    pub dbr: u8,
+    pub pbr: u8,
+    pub emulation: bool,
+}
+
+// What next_instruction() went through
+struct Executed {
+    cycles: u64,
+    interrupt: bool,
+    // The instruction that started, and its address
+    instruction: Option<(u32, Instruction)>,
+}
+
+// What caused an interrupt sequence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Interrupt {
+    Brk,
+    Cop,
+    Irq,
+    Nmi,
+}
+
+impl Interrupt {
+    fn vector(&self, native: bool) -> u16 {
+        match (self, native) {
+            (Interrupt::Brk, false) | (Interrupt::Irq, false) => IRQ_VECTOR,
+            (Interrupt::Nmi, false) => NMI_VECTOR,
+            (Interrupt::Cop, false) => COP_VECTOR,
+            (Interrupt::Brk, true) => NATIVE_BRK_VECTOR,
+            (Interrupt::Irq, true) => NATIVE_IRQ_VECTOR,
+            (Interrupt::Nmi, true) => NATIVE_NMI_VECTOR,
+            (Interrupt::Cop, true) => NATIVE_COP_VECTOR,
+        }
+    }
+}
 
 // Processor based on the 6502
 // Components:
-//   - RAM:
+//   - Bus: the memory the CPU sees, plain RAM by default, laid out like this:
 //      - Zero page (0x0000 -> 0x00ff)
 //      - Stack (0x0100 -> 0x01ff)
-//      - Program (0x200 -> 0xfffb)
-//      - EXEC START -> 0xfffc+0xfffd
+//      - Program (0x200 -> 0xfff9)
+//      (The full 64 KiB are addressable, all address arithmetic wraps around at 0xffff.
+//       The 65816 addresses 16 MiB, bank 0 holds the zero page, stack and vectors)
+//      - NMI vector -> 0xfffa+0xfffb
+//      - EXEC START (RESET vector) -> 0xfffc+0xfffd
+//      - IRQ/BRK vector -> 0xfffe+0xffff
 //   - Registers:
 //      - Accumulator (A): 8 bits
 //      - X: 8 bits
 //      - Y: 8 bits
 //      - Program Counter (PC): 16 bits
 //      - Stack Pointer (SP): 8 bits
-//      - Status (SR) (Bits meaning): 8bits
-//          - 7th: Negative
-//          - 6th: Overflow
-//          - 5th: -
-//          - 4th: Break
-//          - 3rd: Decimal
-//          - 2nd: Interrupt disable
-//          - 1st: Zero
-//          - 0th: Carry
+//      - Status (SR): 8 bits, see Status for the meaning of each bit
+//   - 65816 registers:
+//      - B: high byte of the 16 bit accumulator C, X and Y high bytes, SP high byte
+//      - Direct page (D): 16 bits, where the zero page modes point to
+//      - Data bank (DBR) and program bank (PBR): 8 bits, top byte of 24 bit addresses
+//      - Emulation flag (E)
+//     On the other variants they keep the values that make them behave like a 6502:
+//     zero high bytes and banks, the stack in page 0x01 and E set.
 #[allow(dead_code)]
-pub struct Processor {
-    ram: [u8; 0xffff],
+pub struct Processor<B: Bus = Ram> {
+    bus: B,
     a: u8,
     x: u8,
     y: u8,
     pc: u16,
     sp: u8,
-    sr: u8,
+    sr: Status,
+    variant: Variant,
+
+    b: u8,
+    xh: u8,
+    yh: u8,
+    sh: u8,
+    dp: u16,
+    dbr: u8,
+    pbr: u8,
+    emulation: bool,
+
+    // Interrupt lines. IRQ is level triggered and serviced for as long as it is held,
+    // NMI is edge triggered so we remember that an edge happened until it is serviced.
+    // Both can be pulled by the host (set_irq and set_nmi) and by devices on the bus.
+    irq_line: bool,
+    nmi_line: bool,
+    bus_nmi: bool,
+    nmi_pending: bool,
+
+    state: State,
+
+    // Undocumented NMOS opcodes are only executed when enabled
+    undocumented_opcodes: bool,
+    unstable_magic: u8,
+
+    // The 6510 I/O port, which hides the RAM at $0000 and $0001
+    io_port: Option<IoPort>,
+
+    // Instruction tick() is in the middle of
+    sequence: Option<cycle::Sequence>,
+
+    // Addresses execute() stops in front of
+    breakpoints: HashSet<u32>,
+
+    // Reads and writes done during step(), None when nobody is listening
+    accesses: Option<Vec<MemoryAccess>>,
+}
+
+impl Default for Processor {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Processor {
     // Create a new 6502 Processor
     pub fn new() -> Self {
+        Self::with_variant(Variant::Nmos6502)
+    }
+
+    // Create a new Processor behaving like the given CPU variant, with RAM over its whole
+    // address space
+    pub fn with_variant(variant: Variant) -> Self {
+        Self::with_bus(variant, Ram::new(variant.memory_size()))
+    }
+}
+
+impl<B: Bus> Processor<B> {
+    // Create a new Processor reading and writing memory through the given bus
+    pub fn with_bus(variant: Variant, bus: B) -> Self {
         Self {
-            ram: [0; 0xffff],
+            bus,
             a: 0,
             x: 0,
             y: 0,
 
             // Hardcode program counter to 0xFFFC.
-            // It will read a word from the address and JMP to that address.
+            // reset() will read a word from the address and JMP to that address.
             pc: 0xFFFC,
 
-            // Hardcode Stack pointer to 0x0100
-            // (Stack address space is from 0x0100 to 0x01ff.
+            // The stack lives in page 0x01 and grows downwards from 0x01ff.
+            // SP holds the low byte of the next free slot, reset() moves it to 0xfd.
             sp: 0x00,
-            sr: 0b11111111,
+            sr: Status::new(),
+            variant,
+
+            b: 0,
+            xh: 0,
+            yh: 0,
+            sh: 0x01,
+            dp: 0x0000,
+            dbr: 0,
+            pbr: 0,
+            emulation: true,
+
+            irq_line: false,
+            nmi_line: false,
+            bus_nmi: false,
+            nmi_pending: false,
+
+            state: State::Running,
+
+            undocumented_opcodes: false,
+            unstable_magic: DEFAULT_UNSTABLE_MAGIC,
+
+            io_port: variant.has_io_port().then(IoPort::new),
+
+            sequence: None,
+
+            breakpoints: HashSet::new(),
+
+            accesses: None,
+        }
+    }
+
+    // Let execute() run undocumented opcodes instead of stopping in front of them
+    pub fn set_undocumented_opcodes(&mut self, enabled: bool) {
+        self.undocumented_opcodes = enabled;
+    }
+
+    // Constant used by XAA and LAX #imm, which OR it into A before the AND.
+    // Real chips vary (0x00, 0xee, 0xff are all seen), so pick what the target machine does.
+    pub fn set_unstable_magic(&mut self, magic: u8) {
+        self.unstable_magic = magic;
+    }
+
+    pub fn state(&self) -> State {
+        self.state
+    }
+
+    pub fn a(&self) -> u8 {
+        self.a
+    }
+
+    pub fn set_a(&mut self, value: u8) {
+        self.a = value;
+    }
+
+    pub fn x(&self) -> u8 {
+        self.x
+    }
+
+    pub fn set_x(&mut self, value: u8) {
+        self.x = value;
+    }
+
+    pub fn y(&self) -> u8 {
+        self.y
+    }
+
+    pub fn set_y(&mut self, value: u8) {
+        self.y = value;
+    }
+
+    pub fn pc(&self) -> u16 {
+        self.pc
+    }
+
+    pub fn set_pc(&mut self, value: u16) {
+        self.pc = value;
+    }
+
+    pub fn sp(&self) -> u8 {
+        self.sp
+    }
+
+    pub fn set_sp(&mut self, value: u8) {
+        self.sp = value;
+    }
+
+    // Flags are read and changed through Status by name, e.g. status.set_carry(true)
+    pub fn status(&self) -> Status {
+        self.sr
+    }
+
+    // Outside native mode B and bit 5 are normalised the way PLP does it
+    pub fn set_status(&mut self, status: Status) {
+        self.load_status(status.bits());
+    }
+
+    pub fn registers(&self) -> Registers {
+        Registers {
+            a: self.a,
+            x: self.x,
+            y: self.y,
+            pc: self.pc,
+            sp: self.sp,
+            sr: self.sr,
+            b: self.b,
+            xh: self.xh,
+            yh: self.yh,
+            sh: self.sh,
+            dp: self.dp,
+            dbr: self.dbr,
+            pbr: self.pbr,
+            emulation: self.emulation,
+        }
+    }
+
+    pub fn bus(&self) -> &B {
+        &self.bus
+    }
+
+    pub fn bus_mut(&mut self) -> &mut B {
+        &mut self.bus
+    }
+
+    // The 6510 I/O port, None on variants without one
+    pub fn io_port(&self) -> Option<&IoPort> {
+        self.io_port.as_ref()
+    }
+
+    // Mutable access to the I/O port, to drive its inputs or hook the banking bits
+    pub fn io_port_mut(&mut self) -> Option<&mut IoPort> {
+        self.io_port.as_mut()
+    }
+
+    // Load every register at once, e.g. a snapshot taken by registers().
+    // Only the 65816 leaves emulation mode and has banks and a movable direct page, the other
+    // variants keep their 6502 values. In emulation mode the stack stays in page 0x01 and the
+    // index registers 8 bits wide.
+    pub fn set_registers(&mut self, registers: Registers) {
+        let registers = if self.variant.has_native_mode() {
+            registers
+        } else {
+            Registers {
+                b: 0,
+                dp: 0x0000,
+                dbr: 0,
+                pbr: 0,
+                emulation: true,
+                ..registers
+            }
+        };
+
+        self.a = registers.a;
+        self.x = registers.x;
+        self.y = registers.y;
+        self.pc = registers.pc;
+        self.sp = registers.sp;
+        self.b = registers.b;
+        self.xh = registers.xh;
+        self.yh = registers.yh;
+        self.sh = registers.sh;
+        self.dp = registers.dp;
+        self.dbr = registers.dbr;
+        self.pbr = registers.pbr;
+        self.emulation = registers.emulation;
+        self.sr = registers.sr;
+        if self.emulation {
+            self.set_emulation(true);
+        } else {
+            self.load_status(registers.sr.bits());
+        }
+    }
+
+    // Reset the CPU like pulling the RESET line low.
+    // The 6502 goes through the motions of an interrupt with writes disabled, so SP drops by 3
+    // without touching the stack, interrupts get disabled and PC is loaded from the RESET vector.
+    pub fn reset(&mut self) -> u64 {
+        // The 65816 goes back to emulation mode, with the direct page and banks at 0
+        self.set_emulation(true);
+        self.dp = 0x0000;
+        self.dbr = 0;
+        self.pbr = 0;
+
+        self.sp = self.sp.wrapping_sub(3);
+        self.sr.set_interrupt_disable(true);
+        if self.variant.is_cmos() {
+            self.sr.set_decimal(false);
+        }
+        self.pc = self.read_word_at_address(RESET_VECTOR);
+        self.nmi_pending = false;
+        self.state = State::Running;
+        self.sequence = None;
+        if let Some(io_port) = &mut self.io_port {
+            io_port.reset();
+        }
+        7
+    }
+
+    // Assert (true) or release (false) the IRQ line
+    pub fn set_irq(&mut self, asserted: bool) {
+        self.irq_line = asserted;
+    }
+
+    // Drive the NMI line. Only the transition from released to asserted triggers an NMI
+    pub fn set_nmi(&mut self, asserted: bool) {
+        let before = self.nmi_line || self.bus_nmi;
+        self.nmi_line = asserted;
+        if !before && asserted {
+            self.nmi_pending = true;
+        }
+    }
+
+    fn irq_asserted(&self) -> bool {
+        self.irq_line || self.bus.irq()
+    }
+
+    // Let the hardware outside the CPU catch up with the cycles just taken,
+    // then look at what it does with the NMI line
+    fn catch_up(&mut self, cycles: u64) {
+        if let Some(io_port) = &mut self.io_port {
+            io_port.tick(cycles);
+        }
+        self.bus.tick(cycles);
+
+        let before = self.nmi_line || self.bus_nmi;
+        self.bus_nmi = self.bus.nmi();
+        if !before && self.bus_nmi {
+            self.nmi_pending = true;
         }
     }
 
     // STACK OPERATIONS
-    pub fn read_byte(&mut self) -> Option<u8> {
-        self.pc += 1;
-        Some(self.ram.get(self.pc as usize - 1)?.to_owned())
+    pub fn read_byte(&mut self) -> u8 {
+        // PC wraps around from 0xffff to 0x0000 like the real address bus,
+        // without leaving the program bank
+        let address = self.pc;
+        self.pc = self.pc.wrapping_add(1);
+        self.read_long(self.bank_address(self.pbr, address))
     }
 
-    pub fn read_byte_at_address(&self, address: u16) -> Option<u8> {
-        Some(self.ram.get(address as usize)?.to_owned())
+    pub fn read_byte_at_address(&mut self, address: u16) -> u8 {
+        self.read_long(address as u32)
     }
 
     pub fn write_byte(&mut self, address: u16, data: u8) {
-        self.ram[address as usize] = data.to_le_bytes()[0];
+        self.write_long(address as u32, data);
+    }
+
+    // Access a full 24 bit address. Only the 65816 has memory above 0xffff,
+    // on the others the bank byte is always 0.
+    pub fn read_long(&mut self, address: u32) -> u8 {
+        let address = address & self.address_mask();
+
+        // The I/O port answers for $0000 and $0001 before the bus sees the access
+        let data = match &self.io_port {
+            Some(io_port) if address <= IoPort::DATA as u32 => io_port.read(address as u16),
+            _ => self.bus.read(address),
+        };
+        self.record(address, data, false);
+        data
+    }
+
+    pub fn write_long(&mut self, address: u32, data: u8) {
+        let address = address & self.address_mask();
+        self.record(address, data, true);
+        if let Some(io_port) = &mut self.io_port {
+            if address <= IoPort::DATA as u32 {
+                io_port.write(address as u16, data);
+                return;
+            }
+        }
+
+        self.bus.write(address, data);
+    }
+
+    fn record(&mut self, address: u32, data: u8, write: bool) {
+        if let Some(accesses) = &mut self.accesses {
+            accesses.push(MemoryAccess {
+                address,
+                data,
+                write,
+            });
+        }
+    }
+
+    // What a read at the address would return, without side effects on the bus
+    pub fn peek(&self, address: u32) -> u8 {
+        let address = address & self.address_mask();
+        match &self.io_port {
+            Some(io_port) if address <= IoPort::DATA as u32 => io_port.read(address as u16),
+            _ => self.bus.peek(address),
+        }
+    }
+
+    // Addresses the CPU can put on the bus: 16 bits, or 24 bits for the 65816
+    fn address_mask(&self) -> u32 {
+        self.variant.memory_size() as u32 - 1
+    }
+
+    fn bank_address(&self, bank: u8, address: u16) -> u32 {
+        (bank as u32) << 16 | address as u32
     }
 
-    pub fn read_word(&mut self) -> Option<u16> {
-        Some(self.read_byte()? as u16 | (self.read_byte()? as u16) << 8)
+    pub fn read_word(&mut self) -> u16 {
+        self.read_byte() as u16 | (self.read_byte() as u16) << 8
     }
 
-    pub fn read_word_at_address(&mut self, address: u16) -> Option<u16> {
-        Some(
-            self.read_byte_at_address(address)? as u16
-                | (self.read_byte_at_address(address + 1)? as u16) << 8,
-        )
+    pub fn read_word_at_address(&mut self, address: u16) -> u16 {
+        self.read_byte_at_address(address) as u16
+            | (self.read_byte_at_address(address.wrapping_add(1)) as u16) << 8
     }
 
     pub fn write_word(&mut self, address: u16, data: u16) {
         data.to_le_bytes()
             .iter()
             .enumerate()
-            .map(|(i, d)| self.write_byte(address + i as u16, *d))
+            .map(|(i, d)| self.write_byte(address.wrapping_add(i as u16), *d))
             .count();
     }
 
-    pub fn write_program(&mut self, program: &Vec<u8>) {
-        // Write the program starting at 0x0200
-        for (address, byte) in program.iter().enumerate() {
-            // Make sure we are NOT writing past 0xfffb
-            assert!(address as u16 + 0x0200 < 0xfffc);
-            self.write_byte(address as u16 + 0x0200, byte.to_owned());
+    // Copy memory starting at `address` into the buffer, without side effects on the bus.
+    // Addresses wrap around at the end of the address space.
+    pub fn peek_bytes(&self, address: u32, buffer: &mut [u8]) {
+        for (i, byte) in buffer.iter_mut().enumerate() {
+            *byte = self.peek(address.wrapping_add(i as u32));
         }
+    }
 
-        // Write the start address to start executing from
-        self.write_word(0xfffc, 0x0200);
-
-        // Set the program counter to 0x0200 for now until we can program the JMP instruction
-        self.pc = 0x0200;
+    // Copy bytes to memory starting at `address`. They are written like the CPU would,
+    // so ROM keeps its content and devices see the writes.
+    pub fn write_bytes(&mut self, address: u32, bytes: &[u8]) {
+        for (i, byte) in bytes.iter().enumerate() {
+            self.write_long(address.wrapping_add(i as u32), *byte);
+        }
     }
 
-    // OPCODES handling
-    pub fn execute(&mut self, cycle_limit: u64) {
-        let mut cycles: u64 = 0;
+    pub fn write_program(&mut self, program: &[u8]) -> Result<(), CpuError> {
+        // Write the program starting at 0x0200, making sure we are NOT writing past 0xfffb
+        if program.len() > (RESET_VECTOR - 0x0200) as usize {
+            return Err(CpuError::ProgramTooLarge {
+                size: program.len(),
+            });
+        }
+        self.write_bytes(0x0200, program);
 
-        while cycles < cycle_limit {
-            let instruction = self.read_byte().unwrap();
-            let param_count = OPCodes::param_count(instruction);
+        // Write the start address to start executing from. reset() will jump there
+        self.write_word(RESET_VECTOR, 0x0200);
+        Ok(())
+    }
 
-            let parameter: Option<isize> = match param_count {
-                0 => None,
-                1 => Some(self.read_byte().unwrap() as isize),
-                _ => Some(self.read_word().unwrap() as isize),
-            };
+    // Push a byte onto the stack at 0x0100 + SP, wrapping around inside page 0x01.
+    // In 65816 native mode the stack pointer is 16 bits and can be anywhere in bank 0.
+    fn push_byte(&mut self, data: u8) {
+        let address = self.stack_pointer();
+        self.write_byte(address, data);
+        self.set_stack_pointer(address.wrapping_sub(1));
+    }
 
-            // Get the OPcode related to the hex code
-            let opcode = OPCodes::instruction_to_opcode(instruction, parameter);
+    fn pull_byte(&mut self) -> u8 {
+        let address = self.stack_pointer().wrapping_add(1);
+        self.set_stack_pointer(address);
+        self.read_byte_at_address(self.stack_pointer())
+    }
 
-            // Return of the program means we finished. Break out of loop.
-            if opcode == OPCodes::RTS {
-                break;
-            }
+    fn stack_pointer(&self) -> u16 {
+        (self.sh as u16) << 8 | self.sp as u16
+    }
 
-            // Increase the amount of cycles we have gone through
-            cycles += self.handle_opcode(opcode);
+    // Outside native mode the high byte stays 0x01
+    fn set_stack_pointer(&mut self, address: u16) {
+        self.sp = address as u8;
+        if !self.emulation {
+            self.sh = (address >> 8) as u8;
         }
     }
 
-    fn zero_flag(&mut self, value: u8) {
-        if value == 0 { self.sr |= 0b0000_0010; }
+    // Words are pushed high byte first so they end up little endian in memory
+    fn push_word(&mut self, data: u16) {
+        self.push_byte((data >> 8) as u8);
+        self.push_byte(data as u8);
     }
 
-    fn negative_flag(&mut self, value: u8) {
-        self.sr |= value & 0b1000_0000;
+    fn pull_word(&mut self) -> u16 {
+        self.pull_byte() as u16 | (self.pull_byte() as u16) << 8
     }
 
-    pub fn handle_opcode(&mut self, instruction: OPCodes) -> u64 {
-        // TODO: Update processor flags.
+    // Push PC and the status register, then jump through the vector with interrupts disabled.
+    // B is only set in the pushed status when the interrupt came from a BRK instruction.
+    // CMOS parts also clear decimal mode so handlers don't have to.
+    // In 65816 native mode the program bank is pushed first and there is no B to set,
+    // which costs an extra cycle. Returns the extra cycles.
+    fn interrupt(&mut self, interrupt: Interrupt) -> u64 {
+        let native = !self.emulation;
+        if native {
+            self.push_byte(self.pbr);
+            self.pbr = 0;
+        }
 
-        const TWO_CYCLE: u64 = 2;
-        const THREE_CYCLE: u64 = 3;
-        const FOUR_CYCLE: u64 = 4;
-        const FIVE_CYCLE: u64 = 5;
-        const SIX_CYCLE: u64 = 6;
+        self.push_word(self.pc);
+        if native {
+            self.push_byte(self.sr.bits());
+        } else {
+            self.push_byte(self.sr.pushed(interrupt == Interrupt::Brk));
+        }
 
-        match instruction {
-            ///////////////////////////////////// Flag setters ///////////////////////////////////////
+        self.sr.set_interrupt_disable(true);
+        if self.variant.is_cmos() {
+            self.sr.set_decimal(false);
+        }
+        self.pc = self.read_word_at_address(interrupt.vector(native));
+        native as u64
+    }
 
-            // Set the CARRY flag
-            SEC => {
-                self.sr |= 0b0000_0001;
-                return TWO_CYCLE;
-            }
-            // Set the INTERRUPT DISABLE flag
-            SEI => {
-                self.sr |= 0b0000_0100;
-                return TWO_CYCLE;
-            }
-            // Set DECIMAL MODE flag
-            SED => {
-                self.sr |= 0b0000_1000;
-                return TWO_CYCLE;
-            }
+    // Service a pending NMI, or the IRQ line if interrupts are enabled.
+    // Returns the cycles taken, 0 if nothing was serviced.
+    fn poll_interrupts(&mut self) -> u64 {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            return 7 + self.interrupt(Interrupt::Nmi);
+        }
 
-            ///////////////////////////////////// Flag clearers //////////////////////////////////////
+        if self.irq_asserted() && !self.sr.interrupt_disable() {
+            return 7 + self.interrupt(Interrupt::Irq);
+        }
 
-            // Clear the CARRY flag
-            CLC => {
-                self.sr &= 0b1111_1110;
-                return TWO_CYCLE;
-            }
-            // Clear the OVERFLOW flag
-            CLV => {
-                self.sr &= 0b1011_1111;
-                return TWO_CYCLE;
-            }
-            // Clear the INTERRUPT DISABLE flag
-            CLI => {
-                self.sr &= 0b1111_1011;
-                return TWO_CYCLE;
-            }
-            // Clear the DECIMAL MODE flag
-            CLD => {
-                self.sr &= 0b1111_0111;
-                return TWO_CYCLE;
-            }
+        0
+    }
 
-            ////////////////////////////////// Handle LDA cases /////////////////////////////////////
-            LDA_IMM(value) => {
-                self.a = value;
-                self.zero_flag(value);
-                self.negative_flag(value);
-                return TWO_CYCLE;
-            }
-            LDA_ABS(value) => {
-                self.handle_opcode(LDA_IMM(self.read_byte_at_address(value).unwrap()));
-                return FOUR_CYCLE;
-            }
-            LDA_XABS(value) => {
-                self.handle_opcode(LDA_IMM(
-                    self.read_byte_at_address(value + self.x as u16).unwrap(),
-                ));
-                return FOUR_CYCLE;
-            }
-            LDA_YABS(value) => {
-                self.handle_opcode(LDA_IMM(
-                    self.read_byte_at_address(value + self.y as u16).unwrap(),
-                ));
-                return FOUR_CYCLE;
-            }
-            LDA_ZPG(value) => {
-                self.handle_opcode(LDA_IMM(
-                    self.read_byte_at_address(value as u16 % 0xff).unwrap(),
-                ));
-                return THREE_CYCLE;
-            }
-            LDA_XZPG(value) => {
-                self.handle_opcode(LDA_IMM(
-                    self.read_byte_at_address((value + self.x) as u16 % 0xff)
-                        .unwrap(),
-                ));
-                return FOUR_CYCLE;
-            }
-            LDA_XIND(value) => {
-                let address: u16 = self
-                    .read_word_at_address(value as u16 + self.x as u16)
-                    .unwrap();
-                self.handle_opcode(LDA_IMM(self.read_byte_at_address(address).unwrap()));
-                return SIX_CYCLE;
-            }
-            LDA_YIND(value) => {
-                let address: u16 = self.read_word_at_address(value as u16).unwrap();
-                self.handle_opcode(LDA_IMM(
-                    self.read_byte_at_address(address + self.y as u16).unwrap(),
-                ));
-                return FIVE_CYCLE;
-            }
-
-            ////////////////////////////////// Handle LDX cases /////////////////////////////////////
-            LDX_IMM(value) => {
-                self.x = value;
-                self.zero_flag(value);
-                self.negative_flag(value); 
-                return TWO_CYCLE;
-            }
-            LDX_ABS(value) => {
-                self.handle_opcode(LDX_IMM(self.read_byte_at_address(value).unwrap()));
-                return FOUR_CYCLE;
-            }
-            LDX_YABS(value) => {
-                self.handle_opcode(LDX_IMM(
-                    self.read_byte_at_address(value + self.y as u16).unwrap(),
-                ));
-                return FOUR_CYCLE;
-            }
-            LDX_ZPG(value) => {
-                self.handle_opcode(LDX_IMM(self.read_byte_at_address(value as u16).unwrap()));
-                return THREE_CYCLE;
-            }
-            LDX_YZPG(value) => {
-                self.handle_opcode(LDX_IMM(
-                    self.read_byte_at_address(value as u16 + self.y as u16)
-                        .unwrap(),
-                ));
-                return FOUR_CYCLE;
-            }
-
-            ////////////////////////////////// Handle LDY cases /////////////////////////////////////
-            LDY_IMM(value) => {
-                self.y = value;
-                self.zero_flag(value);
-                self.negative_flag(value);
-                return TWO_CYCLE;
-            }
-            LDY_ABS(value) => {
-                self.handle_opcode(LDY_IMM(self.read_byte_at_address(value).unwrap()));
-                return FOUR_CYCLE;
-            }
-            LDY_XABS(value) => {
-                self.handle_opcode(LDY_IMM(
-                    self.read_byte_at_address(value + self.x as u16).unwrap(),
-                ));
-                return FOUR_CYCLE;
-            }
-            LDY_ZPG(value) => {
-                self.handle_opcode(LDY_IMM(self.read_byte_at_address(value as u16).unwrap()));
-                return THREE_CYCLE;
-            }
-            LDY_XZPG(value) => {
-                self.handle_opcode(LDY_IMM(
-                    self.read_byte_at_address(value as u16 + self.x as u16)
-                        .unwrap(),
-                ));
-                return FOUR_CYCLE;
-            }
+    // OPCODES handling
+    // Run instructions until `cycle_limit` cycles went by or the CPU stops by itself: STP, WAI
+    // with no interrupt coming, or a trap, i.e. an instruction jumping or branching back to
+    // itself. Breakpoints are checked in front of every instruction but the first one, so
+    // calling execute() again resumes from the breakpoint it stopped at.
+    // Returns the cycles taken when the CPU stopped by itself.
+    pub fn execute(&mut self, cycle_limit: u64) -> Result<u64, CpuError> {
+        let mut cycles: u64 = 0;
 
-            ////////////////////////////////// Handle STA cases /////////////////////////////////////
-            STA_ABS(value) => {
-                self.write_byte(value, self.a);
-                return FOUR_CYCLE;
-            }
-            STA_XABS(value) => {
-                self.write_byte(value + self.x as u16, self.a);
-                return FOUR_CYCLE;
-            }
-            STA_YABS(value) => {
-                self.write_byte(value + self.y as u16, self.a);
-                return FOUR_CYCLE;
-            }
-            STA_ZPG(value) => {
-                self.write_byte(value as u16, self.a);
-                return THREE_CYCLE;
-            }
-            STA_XZPG(value) => {
-                self.write_byte(value as u16 + self.x as u16, self.a);
-                return FOUR_CYCLE;
-            }
-            STA_XIND(value) => {
-                let addr = self
-                    .read_word_at_address(value as u16 + self.x as u16)
-                    .unwrap();
-                self.write_byte(addr, self.a);
-                return SIX_CYCLE;
-            }
-            STA_YIND(value) => {
-                let addr = self.read_word_at_address(value as u16).unwrap();
-                self.write_byte(addr + self.y as u16, self.a);
-                return FIVE_CYCLE;
+        while cycles < cycle_limit {
+            let address = self.bank_address(self.pbr, self.pc);
+            if cycles > 0 && self.breakpoints.contains(&address) {
+                return Err(CpuError::Breakpoint { address });
             }
 
-            ////////////////////////////////// Handle STX cases /////////////////////////////////////
-            
-            STX_ABS(value) => {
-                self.write_byte(value, self.x);
-                return THREE_CYCLE;
-            }
-            STX_ZPG(value) => {
-                self.write_byte(value as u16, self.x);
-                return THREE_CYCLE;
-            }
-            STX_YZPG(value) => {
-                self.write_byte(value as u16 + self.y as u16, self.x);
-                return FOUR_CYCLE;
+            let executed = self.next_instruction()?;
+            if executed.cycles == 0 {
+                return Ok(cycles);
             }
+            cycles += executed.cycles;
 
-            ////////////////////////////////// Handle STY cases /////////////////////////////////////
-            
-            STY_ABS(value) => {
-                self.write_byte(value, self.y);
-                return THREE_CYCLE;
+            // Block moves run again from the same address until they are done
+            if let Some((start, instruction)) = executed.instruction {
+                let trapped = self.bank_address(self.pbr, self.pc) == start;
+                if trapped && !matches!(instruction.mnemonic, MVN | MVP) {
+                    return Ok(cycles);
+                }
             }
-            STY_ZPG(value) => {
-                self.write_byte(value as u16, self.y);
-                return THREE_CYCLE;
-            }
-            STY_XZPG(value) => {
-                self.write_byte(value as u16 + self.x as u16, self.y);
-                return FOUR_CYCLE;
+        }
+
+        Err(CpuError::CycleLimit { cycles })
+    }
+
+    // Run the next instruction, after servicing a pending interrupt if there is one, or finish
+    // the one tick() is in the middle of.
+    // Takes 0 cycles when the CPU is stopped or waiting for an interrupt.
+    fn next_instruction(&mut self) -> Result<Executed, CpuError> {
+        let mut cycles: u64 = 0;
+
+        // Finish what tick() started
+        if self.sequence.is_some() {
+            while self.sequence.is_some() {
+                self.tick()?;
+                cycles += 1;
             }
+            return Ok(Executed {
+                cycles,
+                interrupt: false,
+                instruction: None,
+            });
+        }
 
-            
-            ////////////////////////////// Handle Transfer cases ////////////////////////////////////
-    
-            TAX => {
-                self.x = self.a;
-                self.zero_flag(self.x);
-                self.negative_flag(self.x);
-                return TWO_CYCLE;
+        match self.state {
+            State::Running => {}
+            // WAI wakes up on any interrupt line, even an IRQ masked by I.
+            // A masked IRQ simply resumes with the instruction after WAI.
+            State::Waiting if self.nmi_pending || self.irq_asserted() => {
+                self.state = State::Running;
             }
-            TAY => {
-                self.y = self.a;
-                self.zero_flag(self.y);
-                self.negative_flag(self.y);
-                return TWO_CYCLE;
+            State::Jammed { address, opcode } => return Err(CpuError::Jammed { address, opcode }),
+            State::Waiting | State::Stopped => {
+                return Ok(Executed {
+                    cycles: 0,
+                    interrupt: false,
+                    instruction: None,
+                })
             }
+        }
 
-           TXA => {
-                self.a = self.x;
-                self.zero_flag(self.a);
-                self.negative_flag(self.a);
-                return TWO_CYCLE;
-            }
+        // Interrupts are checked between instructions
+        let serviced = self.poll_interrupts();
+        if serviced > 0 {
+            cycles += serviced;
+            self.catch_up(serviced);
+        }
 
-           TYA => {
-                self.a = self.y;
-                self.zero_flag(self.a);
-                self.negative_flag(self.a);
-                return TWO_CYCLE;
-            }
+        let start = self.pc;
+        let address = self.bank_address(self.pbr, start);
+        let opcode = self.read_byte();
+        let entry = self.widen_immediate(self.variant.opcodes()[opcode as usize]);
 
-           TXS => {
-                self.sp = self.x;
-                self.zero_flag(self.sp);
-                self.negative_flag(self.sp);
-                return TWO_CYCLE;
-            }
+        // Undocumented opcodes only run when enabled, otherwise stop in front of them.
+        // The undefined 65C02 opcodes are guaranteed NOPs and always run.
+        if entry.undocumented && !self.undocumented_opcodes && !self.variant.has_undefined_nops() {
+            self.pc = start;
+            return Err(CpuError::IllegalOpcode {
+                address: start,
+                opcode,
+            });
+        }
 
-           TSX => {
-                self.x = self.sp;
-                self.zero_flag(self.x);
-                self.negative_flag(self.x);
-                return TWO_CYCLE;
-            }
+        let mut operand = 0;
+        for i in 0..entry.mode.operand_bytes() {
+            operand |= (self.read_byte() as u32) << (8 * i);
+        }
 
-            // Other shit todo
-            _ => {
-                dbg!(instruction);
-                return 0;
-            }
+        let instruction = Instruction::new(opcode, entry, operand);
+        let used = self.handle_opcode(instruction);
+        cycles += used;
+        self.catch_up(used);
+
+        if let State::Jammed { address, opcode } = self.state {
+            return Err(CpuError::Jammed { address, opcode });
         }
+        self.check_faults()?;
+        Ok(Executed {
+            cycles,
+            interrupt: serviced > 0,
+            instruction: Some((address, instruction)),
+        })
     }
-}
 
-mod test {
-    #[allow(unused)]
-    use super::*;
+    // Hand over what the bus complained about during the last instruction
+    fn check_faults(&mut self) -> Result<(), CpuError> {
+        match self.bus.fault() {
+            Some(fault) => Err(CpuError::BusFault(fault)),
+            None => Ok(()),
+        }
+    }
 
-    #[test]
-    pub fn write_and_write_byte() {
-        let mut processor = Processor::new();
-        processor.pc = 0xff2f;
-        processor.write_byte(0xff2f, 0x33);
-        assert_eq!(processor.read_byte(), Some(0x33));
+    // Stop execute() in front of the instruction at `address` (24 bits on the 65816)
+    pub fn add_breakpoint(&mut self, address: u32) {
+        self.breakpoints.insert(address);
     }
 
-    #[test]
-    pub fn write_and_read_word() {
-        let mut processor = Processor::new();
+    pub fn remove_breakpoint(&mut self, address: u32) {
+        self.breakpoints.remove(&address);
+    }
 
-        processor.pc = 0xff2f;
-        processor.write_word(0xff2f, 0xf6e4);
-        assert_eq!(processor.read_word(), Some(0xf6e4));
+    // Whether ADC and SBC work in BCD: D is set and the variant has a decimal mode
+    fn decimal_mode(&self) -> bool {
+        self.sr.decimal() && self.variant.has_decimal_mode()
     }
 
-    #[test]
-    pub fn test_lda() {
-        let mut processor = Processor::new();
+    // A + M + C. Overflow is set when both operands share a sign that differs from the result.
+    fn add_with_carry(&mut self, value: u8) {
+        if self.decimal_mode() {
+            match self.variant {
+                Variant::Nmos6502 | Variant::Mos6510 => self.decimal_add_nmos(value),
+                Variant::Wdc65C02 | Variant::Wdc65C816 => self.decimal_add_cmos(value),
+                Variant::Ricoh2A03 => unreachable!("the 2A03 has no decimal mode"),
+            }
+            return;
+        }
 
-        // Write the value 0x30 into ff2f
-        processor.write_byte(0xff2f, 0x30);
+        let sum = self.a as u16 + value as u16 + self.sr.carry() as u16;
+        let result = sum as u8;
 
-        // Write the program
-        processor.write_program(&vec![0xad, 0x2f, 0xff, 0x60]);
+        self.sr.set_overflow((self.a ^ result) & (value ^ result) & 0b1000_0000 != 0);
+        self.sr.set_carry(sum > 0xff);
+        self.a = result;
+        self.sr.set_zero_negative(self.a);
+    }
 
-        // Execute
-        const MAX_CYCLES: u64 = 0xffff;
-        processor.execute(MAX_CYCLES);
+    // A - M - !C. In binary mode this is the same as adding the one's complement of M.
+    fn subtract_with_carry(&mut self, value: u8) {
+        if self.decimal_mode() {
+            match self.variant {
+                Variant::Nmos6502 | Variant::Mos6510 => self.decimal_subtract_nmos(value),
+                Variant::Wdc65C02 | Variant::Wdc65C816 => self.decimal_subtract_cmos(value),
+                Variant::Ricoh2A03 => unreachable!("the 2A03 has no decimal mode"),
+            }
+            return;
+        }
 
-        // Check A
-        assert_eq!(processor.a, 0x30);
+        self.add_with_carry(!value);
+    }
+
+    // BCD addition as done by the NMOS 6502.
+    // Only the accumulator and carry are decimal: Z comes from the binary sum, while N and V
+    // come from the sum after the low nibble has been adjusted but before the high nibble is.
+    fn decimal_add_nmos(&mut self, value: u8) {
+        let carry = self.sr.carry() as i16;
+        let binary = self.a.wrapping_add(value).wrapping_add(carry as u8);
+
+        let mut low = (self.a & 0x0f) as i16 + (value & 0x0f) as i16 + carry;
+        if low >= 0x0a {
+            low = ((low + 0x06) & 0x0f) + 0x10;
+        }
+
+        // N and V look at the half adjusted sum using signed arithmetic
+        let signed = (self.a & 0xf0) as i8 as i16 + (value & 0xf0) as i8 as i16 + low;
+        self.sr.set_negative(signed & 0x80 != 0);
+        self.sr.set_overflow(!(-128..=127).contains(&signed));
+        self.sr.set_zero(binary == 0);
+
+        let mut result = (self.a & 0xf0) as i16 + (value & 0xf0) as i16 + low;
+        if result >= 0xa0 {
+            result += 0x60;
+        }
+
+        self.sr.set_carry(result >= 0x100);
+        self.a = result as u8;
+    }
+
+    // BCD subtraction as done by the NMOS 6502.
+    // Every flag is the same as in binary mode, only the accumulator is decimal adjusted.
+    fn decimal_subtract_nmos(&mut self, value: u8) {
+        let borrow = 1 - self.sr.carry() as i16;
+
+        let mut low = (self.a & 0x0f) as i16 - (value & 0x0f) as i16 - borrow;
+        if low < 0 {
+            low = ((low - 0x06) & 0x0f) - 0x10;
+        }
+
+        let mut result = (self.a & 0xf0) as i16 - (value & 0xf0) as i16 + low;
+        if result < 0 {
+            result -= 0x60;
+        }
+
+        // Flags are calculated exactly like a binary subtraction
+        let decimal = result as u8;
+        self.sr.set_decimal(false);
+        self.add_with_carry(!value);
+        self.sr.set_decimal(true);
+        self.a = decimal;
+    }
+
+    // BCD addition as done by the 65C02. The accumulator, C and V match the NMOS part,
+    // but N and Z are fixed to reflect the decimal result.
+    fn decimal_add_cmos(&mut self, value: u8) {
+        self.decimal_add_nmos(value);
+        self.sr.set_zero_negative(self.a);
+    }
+
+    // BCD subtraction as done by the 65C02. C and V are the binary ones like on the NMOS
+    // part, N and Z reflect the decimal result. The adjustment differs from the NMOS one
+    // for invalid BCD inputs.
+    fn decimal_subtract_cmos(&mut self, value: u8) {
+        let borrow = 1 - self.sr.carry() as i16;
+
+        let low = (self.a & 0x0f) as i16 - (value & 0x0f) as i16 - borrow;
+        let mut result = self.a as i16 - value as i16 - borrow;
+        if result < 0 {
+            result -= 0x60;
+        }
+        if low < 0 {
+            result -= 0x06;
+        }
+
+        let decimal = result as u8;
+        self.sr.set_decimal(false);
+        self.add_with_carry(!value);
+        self.sr.set_decimal(true);
+        self.a = decimal;
+        self.sr.set_zero_negative(self.a);
+    }
+
+    // Branch relative to the next instruction when the condition holds.
+    // Taking the branch costs an extra cycle, and one more if the target is on another page.
+    // Returns the extra cycles.
+    fn branch(&mut self, condition: bool, offset: u32) -> u64 {
+        if !condition {
+            return 0;
+        }
+
+        // Sign extend the offset so negative values wrap backwards
+        let target = self.pc.wrapping_add(offset as u8 as i8 as u16);
+        let cycles = if target & 0xff00 != self.pc & 0xff00 { 2 } else { 1 };
+
+        self.pc = target;
+        cycles
+    }
+
+    // Work out the effective address of a memory operand, the way the address bus would.
+    // Zero page indexing and zero page pointers wrap around inside page zero, so
+    // LDA $ff,X with X = 1 reads $00 and LDA ($ff),Y takes the pointer from $ff and $00.
+    // The boolean tells whether indexing crossed a page boundary.
+    // On the 65816 the zero page is the direct page, and data lives in the data bank.
+    fn effective_address(&mut self, mode: AddressingMode, operand: u32) -> (u32, bool) {
+        let x = self.index_x();
+        let y = self.index_y();
+
+        match mode {
+            ZeroPage => (self.direct_address(operand as u8, 0) as u32, false),
+            ZeroPageX => (self.direct_address(operand as u8, x) as u32, false),
+            ZeroPageY => (self.direct_address(operand as u8, y) as u32, false),
+            Absolute => (self.bank_address(self.dbr, operand as u16), false),
+            AbsoluteX => self.index(self.bank_address(self.dbr, operand as u16), x),
+            AbsoluteY => self.index(self.bank_address(self.dbr, operand as u16), y),
+            IndirectX => {
+                let pointer = self.direct_address(operand as u8, x);
+                let address = self.read_direct_word(pointer);
+                (self.bank_address(self.dbr, address), false)
+            }
+            IndirectY => {
+                let pointer = self.direct_address(operand as u8, 0);
+                let base = self.read_direct_word(pointer);
+                self.index(self.bank_address(self.dbr, base), y)
+            }
+            ZeroPageIndirect => {
+                let pointer = self.direct_address(operand as u8, 0);
+                let address = self.read_direct_word(pointer);
+                (self.bank_address(self.dbr, address), false)
+            }
+            Indirect => {
+                let operand = operand as u16;
+                let low = self.read_byte_at_address(operand);
+
+                // The NMOS part wraps around inside the pointer's page instead of carrying
+                let high_address = if self.variant.has_jmp_indirect_bug() {
+                    (operand & 0xff00) | (operand.wrapping_add(1) & 0x00ff)
+                } else {
+                    operand.wrapping_add(1)
+                };
+                let high = self.read_byte_at_address(high_address);
+
+                (low as u32 | (high as u32) << 8, false)
+            }
+            // The pointer is in the program bank
+            AbsoluteIndexedIndirect => {
+                let pointer = (operand as u16).wrapping_add(x);
+                let low = self.read_long(self.bank_address(self.pbr, pointer));
+                let high = self.read_long(self.bank_address(self.pbr, pointer.wrapping_add(1)));
+                (low as u32 | (high as u32) << 8, false)
+            }
+            // The long and stack relative modes take the same time whether indexing crosses a
+            // page or not
+            AbsoluteLong => (operand, false),
+            AbsoluteLongX => (self.index(operand, x).0, false),
+            ZeroPageIndirectLong => {
+                let pointer = self.direct_address(operand as u8, 0);
+                (self.read_long_pointer(pointer), false)
+            }
+            ZeroPageIndirectLongY => {
+                let pointer = self.direct_address(operand as u8, 0);
+                let base = self.read_long_pointer(pointer);
+                (self.index(base, y).0, false)
+            }
+            StackRelative => {
+                let address = self.stack_pointer().wrapping_add(operand as u16);
+                (address as u32, false)
+            }
+            StackRelativeIndirectY => {
+                let pointer = self.stack_pointer().wrapping_add(operand as u16);
+                let base = self.read_word_at_address(pointer);
+                (self.index(self.bank_address(self.dbr, base), y).0, false)
+            }
+            AbsoluteIndirectLong => (self.read_long_pointer(operand as u16), false),
+            Implied | Accumulator | Immediate | ImmediateWord | Relative | RelativeLong
+            | ZeroPageRelative | BlockMove => {
+                unreachable!("{:?} doesn't address memory", mode)
+            }
+        }
+    }
+
+    // Add an index register to an address. Only the 65816 carries into the bank byte,
+    // the others wrap around at 0xffff.
+    fn index(&self, base: u32, index: u16) -> (u32, bool) {
+        let address = base.wrapping_add(index as u32) & self.address_mask();
+        (address, (address ^ base) & !0xff != 0)
+    }
+
+    // Address inside the direct page. With the direct page on a page boundary outside of
+    // 65816 native mode, indexing wraps around inside the page like on the 6502.
+    fn direct_address(&self, offset: u8, index: u16) -> u16 {
+        if self.emulation && self.dp & 0x00ff == 0 {
+            self.dp | offset.wrapping_add(index as u8) as u16
+        } else {
+            self.dp.wrapping_add(offset as u16).wrapping_add(index)
+        }
+    }
+
+    // Pointers stored in the zero page wrap around from $ff to $00
+    fn read_direct_word(&mut self, pointer: u16) -> u16 {
+        let high = if self.emulation && self.dp & 0x00ff == 0 {
+            (pointer & 0xff00) | (pointer.wrapping_add(1) & 0x00ff)
+        } else {
+            pointer.wrapping_add(1)
+        };
+
+        self.read_byte_at_address(pointer) as u16 | (self.read_byte_at_address(high) as u16) << 8
+    }
+
+    // 24 bit pointer in bank 0 for the long indirect modes
+    fn read_long_pointer(&mut self, pointer: u16) -> u32 {
+        let mut address = 0;
+        for i in 0..3 {
+            let byte = self.read_byte_at_address(pointer.wrapping_add(i));
+            address |= (byte as u32) << (8 * i);
+        }
+        address
+    }
+
+    fn index_x(&self) -> u16 {
+        (self.xh as u16) << 8 | self.x as u16
+    }
+
+    fn index_y(&self) -> u16 {
+        (self.yh as u16) << 8 | self.y as u16
+    }
+
+    // Fetch the operand of a read instruction.
+    // Returns the value and the extra cycle taken when indexing crossed a page.
+    fn read_operand(&mut self, mode: AddressingMode, operand: u32) -> (u8, u64) {
+        if mode == Immediate {
+            return (operand as u8, 0);
+        }
+
+        let (address, page_crossed) = self.effective_address(mode, operand);
+        (self.read_long(address), page_crossed as u64)
+    }
+
+    // Read-modify-write on memory. The NMOS 6502 writes the unmodified value back while the
+    // ALU works on it, and only then writes the result, so memory mapped devices see two writes.
+    // The 65C02 reads the address again instead, which leaves nothing to do here.
+    // Returns the value written.
+    fn read_modify_write(&mut self, address: u32, operation: fn(&mut Self, u8) -> u8) -> u8 {
+        let value = self.read_long(address);
+        if self.writes_back() {
+            self.write_long(address, value);
+        }
+
+        let result = operation(self, value);
+        self.write_long(address, result);
+        result
+    }
+
+    // Whether read-modify-write instructions write the unmodified value back. The 65816 does it
+    // like the NMOS parts in emulation mode, and re-reads like the 65C02 in native mode.
+    fn writes_back(&self) -> bool {
+        !self.variant.is_cmos() || self.variant.has_native_mode() && self.emulation
+    }
+
+    // Shifts, rotates, increments and decrements work either on A or on memory.
+    // Returns the extra cycle the 65C02 takes when abs,X crosses a page. Only the shifts and
+    // rotates are listed with the shorter timing, INC and DEC abs,X always take 7 cycles.
+    fn modify(
+        &mut self,
+        mode: AddressingMode,
+        operand: u32,
+        operation: fn(&mut Self, u8) -> u8,
+    ) -> u64 {
+        if mode == Accumulator {
+            self.a = operation(self, self.a);
+            return 0;
+        }
+
+        let (address, page_crossed) = self.effective_address(mode, operand);
+        self.read_modify_write(address, operation);
+        (page_crossed && mode == AbsoluteX && self.variant == Variant::Wdc65C02) as u64
+    }
+
+    // Shift left, bit 7 goes into carry
+    fn shift_left(&mut self, value: u8) -> u8 {
+        let result = value << 1;
+        self.sr.set_carry(value & 0b1000_0000 != 0);
+        self.sr.set_zero_negative(result);
+        result
+    }
+
+    // Shift right, bit 0 goes into carry
+    fn shift_right(&mut self, value: u8) -> u8 {
+        let result = value >> 1;
+        self.sr.set_carry(value & 0b0000_0001 != 0);
+        self.sr.set_zero_negative(result);
+        result
+    }
+
+    // Rotate left through carry
+    fn rotate_left(&mut self, value: u8) -> u8 {
+        let result = value << 1 | self.sr.carry() as u8;
+        self.sr.set_carry(value & 0b1000_0000 != 0);
+        self.sr.set_zero_negative(result);
+        result
+    }
+
+    // Rotate right through carry
+    fn rotate_right(&mut self, value: u8) -> u8 {
+        let result = value >> 1 | (self.sr.carry() as u8) << 7;
+        self.sr.set_carry(value & 0b0000_0001 != 0);
+        self.sr.set_zero_negative(result);
+        result
+    }
+
+    fn increment(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_add(1);
+        self.sr.set_zero_negative(result);
+        result
+    }
+
+    fn decrement(&mut self, value: u8) -> u8 {
+        let result = value.wrapping_sub(1);
+        self.sr.set_zero_negative(result);
+        result
+    }
+
+    // BIT copies bits 7 and 6 of memory into N and V, and sets Z from A & M
+    fn bit_test(&mut self, value: u8) {
+        self.sr.set_zero(self.a & value == 0);
+        self.sr.set_overflow(value & 0b0100_0000 != 0);
+        self.sr.set_negative(value & 0b1000_0000 != 0);
+    }
+
+    // ARR: AND then ROR A, with flags coming from the adder that sits between the two.
+    // In decimal mode the NMOS part also applies a BCD fix up to the result.
+    fn and_rotate_right(&mut self, value: u8) {
+        let and = self.a & value;
+        let carry = self.sr.carry() as u8;
+        let result = and >> 1 | carry << 7;
+
+        if !self.decimal_mode() {
+            self.a = result;
+            self.sr.set_zero_negative(result);
+            self.sr.set_carry(result & 0b0100_0000 != 0);
+            self.sr.set_overflow(((result >> 6) ^ (result >> 5)) & 1 != 0);
+            return;
+        }
+
+        self.sr.set_negative(carry != 0);
+        self.sr.set_zero(result == 0);
+        self.sr.set_overflow((and ^ result) & 0b0100_0000 != 0);
+
+        let mut result = result;
+        let low = and & 0x0f;
+        if low + (low & 0x01) > 0x05 {
+            result = (result & 0xf0) | (result.wrapping_add(0x06) & 0x0f);
+        }
+
+        let high = and >> 4;
+        let carry = high + (high & 0x01) > 0x05;
+        if carry {
+            result = result.wrapping_add(0x60);
+        }
+        self.sr.set_carry(carry);
+        self.a = result;
+    }
+
+    // SHA/SHX/SHY/TAS store a register ANDed with the high byte of the base address plus one.
+    // When indexing crosses a page, the stored value also replaces the high byte of the address.
+    fn store_high_and(&mut self, mode: AddressingMode, operand: u32, value: u8) {
+        let index = if mode == AbsoluteX { self.x } else { self.y };
+        let (address, _) = self.effective_address(mode, operand);
+        let address = address as u16;
+        self.store_high_and_at(address.wrapping_sub(index as u16), address, value);
+    }
+
+    fn store_high_and_at(&mut self, base: u16, address: u16, value: u8) {
+        let value = value & ((base >> 8) as u8).wrapping_add(1);
+        let address = if (base ^ address) & 0xff00 != 0 {
+            (value as u16) << 8 | (address & 0x00ff)
+        } else {
+            address
+        };
+        self.write_byte(address, value);
+    }
+
+    // Register - M without storing the result. Carry is set when no borrow was needed.
+    fn compare(&mut self, register: u8, value: u8) {
+        let result = register.wrapping_sub(value);
+        self.sr.set_carry(register >= value);
+        self.sr.set_zero_negative(result);
+    }
+
+    // Execute a decoded instruction and return the cycles it took
+    pub fn handle_opcode(&mut self, instruction: Instruction) -> u64 {
+        let mode = instruction.mode;
+        let operand = instruction.operand;
+
+        // Base cycles come from the opcode table, penalties are added on top.
+        // A 65816 direct page that isn't page aligned costs a cycle to add its low byte.
+        let mut cycles = instruction.cycles as u64;
+        if mode.is_zero_page() && self.dp & 0x00ff != 0 {
+            cycles += 1;
+        }
+
+        // 16 bit registers of the 65816 take their own path
+        if let Some(extra) = self.handle_wide(instruction) {
+            return cycles + extra;
+        }
+
+        match instruction.mnemonic {
+            ///////////////////////////////////// Flag setters ///////////////////////////////////////
+
+            // Set the CARRY flag
+            SEC => self.sr.set_carry(true),
+            // Set the INTERRUPT DISABLE flag
+            SEI => self.sr.set_interrupt_disable(true),
+            // Set DECIMAL MODE flag
+            SED => self.sr.set_decimal(true),
+
+            ///////////////////////////////////// Flag clearers //////////////////////////////////////
+
+            // Clear the CARRY flag
+            CLC => self.sr.set_carry(false),
+            // Clear the OVERFLOW flag
+            CLV => self.sr.set_overflow(false),
+            // Clear the INTERRUPT DISABLE flag
+            CLI => self.sr.set_interrupt_disable(false),
+            // Clear the DECIMAL MODE flag
+            CLD => self.sr.set_decimal(false),
+
+            ////////////////////////////////////// Branches /////////////////////////////////////////
+
+            // Branch on result plus (N clear)
+            BPL => cycles += self.branch(!self.sr.negative(), operand),
+            // Branch on result minus (N set)
+            BMI => cycles += self.branch(self.sr.negative(), operand),
+            // Branch on overflow clear
+            BVC => cycles += self.branch(!self.sr.overflow(), operand),
+            // Branch on overflow set
+            BVS => cycles += self.branch(self.sr.overflow(), operand),
+            // Branch on carry clear
+            BCC => cycles += self.branch(!self.sr.carry(), operand),
+            // Branch on carry set
+            BCS => cycles += self.branch(self.sr.carry(), operand),
+            // Branch on result not zero
+            BNE => cycles += self.branch(!self.sr.zero(), operand),
+            // Branch on result zero
+            BEQ => cycles += self.branch(self.sr.zero(), operand),
+            // Branch always
+            BRA => cycles += self.branch(true, operand),
+
+            // Branch on bit reset/set of a zero page byte.
+            // The low byte of the operand is the address, the high byte the offset.
+            BBR(bit) | BBS(bit) => {
+                let value = self.read_byte_at_address(operand as u8 as u16);
+                let set = value & (1 << bit) != 0;
+                let condition = if matches!(instruction.mnemonic, BBS(_)) { set } else { !set };
+                cycles += self.branch(condition, operand >> 8);
+            }
+
+            //////////////////////////////////////// Loads //////////////////////////////////////////
+            LDA => {
+                let (value, extra) = self.read_operand(mode, operand);
+                self.a = value;
+                self.sr.set_zero_negative(value);
+                cycles += extra;
+            }
+            LDX => {
+                let (value, extra) = self.read_operand(mode, operand);
+                self.x = value;
+                self.sr.set_zero_negative(value);
+                cycles += extra;
+            }
+            LDY => {
+                let (value, extra) = self.read_operand(mode, operand);
+                self.y = value;
+                self.sr.set_zero_negative(value);
+                cycles += extra;
+            }
+
+            /////////////////////////////////////// Stores //////////////////////////////////////////
+            STA => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.write_long(address, self.a);
+            }
+            STX => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.write_long(address, self.x);
+            }
+            STY => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.write_long(address, self.y);
+            }
+            STZ => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.write_long(address, 0);
+            }
+
+            ////////////////////////////////// Stack operations /////////////////////////////////////
+
+            // Push the accumulator
+            PHA => self.push_byte(self.a),
+            // Push the status register. B and bit 5 only exist on the stack and are always set
+            PHP => self.push_byte(self.pushed_status()),
+            // Pull the accumulator
+            PLA => {
+                self.a = self.pull_byte();
+                self.sr.set_zero_negative(self.a);
+            }
+            // Push and pull X and Y
+            PHX => self.push_byte(self.x),
+            PHY => self.push_byte(self.y),
+            PLX => {
+                self.x = self.pull_byte();
+                self.sr.set_zero_negative(self.x);
+            }
+            PLY => {
+                self.y = self.pull_byte();
+                self.sr.set_zero_negative(self.y);
+            }
+            // Pull the status register, ignoring B and bit 5
+            PLP => {
+                let status = self.pull_byte();
+                self.load_status(status);
+            }
+            // Jump to subroutine. The return address pushed is the last byte of the JSR
+            JSR => {
+                self.push_word(self.pc.wrapping_sub(1));
+                self.pc = self.effective_address(mode, operand).0 as u16;
+            }
+            // Return from subroutine to the byte after the one JSR pushed
+            RTS => self.pc = self.pull_word().wrapping_add(1),
+
+            ///////////////////////////////////// Interrupts ////////////////////////////////////////
+
+            // Software interrupt. BRK is followed by a padding byte which is skipped on return
+            BRK => {
+                self.pc = self.pc.wrapping_add(1);
+                cycles += self.interrupt(Interrupt::Brk);
+            }
+            // Return from interrupt. Pulls SR (ignoring B and bit 5) then PC, no +1 unlike RTS.
+            // In 65816 native mode the program bank comes last.
+            RTI => {
+                let status = self.pull_byte();
+                self.load_status(status);
+                self.pc = self.pull_word();
+                if !self.emulation {
+                    self.pbr = self.pull_byte();
+                    cycles += 1;
+                }
+            }
+
+            ////////////////////////////// Handle Transfer cases ////////////////////////////////////
+            TAX => {
+                self.x = self.a;
+                self.sr.set_zero_negative(self.x);
+            }
+            TAY => {
+                self.y = self.a;
+                self.sr.set_zero_negative(self.y);
+            }
+            TXA => {
+                self.a = self.x;
+                self.sr.set_zero_negative(self.a);
+            }
+            TYA => {
+                self.a = self.y;
+                self.sr.set_zero_negative(self.a);
+            }
+            // The only transfer that leaves the flags alone
+            TXS => self.sp = self.x,
+            TSX => {
+                self.x = self.sp;
+                self.sr.set_zero_negative(self.x);
+            }
+
+            ////////////////////////////////////// Logic and arithmetic /////////////////////////////
+            ORA => {
+                let (value, extra) = self.read_operand(mode, operand);
+                self.a |= value;
+                self.sr.set_zero_negative(self.a);
+                cycles += extra;
+            }
+            AND => {
+                let (value, extra) = self.read_operand(mode, operand);
+                self.a &= value;
+                self.sr.set_zero_negative(self.a);
+                cycles += extra;
+            }
+            EOR => {
+                let (value, extra) = self.read_operand(mode, operand);
+                self.a ^= value;
+                self.sr.set_zero_negative(self.a);
+                cycles += extra;
+            }
+            // The 65C02 spends an extra cycle fixing up the flags in decimal mode
+            ADC => {
+                let (value, extra) = self.read_operand(mode, operand);
+                self.add_with_carry(value);
+                cycles += extra + (self.decimal_mode() && self.variant == Variant::Wdc65C02) as u64;
+            }
+            SBC => {
+                let (value, extra) = self.read_operand(mode, operand);
+                self.subtract_with_carry(value);
+                cycles += extra + (self.decimal_mode() && self.variant == Variant::Wdc65C02) as u64;
+            }
+            CMP => {
+                let (value, extra) = self.read_operand(mode, operand);
+                self.compare(self.a, value);
+                cycles += extra;
+            }
+            CPX => {
+                let (value, _) = self.read_operand(mode, operand);
+                self.compare(self.x, value);
+            }
+            CPY => {
+                let (value, _) = self.read_operand(mode, operand);
+                self.compare(self.y, value);
+            }
+            // BIT #imm on the 65C02 only sets Z, there is no memory to copy N and V from
+            BIT => {
+                let (value, extra) = self.read_operand(mode, operand);
+                if mode == Immediate {
+                    self.sr.set_zero(self.a & value == 0);
+                } else {
+                    self.bit_test(value);
+                }
+                cycles += extra;
+            }
+            // Test and set/reset the bits of A in memory. Z is set from A & M like BIT
+            TSB => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.read_modify_write(address, |cpu, value| {
+                    cpu.sr.set_zero(cpu.a & value == 0);
+                    value | cpu.a
+                });
+            }
+            TRB => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.read_modify_write(address, |cpu, value| {
+                    cpu.sr.set_zero(cpu.a & value == 0);
+                    value & !cpu.a
+                });
+            }
+            // Reset or set one bit of a zero page byte
+            RMB(bit) => {
+                let value = self.read_byte_at_address(operand as u16);
+                self.write_byte(operand as u16, value & !(1 << bit));
+            }
+            SMB(bit) => {
+                let value = self.read_byte_at_address(operand as u16);
+                self.write_byte(operand as u16, value | 1 << bit);
+            }
+
+            /////////////////////////// Shifts, rotates, increments /////////////////////////////////
+            ASL => cycles += self.modify(mode, operand, Self::shift_left),
+            LSR => cycles += self.modify(mode, operand, Self::shift_right),
+            ROL => cycles += self.modify(mode, operand, Self::rotate_left),
+            ROR => cycles += self.modify(mode, operand, Self::rotate_right),
+            INC => {
+                self.modify(mode, operand, Self::increment);
+            }
+            DEC => {
+                self.modify(mode, operand, Self::decrement);
+            }
+
+            INX => self.x = self.increment(self.x),
+            INY => self.y = self.increment(self.y),
+            DEX => self.x = self.decrement(self.x),
+            DEY => self.y = self.decrement(self.y),
+
+            ////////////////////////////////////// Jumps ////////////////////////////////////////////
+            JMP => self.pc = self.effective_address(mode, operand).0 as u16,
+
+            // The undocumented NOPs with an operand still read it, page crossing included
+            NOP => {
+                if mode != Implied {
+                    cycles += self.read_operand(mode, operand).1;
+                }
+            }
+
+            ///////////////////////////////////// 65816 only ////////////////////////////////////////
+            BRL | COP | JML | JSL | MVN | MVP | PEA | PEI | PER | PHB | PHD | PHK | PLB | PLD
+            | REP | RTL | SEP | TCD | TCS | TDC | TSC | TXY | TYX | WDM | XBA | XCE => {
+                cycles += self.handle_native(instruction);
+            }
+
+            ///////////////////////////////////// Low power /////////////////////////////////////////
+
+            // Wait for an interrupt
+            WAI => self.state = State::Waiting,
+            // Stop the clock until reset
+            STP => self.state = State::Stopped,
+
+            /////////////////////////////// Undocumented opcodes //////////////////////////////////
+
+            // Lock up the CPU until the next reset
+            JAM => {
+                self.pc = self.pc.wrapping_sub(1);
+                self.state = State::Jammed {
+                    address: self.pc,
+                    opcode: instruction.opcode,
+                };
+            }
+
+            // Read-modify-write followed by an ALU operation with the new memory value
+            SLO => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.a |= self.read_modify_write(address, Self::shift_left);
+                self.sr.set_zero_negative(self.a);
+            }
+            RLA => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.a &= self.read_modify_write(address, Self::rotate_left);
+                self.sr.set_zero_negative(self.a);
+            }
+            SRE => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.a ^= self.read_modify_write(address, Self::shift_right);
+                self.sr.set_zero_negative(self.a);
+            }
+            RRA => {
+                let (address, _) = self.effective_address(mode, operand);
+                let value = self.read_modify_write(address, Self::rotate_right);
+                self.add_with_carry(value);
+            }
+            DCP => {
+                let (address, _) = self.effective_address(mode, operand);
+                let value = self.read_modify_write(address, |_, value| value.wrapping_sub(1));
+                self.compare(self.a, value);
+            }
+            ISC => {
+                let (address, _) = self.effective_address(mode, operand);
+                let value = self.read_modify_write(address, |_, value| value.wrapping_add(1));
+                self.subtract_with_carry(value);
+            }
+
+            // Store A & X
+            SAX => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.write_long(address, self.a & self.x);
+            }
+
+            // Load A and X at once. The immediate form is unstable and mixes in A
+            LAX => {
+                let (value, extra) = self.read_operand(mode, operand);
+                let value = if mode == Immediate {
+                    (self.a | self.unstable_magic) & value
+                } else {
+                    value
+                };
+
+                self.a = value;
+                self.x = value;
+                self.sr.set_zero_negative(value);
+                cycles += extra;
+            }
+
+            // AND #imm, then copy N into C
+            ANC => {
+                self.a &= operand as u8;
+                self.sr.set_zero_negative(self.a);
+                self.sr.set_carry(self.sr.negative());
+            }
+            // AND #imm, then LSR A
+            ALR => {
+                let value = self.a & operand as u8;
+                self.a = self.shift_right(value);
+            }
+            ARR => self.and_rotate_right(operand as u8),
+
+            // A = (A | magic) & X & #imm
+            XAA => {
+                self.a = (self.a | self.unstable_magic) & self.x & operand as u8;
+                self.sr.set_zero_negative(self.a);
+            }
+            // A, X and SP = M & SP
+            LAS => {
+                let (value, extra) = self.read_operand(mode, operand);
+                let value = value & self.sp;
+
+                self.a = value;
+                self.x = value;
+                self.sp = value;
+                self.sr.set_zero_negative(value);
+                cycles += extra;
+            }
+            // X = (A & X) - #imm, setting flags like CMP
+            SBX => {
+                let value = self.a & self.x;
+                self.compare(value, operand as u8);
+                self.x = value.wrapping_sub(operand as u8);
+            }
+
+            SHA => self.store_high_and(mode, operand, self.a & self.x),
+            SHX => self.store_high_and(mode, operand, self.x),
+            SHY => self.store_high_and(mode, operand, self.y),
+            // SP = A & X, then store like SHA
+            TAS => {
+                self.sp = self.a & self.x;
+                self.store_high_and(mode, operand, self.sp);
+            }
+        }
+
+        cycles
+    }
+}
+
+mod test {
+    #[allow(unused)]
+    use super::*;
+    #[allow(unused)]
+    use crate::operators::{Mnemonic, NMOS_6502, WDC_65C02};
+
+    // Build an instruction from the NMOS opcode table
+    #[allow(unused)]
+    fn op(mnemonic: Mnemonic, mode: AddressingMode, operand: u32) -> Instruction {
+        Instruction::assemble(&NMOS_6502, mnemonic, mode, operand).unwrap()
+    }
+
+    // Build an instruction from the 65C02 opcode table
+    #[allow(unused)]
+    fn cmos(mnemonic: Mnemonic, mode: AddressingMode, operand: u32) -> Instruction {
+        Instruction::assemble(&WDC_65C02, mnemonic, mode, operand).unwrap()
+    }
+
+    #[test]
+    pub fn write_and_write_byte() {
+        let mut processor = Processor::new();
+        processor.pc = 0xff2f;
+        processor.write_byte(0xff2f, 0x33);
+        assert_eq!(processor.read_byte(), 0x33);
+    }
+
+    #[test]
+    pub fn write_and_read_word() {
+        let mut processor = Processor::new();
+
+        processor.pc = 0xff2f;
+        processor.write_word(0xff2f, 0xf6e4);
+        assert_eq!(processor.read_word(), 0xf6e4);
+    }
+
+    #[test]
+    pub fn test_lda() {
+        let mut processor = Processor::new();
+
+        // Write the value 0x30 into ff2f
+        processor.write_byte(0xff2f, 0x30);
+
+        // Write the program
+        processor
+            .write_program(&[0xad, 0x2f, 0xff, 0x4c, 0x03, 0x02])
+            .unwrap();
+        processor.reset();
+
+        // Execute
+        const MAX_CYCLES: u64 = 0xffff;
+        processor.execute(MAX_CYCLES).unwrap();
+
+        // Check A
+        assert_eq!(processor.a, 0x30);
+    }
+
+    #[test]
+    pub fn test_logical() {
+        let mut processor = Processor::new();
+
+        // LDA #$f0; ORA #$0f; AND #$3c; EOR #$3c; JMP *
+        processor
+            .write_program(&[
+                0xa9, 0xf0, 0x09, 0x0f, 0x29, 0x3c, 0x49, 0x3c, 0x4c, 0x08, 0x02,
+            ])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x00);
+        assert_eq!(processor.sr.bits() & 0b1000_0010, 0b0000_0010);
+    }
+
+    #[test]
+    pub fn test_adc() {
+        let mut processor = Processor::new();
+
+        // CLD; CLC; LDA #$50; ADC #$50; JMP *
+        processor
+            .write_program(&[0xd8, 0x18, 0xa9, 0x50, 0x69, 0x50, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        // Positive + positive = negative: overflow, no carry
+        assert_eq!(processor.a, 0xa0);
+        assert_eq!(processor.sr.bits() & 0b1100_0011, 0b1100_0000);
+
+        // CLD; SEC; LDA #$ff; ADC $0300; JMP * (with $0300 = $00)
+        let mut processor = Processor::new();
+        processor
+            .write_program(&[0xd8, 0x38, 0xa9, 0xff, 0x6d, 0x00, 0x03, 0x4c, 0x07, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x00);
+        assert_eq!(processor.sr.bits() & 0b1100_0011, 0b0000_0011);
+    }
+
+    #[test]
+    pub fn test_sbc() {
+        let mut processor = Processor::new();
+
+        // CLD; SEC; LDA #$50; SBC #$b0; JMP *
+        processor
+            .write_program(&[0xd8, 0x38, 0xa9, 0x50, 0xe9, 0xb0, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        // Positive - negative = negative: overflow, borrow
+        assert_eq!(processor.a, 0xa0);
+        assert_eq!(processor.sr.bits() & 0b1100_0011, 0b1100_0000);
+
+        // CLD; CLC; LDA #$05; SBC #$03; JMP *
+        let mut processor = Processor::new();
+        processor
+            .write_program(&[0xd8, 0x18, 0xa9, 0x05, 0xe9, 0x03, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x01);
+        assert_eq!(processor.sr.bits() & 0b1100_0011, 0b0000_0001);
+    }
+
+    #[test]
+    pub fn test_cmp() {
+        let mut processor = Processor::new();
+        processor.write_byte(0x0010, 0x40);
+        processor.write_word(0x0020, 0x0300);
+        processor.write_byte(0x0302, 0x41);
+
+        // LDA #$40; LDY #$02; CMP $10; JMP *
+        processor
+            .write_program(&[0xa9, 0x40, 0xa0, 0x02, 0xc5, 0x10, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.sr.bits() & 0b1000_0011, 0b0000_0011);
+
+        // LDA #$40; LDY #$02; CMP ($20),Y; JMP *
+        processor
+            .write_program(&[0xa9, 0x40, 0xa0, 0x02, 0xd1, 0x20, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.a, 0x40);
+        assert_eq!(processor.sr.bits() & 0b1000_0011, 0b1000_0000);
+    }
+
+    #[test]
+    pub fn test_decimal_adc() {
+        let mut processor = Processor::new();
+
+        // SED; CLC; LDA #$58; ADC #$46; JMP *
+        processor
+            .write_program(&[0xf8, 0x18, 0xa9, 0x58, 0x69, 0x46, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x04);
+        assert_eq!(processor.sr.bits() & 0b0000_0001, 0b0000_0001);
+
+        // SED; CLC; LDA #$99; ADC #$01; JMP *
+        // The NMOS part reports N from the half adjusted sum and Z from the binary sum
+        let mut processor = Processor::new();
+        processor
+            .write_program(&[0xf8, 0x18, 0xa9, 0x99, 0x69, 0x01, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x00);
+        assert_eq!(processor.sr.bits() & 0b1100_0011, 0b1000_0001);
+    }
+
+    #[test]
+    pub fn test_decimal_sbc() {
+        let mut processor = Processor::new();
+
+        // SED; SEC; LDA #$46; SBC #$12; JMP *
+        processor
+            .write_program(&[0xf8, 0x38, 0xa9, 0x46, 0xe9, 0x12, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x34);
+        assert_eq!(processor.sr.bits() & 0b0000_0001, 0b0000_0001);
+
+        // SED; SEC; LDA #$12; SBC #$21; JMP *
+        let mut processor = Processor::new();
+        processor
+            .write_program(&[0xf8, 0x38, 0xa9, 0x12, 0xe9, 0x21, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x91);
+        assert_eq!(processor.sr.bits() & 0b1100_1011, 0b1000_1000);
+    }
+
+    #[test]
+    pub fn test_branch_loop() {
+        let mut processor = Processor::new();
+
+        // CLD; LDA #$00; loop: CLC; ADC #$01; CMP #$05; BNE loop; JMP *
+        processor
+            .write_program(&[
+                0xd8, 0xa9, 0x00, 0x18, 0x69, 0x01, 0xc9, 0x05, 0xd0, 0xf9, 0x4c, 0x0a, 0x02,
+            ])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x05);
+    }
+
+    #[test]
+    pub fn test_branch_cycles() {
+        let mut processor = Processor::new();
+
+        // Not taken
+        processor.pc = 0x0210;
+        processor.sr = Status::from_bits(0b0000_0010);
+        assert_eq!(processor.handle_opcode(op(BNE, Relative, 0x10)), 2);
+        assert_eq!(processor.pc, 0x0210);
+
+        // Taken, same page
+        assert_eq!(processor.handle_opcode(op(BEQ, Relative, 0xf0)), 3);
+        assert_eq!(processor.pc, 0x0200);
+
+        // Taken, crossing into the previous page
+        assert_eq!(processor.handle_opcode(op(BEQ, Relative, 0xff)), 4);
+        assert_eq!(processor.pc, 0x01ff);
+    }
+
+    #[test]
+    pub fn test_subroutine() {
+        let mut processor = Processor::new();
+
+        // JSR sub; LDX #$01; BNE *; sub: LDA #$42; RTS
+        processor
+            .write_program(&[0x20, 0x07, 0x02, 0xa2, 0x01, 0xd0, 0xfe, 0xa9, 0x42, 0x60])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x42);
+        assert_eq!(processor.x, 0x01);
+        assert_eq!(processor.sp, 0xfd);
+
+        // The return address is the last byte of the JSR instruction
+        assert_eq!(processor.read_word_at_address(0x01fc), 0x0202);
+    }
+
+    #[test]
+    pub fn test_push_pull() {
+        let mut processor = Processor::new();
+
+        // LDA #$80; PHA; LDA #$01; PLA; BMI *
+        processor
+            .write_program(&[0xa9, 0x80, 0x48, 0xa9, 0x01, 0x68, 0x30, 0xfe])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x80);
+        assert_eq!(processor.sr.bits() & 0b1000_0010, 0b1000_0000);
+        assert_eq!(processor.sp, 0xfd);
+
+        // Pushing past 0x0100 wraps around to 0x01ff
+        processor.sp = 0x00;
+        processor.push_byte(0x12);
+        assert_eq!(processor.sp, 0xff);
+        assert_eq!(processor.pull_byte(), 0x12);
+        assert_eq!(processor.read_byte_at_address(0x0100), 0x12);
+    }
+
+    #[test]
+    pub fn test_php_plp() {
+        let mut processor = Processor::new();
+
+        // PHP; PLA; PHA; PLP; BCS *
+        processor
+            .write_program(&[0x08, 0x68, 0x48, 0x28, 0xb0, 0xfe])
+            .unwrap();
+        processor.reset();
+        processor.sr = Status::from_bits(0b1100_0011);
+        processor.execute(0xffff).unwrap();
+
+        // B and bit 5 are set in the pushed copy but never reach SR through PLP
+        assert_eq!(processor.a, 0b1111_0011);
+        assert_eq!(processor.sr.bits(), 0b1110_0011);
+    }
+
+    #[test]
+    pub fn test_reset() {
+        let mut processor = Processor::new();
+        processor.write_word(0xfffc, 0x1234);
+
+        assert_eq!(processor.reset(), 7);
+        assert_eq!(processor.pc, 0x1234);
+        assert_eq!(processor.sp, 0xfd);
+        assert_eq!(processor.sr.bits() & 0b0000_0100, 0b0000_0100);
+    }
+
+    #[test]
+    pub fn test_register_access() {
+        let mut processor = Processor::new();
+        processor.set_a(0x12);
+        processor.set_x(0x34);
+        processor.set_y(0x56);
+        processor.set_pc(0x0400);
+        processor.set_sp(0xf0);
+        assert_eq!(
+            (processor.a(), processor.x(), processor.y()),
+            (0x12, 0x34, 0x56)
+        );
+        assert_eq!((processor.pc(), processor.sp()), (0x0400, 0xf0));
+
+        // Flags by name. B isn't a real flag and doesn't stick
+        let mut status = processor.status();
+        status.set_carry(true);
+        status.set_interrupt_disable(false);
+        processor.set_status(status);
+        processor.set_status(Status::from_native_bits(
+            processor.status().bits() | Status::BREAK,
+        ));
+        assert!(processor.status().carry());
+        assert!(!processor.status().interrupt_disable());
+        assert_eq!(processor.status().bits() & Status::BREAK, 0);
+
+        // Snapshots go back and forth, the 6502 has no banks or direct page to move
+        let mut registers = processor.registers();
+        assert_eq!(registers.a, 0x12);
+        registers.dp = 0x1000;
+        registers.emulation = false;
+        processor.set_registers(registers);
+        assert_eq!(
+            processor.registers(),
+            Registers {
+                dp: 0x0000,
+                emulation: true,
+                ..registers
+            }
+        );
+
+        // The 65816 takes them all
+        let mut processor = Processor::with_variant(Variant::Wdc65C816);
+        let registers = Registers {
+            xh: 0x12,
+            sh: 0x1f,
+            dp: 0x1000,
+            dbr: 0x7e,
+            emulation: false,
+            sr: Status::from_native_bits(0x00),
+            ..processor.registers()
+        };
+        processor.set_registers(registers);
+        assert_eq!(processor.registers(), registers);
+
+        // Bulk copies, wrapping around at the end of memory
+        let mut processor = Processor::new();
+        processor.write_bytes(0xfffe, &[0x01, 0x02, 0x03]);
+        let mut buffer = [0; 4];
+        processor.peek_bytes(0xfffd, &mut buffer);
+        assert_eq!(buffer, [0x00, 0x01, 0x02, 0x03]);
+        assert_eq!(processor.peek(0x0000), 0x03);
+    }
+
+    #[test]
+    pub fn test_brk_rti() {
+        let mut processor = Processor::new();
+
+        // Handler at 0x0300: LDX #$07; RTI
+        processor.write_word(0xfffe, 0x0300);
+        processor.write_byte(0x0300, 0xa2);
+        processor.write_byte(0x0301, 0x07);
+        processor.write_byte(0x0302, 0x40);
+
+        // BRK; (padding); LDA #$01; BNE *
+        processor
+            .write_program(&[0x00, 0xff, 0xa9, 0x01, 0xd0, 0xfe])
+            .unwrap();
+        processor.reset();
+        processor.sr = Status::from_bits(0b0000_0000);
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.x, 0x07);
+        assert_eq!(processor.a, 0x01);
+        assert_eq!(processor.sp, 0xfd);
+
+        // BRK pushed the address after the padding byte and a status with B and bit 5 set
+        assert_eq!(processor.read_word_at_address(0x01fc), 0x0202);
+        assert_eq!(processor.read_byte_at_address(0x01fb), 0b0011_0000);
+
+        // RTI restored the interrupt disable flag
+        assert_eq!(processor.sr.bits() & 0b0000_0100, 0);
+    }
+
+    #[test]
+    pub fn test_irq() {
+        let mut processor = Processor::new();
+        processor.write_word(0xfffe, 0x0300);
+
+        // Handler at 0x0300: LDY #$09; BNE *
+        processor.write_byte(0x0300, 0xa0);
+        processor.write_byte(0x0301, 0x09);
+        processor.write_byte(0x0302, 0xd0);
+        processor.write_byte(0x0303, 0xfe);
+
+        // LDA #$01; BNE *
+        processor.write_program(&[0xa9, 0x01, 0xd0, 0xfe]).unwrap();
+        processor.reset();
+
+        // Interrupts are disabled after reset, so the IRQ is ignored
+        processor.set_irq(true);
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.pc, 0x0202);
+        assert_eq!(processor.y, 0x00);
+
+        // CLI lets it through. B is clear in the pushed status
+        processor.handle_opcode(op(CLI, Implied, 0));
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.y, 0x09);
+        assert_eq!(processor.pc, 0x0302);
+        assert_eq!(processor.read_word_at_address(0x01fc), 0x0202);
+        assert_eq!(
+            processor.read_byte_at_address(0x01fb) & 0b0011_0000,
+            0b0010_0000
+        );
+        assert_eq!(processor.sr.bits() & 0b0000_0100, 0b0000_0100);
+    }
+
+    #[test]
+    pub fn test_nmi_edge() {
+        let mut processor = Processor::new();
+        processor.write_word(0xfffa, 0x0300);
+
+        // Handler at 0x0300: TXA; CLC; ADC #$01; TAX; RTI
+        for (i, byte) in [0x8a, 0x18, 0x69, 0x01, 0xaa, 0x40].iter().enumerate() {
+            processor.write_byte(0x0300 + i as u16, *byte);
+        }
+
+        // LDA #$01; BNE *
+        processor.write_program(&[0xa9, 0x01, 0xd0, 0xfe]).unwrap();
+        processor.reset();
+        processor.handle_opcode(op(CLD, Implied, 0));
+
+        // NMI ignores the I flag, and holding the line only triggers once
+        processor.set_nmi(true);
+        processor.execute(0xffff).unwrap();
+        processor.set_nmi(true);
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.x, 0x01);
+
+        // A new edge triggers again
+        processor.set_nmi(false);
+        processor.set_nmi(true);
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.x, 0x02);
+        assert_eq!(processor.pc, 0x0202);
+    }
+
+    #[test]
+    pub fn test_shifts() {
+        let mut processor = Processor::new();
+
+        // LDA #$81; ASL A; JMP *
+        processor
+            .write_program(&[0xa9, 0x81, 0x0a, 0x4c, 0x03, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.a, 0x02);
+        assert_eq!(processor.sr.bits() & 0b1000_0011, 0b0000_0001);
+
+        // LDA #$01; LSR A; JMP *
+        processor
+            .write_program(&[0xa9, 0x01, 0x4a, 0x4c, 0x03, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.a, 0x00);
+        assert_eq!(processor.sr.bits() & 0b1000_0011, 0b0000_0011);
+
+        // SEC; LDA #$40; ROL A; ROR A; ROR A; JMP *
+        processor
+            .write_program(&[0x38, 0xa9, 0x40, 0x2a, 0x6a, 0x6a, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.a, 0xa0);
+        assert_eq!(processor.sr.bits() & 0b1000_0011, 0b1000_0000);
+    }
+
+    #[test]
+    pub fn test_memory_rmw() {
+        let mut processor = Processor::new();
+        processor.write_byte(0x0010, 0x80);
+        processor.write_byte(0x0015, 0xff);
+        processor.write_byte(0x0400, 0x01);
+        processor.write_byte(0x0405, 0x00);
+
+        // LDX #$05; ASL $10; INC $10,X; ROR $0400; DEC $0400,X; JMP *
+        processor
+            .write_program(&[
+                0xa2, 0x05, 0x06, 0x10, 0xf6, 0x10, 0x6e, 0x00, 0x04, 0xde, 0x00, 0x04, 0x4c, 0x0c,
+                0x02,
+            ])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.read_byte_at_address(0x0010), 0x00);
+        assert_eq!(processor.read_byte_at_address(0x0015), 0x00);
+        assert_eq!(processor.read_byte_at_address(0x0400), 0x80);
+        assert_eq!(processor.read_byte_at_address(0x0405), 0xff);
+        assert_eq!(processor.sr.bits() & 0b1000_0011, 0b1000_0001);
+    }
+
+    #[test]
+    pub fn test_register_increments() {
+        let mut processor = Processor::new();
+
+        // LDX #$ff; INX; LDY #$00; DEY; DEY; JMP *
+        processor
+            .write_program(&[0xa2, 0xff, 0xe8, 0xa0, 0x00, 0x88, 0x88, 0x4c, 0x07, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.x, 0x00);
+        assert_eq!(processor.y, 0xfe);
+        assert_eq!(processor.sr.bits() & 0b1000_0010, 0b1000_0000);
+    }
+
+    #[test]
+    pub fn test_jmp() {
+        let mut processor = Processor::new();
+
+        // JMP $0210; ...; $0210: LDA #$01; JMP $0215 (jumping to itself)
+        processor.write_program(&[0x4c, 0x10, 0x02]).unwrap();
+        for (i, byte) in [0xa9, 0x01, 0x4c, 0x12, 0x02].iter().enumerate() {
+            processor.write_byte(0x0210 + i as u16, *byte);
+        }
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x01);
+        assert_eq!(processor.pc, 0x0212);
+    }
+
+    #[test]
+    pub fn test_jmp_indirect_page_bug() {
+        let mut processor = Processor::new();
+        processor.write_byte(0x02ff, 0x34);
+        processor.write_byte(0x0300, 0x56);
+        processor.write_byte(0x0200, 0x12);
+
+        // JMP ($02ff) reads the high byte from $0200 instead of $0300
+        assert_eq!(processor.handle_opcode(op(JMP, Indirect, 0x02ff)), 5);
+        assert_eq!(processor.pc, 0x1234);
+
+        // Pointers that don't sit on a page boundary are unaffected
+        processor.write_byte(0x02fe, 0x78);
+        processor.handle_opcode(op(JMP, Indirect, 0x02fe));
+        assert_eq!(processor.pc, 0x3478);
+    }
+
+    #[test]
+    pub fn test_bit() {
+        let mut processor = Processor::new();
+        processor.write_byte(0x0010, 0b1100_0000);
+        processor.write_byte(0x0300, 0b0000_0001);
+
+        processor.a = 0b0000_0001;
+        processor.handle_opcode(op(BIT, ZeroPage, 0x10));
+        assert_eq!(processor.sr.bits() & 0b1100_0010, 0b1100_0010);
+
+        processor.handle_opcode(op(BIT, Absolute, 0x0300));
+        assert_eq!(processor.sr.bits() & 0b1100_0010, 0b0000_0000);
+        assert_eq!(processor.a, 0b0000_0001);
+    }
+
+    #[test]
+    pub fn test_cpx_cpy() {
+        let mut processor = Processor::new();
+        processor.write_byte(0x0010, 0x20);
+
+        processor.x = 0x10;
+        processor.handle_opcode(op(CPX, ZeroPage, 0x10));
+        assert_eq!(processor.sr.bits() & 0b1000_0011, 0b1000_0000);
+
+        processor.y = 0x20;
+        processor.handle_opcode(op(CPY, Immediate, 0x20));
+        assert_eq!(processor.sr.bits() & 0b1000_0011, 0b0000_0011);
+
+        processor.handle_opcode(op(CPY, Immediate, 0x01));
+        assert_eq!(processor.sr.bits() & 0b1000_0011, 0b0000_0001);
+    }
+
+    #[test]
+    pub fn test_load_clears_flags() {
+        let mut processor = Processor::new();
+        assert_eq!(processor.sr.bits(), 0b0010_0100);
+
+        // LDA #$00; LDX #$80; TXA; LDY #$01; TYA; JMP *
+        processor
+            .write_program(&[
+                0xa9, 0x00, 0xa2, 0x80, 0x8a, 0xa0, 0x01, 0x98, 0x4c, 0x08, 0x02,
+            ])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        // Z from the first load and N from the transfer have both been cleared again
+        assert_eq!(processor.a, 0x01);
+        assert!(!processor.sr.zero());
+        assert!(!processor.sr.negative());
+    }
+
+    #[test]
+    pub fn test_address_wrap_around() {
+        let mut processor = Processor::new();
+
+        // The last byte of memory is addressable
+        processor.write_word(0xfffe, 0xbeef);
+        assert_eq!(processor.read_byte_at_address(0xffff), 0xbe);
+
+        // Words and PC wrap around to the zero page
+        processor.write_byte(0x0000, 0x12);
+        assert_eq!(processor.read_word_at_address(0xffff), 0x12be);
+
+        processor.pc = 0xffff;
+        assert_eq!(processor.read_word(), 0x12be);
+        assert_eq!(processor.pc, 0x0001);
+
+        // Absolute indexed addressing wraps around too
+        processor.x = 0x01;
+        processor.handle_opcode(op(LDA, AbsoluteX, 0xffff));
+        assert_eq!(processor.a, 0x12);
+
+        processor.y = 0x02;
+        processor.a = 0x34;
+        processor.handle_opcode(op(STA, AbsoluteY, 0xffff));
+        assert_eq!(processor.read_byte_at_address(0x0001), 0x34);
+    }
+
+    #[test]
+    pub fn test_zero_page_wrap_around() {
+        let mut processor = Processor::new();
+        processor.write_byte(0x0000, 0x11);
+        processor.write_byte(0x00ff, 0x22);
+        processor.write_byte(0x0100, 0x33);
+
+        // $ff,X with X = 1 stays in page zero
+        processor.x = 0x01;
+        assert_eq!(processor.handle_opcode(op(LDA, ZeroPageX, 0xff)), 4);
+        assert_eq!(processor.a, 0x11);
+
+        // $ff is $ff, not $00
+        processor.handle_opcode(op(LDA, ZeroPage, 0xff));
+        assert_eq!(processor.a, 0x22);
+
+        // ($ff),Y takes the pointer from $ff and $00: $1122
+        processor.write_byte(0x1123, 0x44);
+        processor.y = 0x01;
+        processor.handle_opcode(op(LDA, IndirectY, 0xff));
+        assert_eq!(processor.a, 0x44);
+
+        // ($fe,X) with X = 1 uses the same pointer
+        processor.a = 0x55;
+        processor.handle_opcode(op(STA, IndirectX, 0xfe));
+        assert_eq!(processor.read_byte_at_address(0x1122), 0x55);
+
+        // $ff,Y for LDX
+        processor.handle_opcode(op(LDX, ZeroPageY, 0xff));
+        assert_eq!(processor.x, 0x11);
+    }
+
+    #[test]
+    pub fn test_page_cross_cycles() {
+        let mut processor = Processor::new();
+        processor.x = 0x01;
+        processor.y = 0x01;
+        processor.write_word(0x0010, 0x02ff);
+
+        // Reads pay a cycle when indexing crosses a page
+        assert_eq!(processor.handle_opcode(op(LDA, AbsoluteX, 0x02fe)), 4);
+        assert_eq!(processor.handle_opcode(op(LDA, AbsoluteX, 0x02ff)), 5);
+        assert_eq!(processor.handle_opcode(op(ADC, AbsoluteY, 0x02ff)), 5);
+        assert_eq!(processor.handle_opcode(op(CMP, IndirectY, 0x10)), 6);
+        assert_eq!(processor.handle_opcode(op(LDY, AbsoluteX, 0x02ff)), 5);
+
+        // Zero page indexing never crosses, it wraps
+        assert_eq!(processor.handle_opcode(op(LDA, ZeroPageX, 0xff)), 4);
+    }
+
+    #[test]
+    pub fn test_breakpoints() {
+        let mut processor = Processor::new();
+
+        // LDX #$00; loop: INX; CPX #$03; BNE loop; JMP *
+        processor
+            .write_program(&[0xa2, 0x00, 0xe8, 0xe0, 0x03, 0xd0, 0xfb, 0x4c, 0x07, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.add_breakpoint(0x0202);
+
+        // Stops in front of INX every time around, running again resumes from there
+        for x in 0..3 {
+            let result = processor.execute(0xffff);
+            assert_eq!(result, Err(CpuError::Breakpoint { address: 0x0202 }));
+            assert_eq!(processor.x, x);
+        }
+
+        processor.remove_breakpoint(0x0202);
+        assert!(processor.execute(0xffff).is_ok());
+        assert_eq!(processor.x, 3);
+    }
+
+    #[test]
+    pub fn test_execute_errors() {
+        let mut processor = Processor::new();
+
+        // Only $0200-$fffb is free for programs
+        let result = processor.write_program(&[0xea; 0xfe00]);
+        assert_eq!(result, Err(CpuError::ProgramTooLarge { size: 0xfe00 }));
+        assert_eq!(processor.peek(0x0200), 0x00);
+
+        // NOP; JMP $0200 never stops by itself
+        processor.write_program(&[0xea, 0x4c, 0x00, 0x02]).unwrap();
+        processor.reset();
+        let result = processor.execute(100);
+        assert_eq!(result, Err(CpuError::CycleLimit { cycles: 100 }));
+        assert_eq!(
+            result.unwrap_err().to_string(),
+            "still running after 100 cycles"
+        );
+
+        // One instruction at a time
+        assert_eq!(processor.step().map(|report| report.cycles), Ok(2));
+        assert_eq!(processor.step().map(|report| report.cycles), Ok(3));
+        assert_eq!(processor.pc, 0x0200);
+    }
+
+    #[test]
+    pub fn test_undocumented_opcode_stops() {
+        let mut processor = Processor::new();
+
+        // LDA #$01; SLO $10 (undocumented)
+        processor.write_program(&[0xa9, 0x01, 0x07, 0x10]).unwrap();
+        processor.reset();
+
+        // Execution stops in front of it instead of panicking
+        assert_eq!(
+            processor.execute(0xffff),
+            Err(CpuError::IllegalOpcode {
+                address: 0x0202,
+                opcode: 0x07
+            })
+        );
+        assert_eq!(processor.a, 0x01);
+        assert_eq!(processor.pc, 0x0202);
+    }
+
+    #[test]
+    pub fn test_undocumented_opcodes() {
+        let mut processor = Processor::new();
+        processor.set_undocumented_opcodes(true);
+        processor.write_byte(0x0010, 0x81);
+        processor.write_byte(0x0011, 0x42);
+
+        // CLD; LDA #$01; SLO $10; LAX $11; SAX $12; DCP $11; JMP *
+        processor
+            .write_program(&[
+                0xd8, 0xa9, 0x01, 0x07, 0x10, 0xa7, 0x11, 0x87, 0x12, 0xc7, 0x11, 0x4c, 0x0b, 0x02,
+            ])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        // SLO: $10 = $02, A = $01 | $02
+        assert_eq!(processor.read_byte_at_address(0x0010), 0x02);
+
+        // LAX loaded $42 into both A and X, SAX stored A & X
+        assert_eq!(processor.a, 0x42);
+        assert_eq!(processor.x, 0x42);
+        assert_eq!(processor.read_byte_at_address(0x0012), 0x42);
+
+        // DCP: $11 = $41, then CMP #$41 with A = $42
+        assert_eq!(processor.read_byte_at_address(0x0011), 0x41);
+        assert_eq!(processor.sr.bits() & 0b1000_0011, 0b0000_0001);
+    }
+
+    #[test]
+    pub fn test_undocumented_immediates() {
+        let mut processor = Processor::new();
+
+        processor.a = 0xff;
+        processor.handle_opcode(op(ANC, Immediate, 0x80));
+        assert!(processor.sr.carry());
+
+        processor.a = 0x03;
+        processor.handle_opcode(op(ALR, Immediate, 0x01));
+        assert_eq!(processor.a, 0x00);
+        assert!(processor.sr.carry() && processor.sr.zero());
+
+        // ARR: C from bit 6, V from bit 6 ^ bit 5
+        processor.a = 0xff;
+        processor.sr.set_carry(true);
+        processor.handle_opcode(op(ARR, Immediate, 0xc0));
+        assert_eq!(processor.a, 0xe0);
+        assert!(processor.sr.carry() && !processor.sr.overflow());
+
+        processor.a = 0x0f;
+        processor.x = 0x3c;
+        processor.handle_opcode(op(SBX, Immediate, 0x02));
+        assert_eq!(processor.x, 0x0a);
+        assert!(processor.sr.carry());
+
+        // Unstable opcodes use the magic constant
+        processor.set_unstable_magic(0x00);
+        processor.a = 0xf0;
+        processor.x = 0x3c;
+        processor.handle_opcode(op(XAA, Immediate, 0xff));
+        assert_eq!(processor.a, 0x30);
+
+        processor.set_unstable_magic(0xff);
+        processor.a = 0x00;
+        processor.handle_opcode(op(LAX, Immediate, 0x5a));
+        assert_eq!(processor.a, 0x5a);
+        assert_eq!(processor.x, 0x5a);
+    }
+
+    #[test]
+    pub fn test_undocumented_high_byte_stores() {
+        let mut processor = Processor::new();
+
+        // SHX $0210,Y stores X & ($02 + 1)
+        processor.x = 0xff;
+        processor.y = 0x01;
+        processor.handle_opcode(op(SHX, AbsoluteY, 0x0210));
+        assert_eq!(processor.read_byte_at_address(0x0211), 0x03);
+
+        // Crossing a page replaces the high byte of the address with the value
+        processor.x = 0x01;
+        processor.y = 0x20;
+        processor.handle_opcode(op(SHX, AbsoluteY, 0x02f0));
+        assert_eq!(processor.read_byte_at_address(0x0110), 0x01);
+        assert_eq!(processor.read_byte_at_address(0x0310), 0x00);
+
+        // TAS also moves A & X into SP
+        processor.a = 0x0f;
+        processor.x = 0x3c;
+        processor.y = 0x00;
+        processor.handle_opcode(op(TAS, AbsoluteY, 0x0400));
+        assert_eq!(processor.sp, 0x0c);
+        assert_eq!(processor.read_byte_at_address(0x0400), 0x04);
+    }
+
+    #[test]
+    pub fn test_jam() {
+        let mut processor = Processor::new();
+        processor.set_undocumented_opcodes(true);
+
+        // LDA #$01; JAM; LDA #$02
+        processor
+            .write_program(&[0xa9, 0x01, 0x02, 0xa9, 0x02])
+            .unwrap();
+        processor.reset();
+        let jammed = CpuError::Jammed {
+            address: 0x0202,
+            opcode: 0x02,
+        };
+        assert_eq!(processor.execute(0xffff), Err(jammed.clone()));
+
+        assert_eq!(processor.a, 0x01);
+        assert_eq!(
+            processor.state(),
+            State::Jammed {
+                address: 0x0202,
+                opcode: 0x02
+            }
+        );
+
+        // Interrupts don't get it going again, reset does
+        processor.set_nmi(true);
+        assert_eq!(processor.step(), Err(jammed));
+        assert_eq!(processor.pc, 0x0202);
+
+        processor.reset();
+        assert_eq!(processor.state(), State::Running);
+    }
+
+    #[test]
+    pub fn test_65c02_stack_and_store_zero() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C02);
+
+        // LDX #$12; LDY #$34; PHX; PHY; PLX; PLY; LDA #$ff; STA $10; STZ $10;
+        // INC A; DEC A; BRA *+2; JMP *
+        processor
+            .write_program(&[
+                0xa2, 0x12, 0xa0, 0x34, 0xda, 0x5a, 0xfa, 0x7a, 0xa9, 0xff, 0x85, 0x10, 0x64, 0x10,
+                0x1a, 0x3a, 0x80, 0x00, 0x4c, 0x12, 0x02,
+            ])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.x, 0x34);
+        assert_eq!(processor.y, 0x12);
+        assert_eq!(processor.a, 0xff);
+        assert_eq!(processor.read_byte_at_address(0x0010), 0x00);
+    }
+
+    #[test]
+    pub fn test_65c02_test_and_set_bits() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C02);
+        processor.write_byte(0x0010, 0x30);
+
+        // TSB sets the bits of A, Z tells whether any of them were set before
+        processor.a = 0x0f;
+        processor.handle_opcode(cmos(TSB, ZeroPage, 0x10));
+        assert_eq!(processor.read_byte_at_address(0x0010), 0x3f);
+        assert!(processor.sr.zero());
+
+        processor.a = 0x03;
+        processor.handle_opcode(cmos(TRB, ZeroPage, 0x10));
+        assert_eq!(processor.read_byte_at_address(0x0010), 0x3c);
+        assert!(!processor.sr.zero());
+    }
+
+    #[test]
+    pub fn test_65c02_indirect_modes() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C02);
+        processor.write_word(0x0020, 0x0300);
+        processor.write_byte(0x0300, 0x42);
+
+        // LDA ($20) and STA ($20) without any index
+        processor.handle_opcode(cmos(LDA, ZeroPageIndirect, 0x20));
+        assert_eq!(processor.a, 0x42);
+        processor.a = 0x55;
+        processor.handle_opcode(cmos(STA, ZeroPageIndirect, 0x20));
+        assert_eq!(processor.read_byte_at_address(0x0300), 0x55);
+
+        // JMP ($0400,X)
+        processor.x = 0x02;
+        processor.write_word(0x0402, 0x1234);
+        assert_eq!(processor.handle_opcode(cmos(JMP, AbsoluteIndexedIndirect, 0x0400)), 6);
+        assert_eq!(processor.pc, 0x1234);
+
+        // JMP ($02ff) takes the high byte from $0300, the NMOS bug is fixed
+        processor.write_byte(0x02ff, 0x34);
+        processor.write_byte(0x0300, 0x56);
+        assert_eq!(processor.handle_opcode(cmos(JMP, Indirect, 0x02ff)), 6);
+        assert_eq!(processor.pc, 0x5634);
+    }
+
+    #[test]
+    pub fn test_65c02_bit() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C02);
+
+        // BIT #imm only changes Z
+        processor.a = 0x01;
+        processor.sr = Status::from_bits(0b1100_0000);
+        processor.handle_opcode(cmos(BIT, Immediate, 0x80));
+        assert_eq!(processor.sr.bits() & 0b1100_0010, 0b1100_0010);
+
+        // BIT $nn,X behaves like the other forms
+        processor.x = 0x01;
+        processor.write_byte(0x0011, 0x41);
+        processor.handle_opcode(cmos(BIT, ZeroPageX, 0x10));
+        assert_eq!(processor.sr.bits() & 0b1100_0010, 0b0100_0000);
+    }
+
+    #[test]
+    pub fn test_65c02_bit_operations() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C02);
+
+        // SMB3 $10; BBS3 $10,*+5; LDA #$01; RMB3 $10; BBR3 $10,*+5; LDA #$02; JMP *
+        processor
+            .write_program(&[
+                0xb7, 0x10, 0xbf, 0x10, 0x02, 0xa9, 0x01, 0x37, 0x10, 0x3f, 0x10, 0x02, 0xa9, 0x02,
+                0x4c, 0x0e, 0x02,
+            ])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        // Both branches were taken
+        assert_eq!(processor.a, 0x00);
+        assert_eq!(processor.read_byte_at_address(0x0010), 0x00);
+
+        // Not taken, then taken
+        processor.pc = 0x0210;
+        assert_eq!(processor.handle_opcode(cmos(BBS(0), ZeroPageRelative, 0x1010)), 5);
+        assert_eq!(processor.handle_opcode(cmos(BBR(0), ZeroPageRelative, 0x1010)), 6);
+        assert_eq!(processor.pc, 0x0220);
+    }
+
+    #[test]
+    pub fn test_65c02_wai_stp() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C02);
+
+        // SEI; WAI; LDA #$01; STP; LDA #$02
+        processor
+            .write_program(&[0x78, 0xcb, 0xa9, 0x01, 0xdb, 0xa9, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.state(), State::Waiting);
+
+        // Nothing happens until an interrupt line is asserted
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.state(), State::Waiting);
+
+        // A masked IRQ wakes the CPU up without being serviced
+        processor.set_irq(true);
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.a, 0x01);
+        assert_eq!(processor.state(), State::Stopped);
+
+        processor.reset();
+        assert_eq!(processor.state(), State::Running);
+    }
+
+    #[test]
+    pub fn test_65c02_decimal() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C02);
+
+        // SED; CLC; LDA #$99; ADC #$01; JMP *
+        // Unlike the NMOS part, N and Z reflect the decimal result
+        processor
+            .write_program(&[0xf8, 0x18, 0xa9, 0x99, 0x69, 0x01, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x00);
+        assert_eq!(processor.sr.bits() & 0b1000_0011, 0b0000_0011);
+
+        // BRK clears decimal mode
+        assert!(processor.sr.decimal());
+        processor.handle_opcode(cmos(BRK, Implied, 0));
+        assert!(!processor.sr.decimal());
+
+        // Decimal mode costs an extra cycle
+        processor.sr.set_decimal(true);
+        processor.sr.set_carry(true);
+        processor.a = 0x00;
+        assert_eq!(processor.handle_opcode(cmos(SBC, Immediate, 0x01)), 3);
+        assert_eq!(processor.a, 0x99);
+        assert_eq!(processor.sr.bits() & 0b1000_0011, 0b1000_0000);
+    }
+
+    #[test]
+    pub fn test_65c02_undefined_opcodes() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C02);
+
+        // Undefined opcodes of 1, 2 and 3 bytes followed by LDA #$07; JMP *
+        processor
+            .write_program(&[
+                0x03, 0x02, 0xff, 0x5c, 0x00, 0x00, 0xdc, 0x00, 0x00, 0xa9, 0x07, 0x4c, 0x0b, 0x02,
+            ])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x07);
+        assert_eq!(processor.state(), State::Running);
+
+        let instruction = Instruction::decode(&WDC_65C02, &[0x5c, 0x00, 0x00]).unwrap();
+        assert_eq!(processor.handle_opcode(instruction), 8);
+        let instruction = Instruction::decode(&WDC_65C02, &[0x03]).unwrap();
+        assert_eq!(processor.handle_opcode(instruction), 1);
+    }
+
+    #[test]
+    pub fn test_65c02_shift_cycles() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C02);
+
+        // ASL abs,X only takes the extra cycle when crossing a page
+        processor.x = 0x01;
+        assert_eq!(processor.handle_opcode(cmos(ASL, AbsoluteX, 0x0300)), 6);
+        assert_eq!(processor.handle_opcode(cmos(ASL, AbsoluteX, 0x03ff)), 7);
+
+        // INC abs,X always takes 7
+        assert_eq!(processor.handle_opcode(cmos(INC, AbsoluteX, 0x0300)), 7);
+    }
+
+    #[test]
+    pub fn test_opcode_cycles() {
+        // Opcodes paying a cycle when indexing crosses a page, from the data sheets
+        // (the 65816 ones in emulation mode)
+        #[rustfmt::skip]
+        let cases: [(Variant, &[u8]); 3] = [
+            (Variant::Nmos6502, &[
+                0x11, 0x19, 0x1c, 0x1d, 0x31, 0x39, 0x3c, 0x3d, 0x51, 0x59, 0x5c, 0x5d, 0x71, 0x79,
+                0x7c, 0x7d, 0xb1, 0xb3, 0xb9, 0xbb, 0xbc, 0xbd, 0xbe, 0xbf, 0xd1, 0xd9, 0xdc, 0xdd,
+                0xf1, 0xf9, 0xfc, 0xfd,
+            ]),
+            (Variant::Wdc65C02, &[
+                0x11, 0x19, 0x1d, 0x1e, 0x31, 0x39, 0x3c, 0x3d, 0x3e, 0x51, 0x59, 0x5d, 0x5e, 0x71,
+                0x79, 0x7d, 0x7e, 0xb1, 0xb9, 0xbc, 0xbd, 0xbe, 0xd1, 0xd9, 0xdd, 0xf1, 0xf9, 0xfd,
+            ]),
+            (Variant::Wdc65C816, &[
+                0x11, 0x19, 0x1d, 0x31, 0x39, 0x3c, 0x3d, 0x51, 0x59, 0x5d, 0x71, 0x79, 0x7d, 0xb1,
+                0xb9, 0xbc, 0xbd, 0xbe, 0xd1, 0xd9, 0xdd, 0xf1, 0xf9, 0xfd,
+            ]),
+        ];
+
+        for (variant, page_crossing) in cases {
+            for (opcode, entry) in variant.opcodes().iter().enumerate() {
+                // Branches are covered by test_branch_cycles
+                if matches!(entry.mode, Relative | ZeroPageRelative) {
+                    continue;
+                }
+
+                // Every operand and pointer is $1210, indexing by $f0 crosses into $13xx
+                let cycles = |index: u8| {
+                    let mut processor = Processor::with_variant(variant);
+                    processor.set_undocumented_opcodes(true);
+                    processor.write_word(0x0010, 0x1210);
+                    processor.x = index;
+                    processor.y = index;
+
+                    let operand = [0, 0x10, 0x1210, 0x001210][entry.mode.operand_bytes() as usize];
+                    processor.handle_opcode(Instruction::new(opcode as u8, *entry, operand))
+                };
+
+                let name = format!("{:?} {:02x} {}", variant, opcode, entry.mnemonic);
+                let penalty = page_crossing.contains(&(opcode as u8)) as u64;
+                assert_eq!(cycles(0x00), entry.cycles as u64, "{}", name);
+                assert_eq!(cycles(0xf0), entry.cycles as u64 + penalty, "{} crossing", name);
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_2a03_matches_nmos() {
+        let programs: [&[u8]; 4] = [
+            // CLD; CLC; LDA #$50; ADC #$50; SBC #$10; JMP *
+            &[
+                0xd8, 0x18, 0xa9, 0x50, 0x69, 0x50, 0xe9, 0x10, 0x4c, 0x08, 0x02,
+            ],
+            // CLD; LDA #$00; loop: CLC; ADC #$01; CMP #$05; BNE loop; JMP *
+            &[
+                0xd8, 0xa9, 0x00, 0x18, 0x69, 0x01, 0xc9, 0x05, 0xd0, 0xf9, 0x4c, 0x0a, 0x02,
+            ],
+            // LDX #$80; STX $10; ASL $10; ROL $10; LDA #$37; PHA; LDY #$00; PLA; TAX; JMP *
+            &[
+                0xa2, 0x80, 0x86, 0x10, 0x06, 0x10, 0x26, 0x10, 0xa9, 0x37, 0x48, 0xa0, 0x00, 0x68,
+                0xaa, 0x4c, 0x0f, 0x02,
+            ],
+            // SED; LDA #$ff; AND #$0f; CLD; BIT $10; JMP *
+            &[
+                0xf8, 0xa9, 0xff, 0x29, 0x0f, 0xd8, 0x24, 0x10, 0x4c, 0x08, 0x02,
+            ],
+        ];
+
+        for program in programs {
+            let mut nmos = Processor::new();
+            let mut ricoh = Processor::with_variant(Variant::Ricoh2A03);
+
+            for processor in [&mut nmos, &mut ricoh] {
+                processor.write_program(program).unwrap();
+                processor.reset();
+                processor.execute(0xffff).unwrap();
+            }
+
+            assert_eq!((nmos.a, nmos.x, nmos.y), (ricoh.a, ricoh.x, ricoh.y));
+            assert_eq!((nmos.pc, nmos.sp, nmos.sr), (ricoh.pc, ricoh.sp, ricoh.sr));
+            assert_eq!(nmos.bus.bytes()[..0x0200], ricoh.bus.bytes()[..0x0200]);
+        }
+    }
+
+    #[test]
+    pub fn test_2a03_ignores_decimal() {
+        let mut processor = Processor::with_variant(Variant::Ricoh2A03);
+
+        // SED; CLC; LDA #$58; ADC #$46; JMP *
+        // The NMOS part gives $04 with carry, the 2A03 adds in binary
+        processor
+            .write_program(&[0xf8, 0x18, 0xa9, 0x58, 0x69, 0x46, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0x9e);
+        assert_eq!(processor.sr.bits() & 0b1100_1011, 0b1100_1000);
+
+        // SED; SEC; LDA #$12; SBC #$21; JMP *
+        let mut processor = Processor::with_variant(Variant::Ricoh2A03);
+        processor
+            .write_program(&[0xf8, 0x38, 0xa9, 0x12, 0xe9, 0x21, 0x4c, 0x06, 0x02])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.a, 0xf1);
+        assert!(processor.sr.decimal());
+
+        // ARR skips the BCD fix up as well
+        processor.a = 0xff;
+        processor.sr.set_carry(false);
+        processor.handle_opcode(op(ARR, Immediate, 0xff));
+        assert_eq!(processor.a, 0x7f);
+    }
+
+    #[test]
+    pub fn test_6510_io_port() {
+        let mut processor = Processor::with_variant(Variant::Mos6510);
+        let banks = std::rc::Rc::new(std::cell::Cell::new(0));
+        let seen = banks.clone();
+        processor
+            .io_port_mut()
+            .unwrap()
+            .set_hook(move |pins| seen.set(pins & 0b0000_0111));
+
+        // The C64 KERNAL set up: LDA #$2f; STA $00; LDA #$36; STA $01; LDA $01; JMP *
+        processor
+            .write_program(&[
+                0xa9, 0x2f, 0x85, 0x00, 0xa9, 0x36, 0x85, 0x01, 0xa5, 0x01, 0x4c, 0x0a, 0x02,
+            ])
+            .unwrap();
+        processor.reset();
+        assert!(processor.execute(0xffff).is_ok());
+
+        // LORAM was switched off
+        assert_eq!(banks.get(), IoPort::HIRAM | IoPort::CHAREN);
+        assert_eq!(processor.a, 0x36);
+
+        // RAM underneath the port is untouched
+        assert_eq!(processor.bus.peek(0x0000), 0x00);
+        assert_eq!(processor.bus.peek(0x0001), 0x00);
+
+        // Reset turns every pin back into a pulled up input
+        processor.reset();
+        assert_eq!(banks.get(), IoPort::LORAM | IoPort::HIRAM | IoPort::CHAREN);
+        assert_eq!(processor.read_byte_at_address(0x0000), 0x00);
+
+        // Other variants have plain RAM there
+        let mut processor = Processor::new();
+        assert!(processor.io_port().is_none());
+        processor.write_byte(0x0001, 0x42);
+        assert_eq!(processor.bus.peek(0x0001), 0x42);
+    }
+
+    // RAM at $0000-$3fff, a register counting its reads at $4000, ROM at $8000-$ffff
+    #[allow(unused)]
+    struct CountingBus {
+        ram: Vec<u8>,
+        rom: Vec<u8>,
+        reads: u8,
+    }
+
+    impl Bus for CountingBus {
+        fn read(&mut self, address: u32) -> u8 {
+            if address == 0x4000 {
+                self.reads += 1;
+            }
+            self.peek(address)
+        }
+
+        fn write(&mut self, address: u32, data: u8) {
+            if address < 0x4000 {
+                self.ram[address as usize] = data;
+            }
+        }
+
+        fn peek(&self, address: u32) -> u8 {
+            match address {
+                0x0000..=0x3fff => self.ram[address as usize],
+                0x4000 => self.reads,
+                0x8000..=0xffff => self.rom[address as usize - 0x8000],
+                _ => 0x00,
+            }
+        }
+    }
+
+    #[test]
+    pub fn test_custom_bus() {
+        // LDA $4000; LDA $4000; STA $10; STA $8000; JMP $800b
+        let mut rom = vec![0; 0x8000];
+        rom[..14].copy_from_slice(&[
+            0xad, 0x00, 0x40, 0xad, 0x00, 0x40, 0x85, 0x10, 0x8d, 0x00, 0x80, 0x4c, 0x0b, 0x80,
+        ]);
+        rom[0x7ffc..0x7ffe].copy_from_slice(&[0x00, 0x80]);
+
+        let bus = CountingBus {
+            ram: vec![0; 0x4000],
+            rom,
+            reads: 0,
+        };
+        let mut processor = Processor::with_bus(Variant::Nmos6502, bus);
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        // Every read reached the register, the write to ROM went nowhere
+        assert_eq!(processor.a, 0x02);
+        assert_eq!(processor.bus().ram[0x10], 0x02);
+        assert_eq!(processor.peek(0x8000), 0xad);
+
+        // Peeking doesn't count as a read
+        assert_eq!(processor.peek(0x4000), 0x02);
+        assert_eq!(processor.bus().reads, 0x02);
+
+        // The bus can also be picked at runtime
+        let mut processor: Processor<Box<dyn Bus>> =
+            Processor::with_bus(Variant::Nmos6502, Box::new(Ram::new(0x1_0000)));
+        processor.write_byte(0x1234, 0x56);
+        assert_eq!(processor.peek(0x1234), 0x56);
     }
 }
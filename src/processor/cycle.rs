@@ -0,0 +1,1505 @@
+// Cycle by cycle execution. tick() runs a single clock cycle, during which the CPU does exactly
+// one bus access, in the order the real chip does them: dummy reads while indexing crosses a
+// page, the double write of read-modify-write instructions, stack reads while S is adjusted...
+// Devices are ticked after every cycle, so they see each access when it really happens.
+//
+// Every variant is laid out here. The 65C02 re-reads instead of writing back during
+// read-modify-write, re-reads the last byte of the instruction while fixing an indexed address,
+// and has its own instructions and modes. The 65816 moves 16 bit data one byte per cycle, spends
+// a cycle on a direct page that isn't page aligned, and its internal cycles read the bus like
+// the dummy reads of the 6502.
+use super::{CpuError, Interrupt, Processor, State};
+use crate::bus::Bus;
+use crate::operators::AddressingMode::{self, *};
+use crate::operators::Mnemonic::{self, *};
+use crate::operators::{Instruction, Opcode};
+use crate::variant::Variant;
+use std::collections::VecDeque;
+
+// One cycle of an instruction
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Step {
+    ////////////////////////////////////// Addressing ///////////////////////////////////////////
+    // Fetch the low byte of the address
+    AddressLow,
+    AddressHigh,
+    // Fetch the high byte and add the index to the low byte, without carrying yet
+    AddressHighIndexed,
+    // Fetch the bank byte of a long address, and add X for long,X
+    AddressBank,
+    // Fetch the zero page address, which is inside the direct page
+    Direct,
+    // The 65816 adds the low byte of a direct page that isn't page aligned
+    DirectOffset,
+    // Read the unindexed zero page address while the index is added
+    ZeroPageIndexed,
+    // Fetch the zero page address of a pointer
+    Pointer,
+    // Read the unindexed pointer while X is added
+    PointerIndexed,
+    // Add S to the offset of the stack relative modes
+    StackOffset,
+    PointerLow,
+    PointerHigh,
+    // Fetch the pointer's high byte and add Y to the low byte, without carrying yet
+    PointerHighIndexed,
+    // Third byte of a long pointer, Y is added for [dp],Y
+    PointerBank,
+    // Read the address before the carry was added to its high byte. When there was nothing to
+    // carry the cycle goes to the data access right away.
+    ReadUnfixed,
+    // Same for writes and read-modify-writes, which always read and fix
+    DummyUnfixed,
+    // Read the last byte of the instruction again while the 65C02 works on the address
+    DummyOperand,
+
+    ////////////////////////////////////// Data access //////////////////////////////////////////
+    Read,
+    Write,
+    // 16 bit data of the 65816 goes low byte first, the high byte runs the instruction
+    ReadLow,
+    ReadHigh,
+    WriteLow,
+    WriteHigh,
+    // Read, write the value back unmodified while the ALU works on it, write the result
+    ModifyRead,
+    ModifyDummy,
+    ModifyWrite,
+    // 16 bit read-modify-write: the high byte is written first
+    ModifyReadHigh,
+    ModifyWriteHigh,
+    ModifyWriteLow,
+    // Read the effective address again, the CMOS parts do it instead of the write back
+    DummyAddress,
+    // Dummy read of the next byte while the instruction runs
+    Implied,
+    ImmediateLow,
+    Immediate,
+
+    /////////////////////////////////// Stack and jumps /////////////////////////////////////////
+    DummyPc,
+    DummyStack,
+    // Run the instruction, whose only access is the push or pull
+    Execute,
+    PushPbr,
+    PushPch,
+    PushPcl,
+    PushStatus,
+    // Push or pull a 16 bit register or value, high byte on top
+    PushHigh,
+    PushLow,
+    PullLow,
+    PullHigh,
+    PullStatus,
+    PullPcl,
+    PullPch,
+    PullPbr,
+    // Dummy read of the byte RTS returns on, then skip it
+    IncrementPc,
+    // Fetch the high byte of the target and jump
+    JumpHigh,
+    // Fetch the bank of the target and jump
+    JumpBank,
+    // Fetch the bank of the target of JSL, which jumps once PC is pushed
+    CallBank,
+    IndirectLow,
+    IndirectHigh,
+    IndirectBank,
+    // BRK and COP skip the byte after them
+    Signature,
+    VectorLow,
+    VectorHigh,
+    Branch,
+    BranchTaken,
+    BranchFix,
+    // One byte of MVN or MVP, then two internal cycles
+    MoveRead,
+    MoveWrite,
+}
+
+// The rest of the instruction (or interrupt) being run one cycle at a time
+pub(super) struct Sequence {
+    instruction: Instruction,
+    interrupt: Interrupt,
+    steps: VecDeque<Step>,
+
+    // Effective address, and what it was before indexing
+    address: u32,
+    base: u32,
+    pointer: u16,
+    data: u8,
+    // 16 bit value being read, written, pushed or pulled
+    word: u16,
+}
+
+impl Sequence {
+    fn new(instruction: Instruction, steps: &[Step]) -> Self {
+        let interrupt = if instruction.mnemonic == COP {
+            Interrupt::Cop
+        } else {
+            Interrupt::Brk
+        };
+
+        Self {
+            instruction,
+            interrupt,
+            steps: steps.iter().copied().collect(),
+            address: 0,
+            base: 0,
+            pointer: 0,
+            data: 0,
+            word: 0,
+        }
+    }
+
+    // The address with the carry from indexing not added to the high byte yet
+    fn unfixed(&self) -> u32 {
+        (self.base & !0xff) | (self.address & 0xff)
+    }
+}
+
+// Which data access an instruction makes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Access {
+    Read,
+    Write,
+    Modify,
+}
+
+fn access(mnemonic: Mnemonic) -> Access {
+    match mnemonic {
+        STA | STX | STY | STZ | SAX | SHA | SHX | SHY | TAS => Access::Write,
+        ASL | LSR | ROL | ROR | INC | DEC | SLO | RLA | SRE | RRA | DCP | ISC | TSB | TRB
+        | RMB(_) | SMB(_) => Access::Modify,
+        _ => Access::Read,
+    }
+}
+
+impl<B: Bus> Processor<B> {
+    // Run one clock cycle: the opcode fetch of the next instruction, or the next bus access of
+    // the one in progress. Interrupts are taken between instructions like execute() does.
+    // Returns true when the CPU is between instructions after the cycle. Fails like execute()
+    // on the fetch of an illegal opcode and once jammed, bus faults are reported when the
+    // instruction causing them is done.
+    pub fn tick(&mut self) -> Result<bool, CpuError> {
+        let done = match self.sequence.take() {
+            Some(sequence) => self.run_step(sequence),
+            None => self.start_sequence()?,
+        };
+
+        self.catch_up(1);
+        if done {
+            if let State::Jammed { address, opcode } = self.state {
+                return Err(CpuError::Jammed { address, opcode });
+            }
+            self.check_faults()?;
+        }
+        Ok(done)
+    }
+
+    // First cycle of an instruction or interrupt
+    fn start_sequence(&mut self) -> Result<bool, CpuError> {
+        match self.state {
+            State::Running => {}
+            State::Waiting if self.nmi_pending || self.irq_asserted() => {
+                self.state = State::Running;
+            }
+            State::Jammed { address, opcode } => return Err(CpuError::Jammed { address, opcode }),
+            State::Waiting | State::Stopped => return Ok(true),
+        }
+
+        let interrupt = if self.nmi_pending {
+            self.nmi_pending = false;
+            Some(Interrupt::Nmi)
+        } else if self.irq_asserted() && !self.sr.interrupt_disable() {
+            Some(Interrupt::Irq)
+        } else {
+            None
+        };
+
+        // Interrupts go through the motions of BRK, with the opcode fetch and the signature
+        // byte read but ignored
+        if let Some(interrupt) = interrupt {
+            self.dummy_pc(self.pc);
+            let entry = self.variant.opcodes()[0];
+            let mut sequence = Sequence::new(Instruction::new(0x00, entry, 0), &self.steps(entry));
+            sequence.steps[0] = Step::DummyPc;
+            sequence.interrupt = interrupt;
+            self.sequence = Some(sequence);
+            return Ok(false);
+        }
+
+        let start = self.pc;
+        let opcode = self.read_byte();
+        let entry = self.widen_immediate(self.variant.opcodes()[opcode as usize]);
+
+        // Stay in front of undocumented opcodes when they aren't enabled, like execute()
+        if entry.undocumented && !self.undocumented_opcodes && !self.variant.has_undefined_nops() {
+            self.pc = start;
+            return Err(CpuError::IllegalOpcode {
+                address: start,
+                opcode,
+            });
+        }
+
+        let instruction = Instruction::new(opcode, entry, 0);
+        let steps = self.steps(entry);
+        if steps.is_empty() {
+            self.handle_opcode(instruction);
+            return Ok(true);
+        }
+        self.sequence = Some(Sequence::new(instruction, &steps));
+        Ok(false)
+    }
+
+    // The cycles following the opcode fetch, for the mode and register widths the CPU is in
+    fn steps(&self, entry: Opcode) -> Vec<Step> {
+        use Step::*;
+
+        let wide = self.wide_data(entry.mnemonic);
+        let access = access(entry.mnemonic);
+        let data: &[Step] = match (access, wide) {
+            (Access::Read, false) => &[Read],
+            (Access::Read, true) => &[ReadLow, ReadHigh],
+            (Access::Write, false) => &[Write],
+            (Access::Write, true) => &[WriteLow, WriteHigh],
+            (Access::Modify, false) if !self.writes_back() => {
+                &[ModifyRead, DummyAddress, ModifyWrite]
+            }
+            (Access::Modify, false) => &[ModifyRead, ModifyDummy, ModifyWrite],
+            (Access::Modify, true) => &[
+                ReadLow,
+                ModifyReadHigh,
+                DummyAddress,
+                ModifyWriteHigh,
+                ModifyWriteLow,
+            ],
+        };
+        // The 65C02 only fixes the address of shifts and rotates when indexing crossed a page
+        let wdc65c02 = self.variant == Variant::Wdc65C02;
+        let shift = matches!(entry.mnemonic, ASL | LSR | ROL | ROR);
+        let indexed = if access == Access::Read || wdc65c02 && shift {
+            ReadUnfixed
+        } else {
+            DummyUnfixed
+        };
+        let native = !self.emulation;
+
+        let mut steps = match (entry.mnemonic, entry.mode) {
+            (BRK | COP, _) if native => {
+                vec![
+                    Signature, PushPbr, PushPch, PushPcl, PushStatus, VectorLow, VectorHigh,
+                ]
+            }
+            (BRK | COP, _) => vec![
+                Signature, PushPch, PushPcl, PushStatus, VectorLow, VectorHigh,
+            ],
+            (RTI, _) if native => {
+                vec![DummyPc, DummyStack, PullStatus, PullPcl, PullPch, PullPbr]
+            }
+            (RTI, _) => vec![DummyPc, DummyStack, PullStatus, PullPcl, PullPch],
+            (RTS, _) => vec![DummyPc, DummyStack, PullPcl, PullPch, IncrementPc],
+            (RTL, _) => vec![DummyPc, DummyStack, PullPcl, PullPch, PullPbr],
+            (JSR, AbsoluteIndexedIndirect) => vec![
+                AddressLow,
+                PushPch,
+                PushPcl,
+                AddressHighIndexed,
+                DummyOperand,
+                IndirectLow,
+                IndirectHigh,
+            ],
+            (JSR, _) => vec![AddressLow, DummyStack, PushPch, PushPcl, JumpHigh],
+            (JSL, _) => {
+                vec![
+                    AddressLow,
+                    AddressHigh,
+                    PushPbr,
+                    DummyStack,
+                    CallBank,
+                    PushPch,
+                    PushPcl,
+                ]
+            }
+            (JMP, Absolute) => vec![AddressLow, JumpHigh],
+            (JMP, Indirect) if wdc65c02 => {
+                vec![
+                    AddressLow,
+                    AddressHigh,
+                    DummyOperand,
+                    IndirectLow,
+                    IndirectHigh,
+                ]
+            }
+            (JMP, Indirect) => vec![AddressLow, AddressHigh, IndirectLow, IndirectHigh],
+            (JMP, AbsoluteIndexedIndirect) => {
+                vec![
+                    AddressLow,
+                    AddressHighIndexed,
+                    DummyOperand,
+                    IndirectLow,
+                    IndirectHigh,
+                ]
+            }
+            (JML, AbsoluteLong) => vec![AddressLow, AddressHigh, JumpBank],
+            (JML, _) => vec![
+                AddressLow,
+                AddressHigh,
+                IndirectLow,
+                IndirectHigh,
+                IndirectBank,
+            ],
+            (BRL, _) => vec![AddressLow, AddressHigh, Implied],
+            (PHA | PHX | PHY | PHD, _) if wide => vec![DummyPc, PushHigh, PushLow],
+            (PHA | PHP | PHX | PHY | PHB | PHK, _) => vec![DummyPc, Execute],
+            (PLA | PLX | PLY | PLD, _) if wide => vec![DummyPc, DummyStack, PullLow, PullHigh],
+            (PLA | PLP | PLX | PLY | PLB, _) => vec![DummyPc, DummyStack, Execute],
+            (PEA, _) => vec![AddressLow, AddressHigh, PushHigh, PushLow],
+            (PEI, _) => vec![Pointer, PointerLow, PointerHigh, PushHigh, PushLow],
+            (PER, _) => vec![AddressLow, AddressHigh, DummyPc, PushHigh, PushLow],
+            (MVN | MVP, _) => {
+                vec![
+                    AddressLow,
+                    AddressHigh,
+                    MoveRead,
+                    MoveWrite,
+                    DummyAddress,
+                    DummyAddress,
+                ]
+            }
+            (WAI | STP | XBA, _) => vec![DummyPc, Implied],
+            (REP | SEP, _) => vec![Immediate, DummyPc],
+            // The single byte NOPs of the 65C02 are done with their opcode fetch
+            (NOP, AddressingMode::Implied) if entry.cycles == 1 => vec![],
+            (_, AddressingMode::Implied | Accumulator) => vec![Implied],
+            (_, AddressingMode::Immediate) => vec![Immediate],
+            (_, ImmediateWord) => vec![ImmediateLow, Immediate],
+            (_, Relative) => vec![Branch],
+            // BBR and BBS read the zero page byte twice before fetching the offset
+            (_, ZeroPageRelative) => vec![Direct, ModifyRead, DummyAddress, Branch],
+            (NOP, Absolute) if entry.cycles == 8 => {
+                [&[AddressLow, AddressHigh, Read], &[DummyAddress; 4][..]].concat()
+            }
+
+            (_, ZeroPage) => [&[Direct], data].concat(),
+            (_, ZeroPageX | ZeroPageY) => [&[Direct, ZeroPageIndexed], data].concat(),
+            (_, Absolute) => [&[AddressLow, AddressHigh], data].concat(),
+            (_, AbsoluteX | AbsoluteY) => {
+                [&[AddressLow, AddressHighIndexed, indexed], data].concat()
+            }
+            (_, AbsoluteLong | AbsoluteLongX) => {
+                [&[AddressLow, AddressHigh, AddressBank], data].concat()
+            }
+            (_, IndirectX) => [&[Pointer, PointerIndexed, PointerLow, PointerHigh], data].concat(),
+            (_, IndirectY) => [&[Pointer, PointerLow, PointerHighIndexed, indexed], data].concat(),
+            (_, ZeroPageIndirect) => [&[Pointer, PointerLow, PointerHigh], data].concat(),
+            (_, ZeroPageIndirectLong | ZeroPageIndirectLongY) => {
+                [&[Pointer, PointerLow, PointerHigh, PointerBank], data].concat()
+            }
+            (_, StackRelative) => [&[Pointer, StackOffset], data].concat(),
+            (_, StackRelativeIndirectY) => {
+                let pointer = [
+                    Pointer,
+                    StackOffset,
+                    PointerLow,
+                    PointerHighIndexed,
+                    DummyUnfixed,
+                ];
+                [&pointer[..], data].concat()
+            }
+            _ => unreachable!("{} {:?} has no cycles laid out", entry.mnemonic, entry.mode),
+        };
+
+        if entry.mode.is_zero_page() && self.dp & 0x00ff != 0 {
+            steps.insert(1, DirectOffset);
+        }
+        steps
+    }
+
+    // Whether the instruction moves 16 bits of data, following the width of its register
+    fn wide_data(&self, mnemonic: Mnemonic) -> bool {
+        match mnemonic {
+            LDA | STA | STZ | ORA | AND | EOR | ADC | SBC | CMP | BIT | TSB | TRB | ASL | LSR
+            | ROL | ROR | INC | DEC | PHA | PLA => self.wide_accumulator(),
+            LDX | LDY | STX | STY | CPX | CPY | PHX | PHY | PLX | PLY => self.wide_index(),
+            PHD | PLD | PEA | PEI | PER => true,
+            _ => false,
+        }
+    }
+
+    fn run_step(&mut self, mut sequence: Sequence) -> bool {
+        let Some(mut step) = sequence.steps.pop_front() else {
+            return true;
+        };
+        if step == Step::ReadUnfixed && sequence.unfixed() == sequence.address {
+            step = sequence
+                .steps
+                .pop_front()
+                .expect("data access after indexing");
+        }
+        let mode = sequence.instruction.mode;
+
+        match step {
+            ////////////////////////////////////// Addressing ///////////////////////////////////
+            Step::AddressLow => sequence.address = self.read_byte() as u32,
+            Step::AddressHigh => {
+                sequence.address |= (self.read_byte() as u32) << 8;
+                if mode == Absolute {
+                    sequence.address |= (self.dbr as u32) << 16;
+                }
+            }
+            // JMP (a,X) and JSR (a,X) find their pointer in the program bank, without carrying
+            // into the bank byte
+            Step::AddressHighIndexed => {
+                let address = sequence.address as u16 | (self.read_byte() as u16) << 8;
+                let index = self.index_register(sequence.instruction);
+                if mode == AbsoluteIndexedIndirect {
+                    sequence.address = self.bank_address(self.pbr, address.wrapping_add(index));
+                } else {
+                    sequence.base = self.bank_address(self.dbr, address);
+                    sequence.address = self.index(sequence.base, index).0;
+                }
+            }
+            Step::AddressBank => {
+                let base = sequence.address | (self.read_byte() as u32) << 16;
+                sequence.address = if mode == AbsoluteLongX {
+                    self.index(base, self.index_x()).0
+                } else {
+                    base
+                };
+            }
+            Step::Direct => {
+                sequence.data = self.read_byte();
+                sequence.address = self.direct_address(sequence.data, 0) as u32;
+            }
+            Step::DirectOffset => self.dummy_pc(self.pc),
+            Step::ZeroPageIndexed => {
+                self.dummy_read(sequence.address);
+                let index = self.index_register(sequence.instruction);
+                sequence.address = self.direct_address(sequence.data, index) as u32;
+            }
+            Step::Pointer => {
+                sequence.data = self.read_byte();
+                sequence.pointer = self.direct_address(sequence.data, 0);
+            }
+            Step::PointerIndexed => {
+                self.dummy_read(sequence.pointer as u32);
+                sequence.pointer = self.direct_address(sequence.data, self.index_x());
+            }
+            Step::StackOffset => {
+                self.dummy_pc(self.pc.wrapping_sub(1));
+                sequence.pointer = self.stack_pointer().wrapping_add(sequence.data as u16);
+                sequence.address = sequence.pointer as u32;
+            }
+            Step::PointerLow => {
+                sequence.address = self.read_byte_at_address(sequence.pointer) as u32;
+            }
+            Step::PointerHigh | Step::PointerHighIndexed => {
+                let high = self.read_byte_at_address(self.pointer_high(&sequence)) as u16;
+                let address = sequence.address as u16 | high << 8;
+                sequence.address = self.bank_address(self.dbr, address);
+                if step == Step::PointerHighIndexed {
+                    sequence.base = sequence.address;
+                    sequence.address = self.index(sequence.base, self.index_y()).0;
+                }
+            }
+            Step::PointerBank => {
+                let pointer = sequence.pointer.wrapping_add(2);
+                let bank = self.read_byte_at_address(pointer) as u32;
+                let base = (sequence.address & 0xffff) | bank << 16;
+                sequence.address = if mode == ZeroPageIndirectLongY {
+                    self.index(base, self.index_y()).0
+                } else {
+                    base
+                };
+            }
+            // The 65C02 reads the last byte of the instruction again instead of the unfixed
+            // address
+            Step::ReadUnfixed | Step::DummyUnfixed if self.variant != Variant::Wdc65C02 => {
+                self.dummy_read(sequence.unfixed());
+            }
+            Step::ReadUnfixed | Step::DummyUnfixed | Step::DummyOperand => {
+                self.dummy_pc(self.pc.wrapping_sub(1));
+            }
+
+            ////////////////////////////////////// Data access //////////////////////////////////
+            Step::Read => {
+                self.read_data(&sequence);
+                self.decimal_cycle(&mut sequence);
+            }
+            Step::Write => self.write_data(&sequence),
+            Step::ReadLow => sequence.word = self.read_long(sequence.address) as u16,
+            Step::ReadHigh => {
+                let high = self.read_long(sequence.address.wrapping_add(1)) as u16;
+                let value = sequence.word | high << 8;
+                self.handle_opcode(Instruction {
+                    mode: ImmediateWord,
+                    operand: value as u32,
+                    ..sequence.instruction
+                });
+                // BIT # leaves N and V alone, the other modes copy them from memory
+                if sequence.instruction.mnemonic == BIT {
+                    self.sr.set_overflow(value & 0x4000 != 0);
+                    self.sr.set_negative(value & 0x8000 != 0);
+                }
+            }
+            Step::WriteLow => {
+                sequence.word = self.stored_word(sequence.instruction.mnemonic);
+                self.write_long(sequence.address, sequence.word as u8);
+            }
+            Step::WriteHigh => {
+                let address = sequence.address.wrapping_add(1);
+                self.write_long(address, (sequence.word >> 8) as u8);
+            }
+            Step::ModifyRead => sequence.data = self.read_long(sequence.address),
+            Step::ModifyDummy => self.write_long(sequence.address, sequence.data),
+            Step::ModifyWrite => {
+                let result = self.modify_value(sequence.instruction.mnemonic, sequence.data);
+                self.write_long(sequence.address, result);
+            }
+            Step::ModifyReadHigh => {
+                let high = self.read_long(sequence.address.wrapping_add(1)) as u16;
+                sequence.word |= high << 8;
+            }
+            Step::ModifyWriteHigh => {
+                let mnemonic = sequence.instruction.mnemonic;
+                sequence.word = self.modify_word_value(mnemonic, sequence.word);
+                let address = sequence.address.wrapping_add(1);
+                self.write_long(address, (sequence.word >> 8) as u8);
+            }
+            Step::ModifyWriteLow => self.write_long(sequence.address, sequence.word as u8),
+            Step::DummyAddress => self.dummy_read(sequence.address),
+            Step::Implied => {
+                self.dummy_pc(self.pc);
+                self.handle_opcode(Instruction {
+                    operand: sequence.address,
+                    ..sequence.instruction
+                });
+            }
+            Step::ImmediateLow => sequence.word = self.read_byte() as u16,
+            Step::Immediate => {
+                let mut operand = self.read_byte() as u32;
+                if mode == ImmediateWord {
+                    operand = sequence.word as u32 | operand << 8;
+                }
+                self.handle_opcode(Instruction {
+                    operand,
+                    ..sequence.instruction
+                });
+                self.decimal_cycle(&mut sequence);
+            }
+
+            /////////////////////////////////// Stack and jumps /////////////////////////////////
+            Step::DummyPc => self.dummy_pc(self.pc),
+            Step::DummyStack => self.dummy_read(self.stack_pointer() as u32),
+            Step::Execute => {
+                self.handle_opcode(sequence.instruction);
+            }
+            Step::PushPbr => self.push_byte(self.pbr),
+            Step::PushPch => self.push_byte((self.pc >> 8) as u8),
+            // JSL jumps once the return address is on the stack
+            Step::PushPcl => {
+                self.push_byte(self.pc as u8);
+                if sequence.instruction.mnemonic == JSL {
+                    self.pc = sequence.address as u16;
+                    self.pbr = sequence.data;
+                }
+            }
+            Step::PushStatus => {
+                let status = if self.emulation {
+                    self.sr.pushed(sequence.interrupt == Interrupt::Brk)
+                } else {
+                    self.sr.bits()
+                };
+                self.push_byte(status);
+                self.sr.set_interrupt_disable(true);
+                if self.variant.is_cmos() {
+                    self.sr.set_decimal(false);
+                }
+            }
+            Step::PushHigh => {
+                sequence.word = self.pushed_word(&sequence);
+                self.push_byte((sequence.word >> 8) as u8);
+            }
+            Step::PushLow => self.push_byte(sequence.word as u8),
+            Step::PullLow => sequence.word = self.pull_byte() as u16,
+            Step::PullHigh => {
+                let value = sequence.word | (self.pull_byte() as u16) << 8;
+                self.pulled_word(sequence.instruction.mnemonic, value);
+            }
+            Step::PullStatus => {
+                let status = self.pull_byte();
+                self.load_status(status);
+            }
+            Step::PullPcl => sequence.address = self.pull_byte() as u32,
+            Step::PullPch => self.pc = (self.pull_byte() as u16) << 8 | sequence.address as u16,
+            // RTL returns on the byte after the one JSL pushed, like RTS
+            Step::PullPbr => {
+                self.pbr = self.pull_byte();
+                if sequence.instruction.mnemonic == RTL {
+                    self.pc = self.pc.wrapping_add(1);
+                }
+            }
+            Step::IncrementPc => {
+                self.read_byte();
+            }
+            Step::JumpHigh => {
+                let high = self.read_long(self.bank_address(self.pbr, self.pc)) as u16;
+                self.pc = high << 8 | sequence.address as u16;
+            }
+            Step::JumpBank => {
+                self.pbr = self.read_byte();
+                self.pc = sequence.address as u16;
+            }
+            Step::CallBank => sequence.data = self.read_long(self.bank_address(self.pbr, self.pc)),
+            Step::IndirectLow => sequence.data = self.read_long(sequence.address),
+            // The NMOS part doesn't carry into the high byte of the pointer. The pointer of
+            // JMP (a,X) stays in the program bank
+            Step::IndirectHigh => {
+                let pointer = sequence.address as u16;
+                let mut next = pointer.wrapping_add(1);
+                if self.variant.has_jmp_indirect_bug() {
+                    next = (pointer & 0xff00) | (next & 0x00ff);
+                }
+                let bank = (sequence.address >> 16) as u8;
+                let high = self.read_long(self.bank_address(bank, next)) as u16;
+                self.pc = high << 8 | sequence.data as u16;
+            }
+            Step::IndirectBank => {
+                let pointer = (sequence.address as u16).wrapping_add(2);
+                self.pbr = self.read_byte_at_address(pointer);
+            }
+            Step::Signature => {
+                self.read_byte();
+            }
+            Step::VectorLow => {
+                let vector = sequence.interrupt.vector(!self.emulation);
+                sequence.data = self.read_byte_at_address(vector);
+            }
+            // Handlers run in bank 0
+            Step::VectorHigh => {
+                let vector = sequence.interrupt.vector(!self.emulation).wrapping_add(1);
+                let high = self.read_byte_at_address(vector) as u16;
+                self.pc = high << 8 | sequence.data as u16;
+                if !self.emulation {
+                    self.pbr = 0;
+                }
+            }
+            // Taking the branch adds the offset to PCL, then one more cycle fixes PCH
+            Step::Branch => {
+                let offset = self.read_byte();
+                if self.branch_taken(sequence.instruction.mnemonic, sequence.data) {
+                    sequence.address = self.pc.wrapping_add(offset as i8 as u16) as u32;
+                    sequence.steps.push_back(Step::BranchTaken);
+                }
+            }
+            Step::BranchTaken => {
+                self.dummy_pc(self.pc);
+                let target = sequence.address as u16;
+                if target & 0xff00 != self.pc & 0xff00 {
+                    self.pc = (self.pc & 0xff00) | (target & 0x00ff);
+                    sequence.steps.push_back(Step::BranchFix);
+                } else {
+                    self.pc = target;
+                }
+            }
+            Step::BranchFix => {
+                self.dummy_pc(self.pc);
+                self.pc = sequence.address as u16;
+            }
+            // The operand is the destination bank, then the source bank
+            Step::MoveRead => {
+                let source = (sequence.address >> 8) as u8;
+                sequence.data = self.read_long(self.bank_address(source, self.index_x()));
+            }
+            Step::MoveWrite => {
+                let destination = sequence.address as u8;
+                let mnemonic = sequence.instruction.mnemonic;
+                sequence.address = self.bank_address(destination, self.index_y());
+                self.move_byte(mnemonic, destination, sequence.data);
+            }
+        }
+
+        if sequence.steps.is_empty() {
+            return true;
+        }
+        self.sequence = Some(sequence);
+        false
+    }
+
+    fn dummy_read(&mut self, address: u32) {
+        self.read_long(address);
+    }
+
+    // Dummy read in the program bank
+    fn dummy_pc(&mut self, address: u16) {
+        self.read_long(self.bank_address(self.pbr, address));
+    }
+
+    fn index_register(&self, instruction: Instruction) -> u16 {
+        match instruction.mode {
+            ZeroPageX | AbsoluteX | IndirectX | AbsoluteIndexedIndirect => self.index_x(),
+            _ => self.index_y(),
+        }
+    }
+
+    // The byte after a pointer. Direct page pointers wrap around inside the page like on the
+    // 6502, the long and stack relative ones don't.
+    fn pointer_high(&self, sequence: &Sequence) -> u16 {
+        let pointer = sequence.pointer;
+        let direct = matches!(
+            sequence.instruction.mode,
+            IndirectX | IndirectY | ZeroPageIndirect
+        );
+        if direct && self.emulation && self.dp & 0x00ff == 0 {
+            (pointer & 0xff00) | (pointer.wrapping_add(1) & 0x00ff)
+        } else {
+            pointer.wrapping_add(1)
+        }
+    }
+
+    // ADC and SBC in decimal mode take one more cycle on the 65C02, reading PC
+    fn decimal_cycle(&self, sequence: &mut Sequence) {
+        let decimal = self.decimal_mode() && self.variant == Variant::Wdc65C02;
+        if decimal && matches!(sequence.instruction.mnemonic, ADC | SBC) {
+            sequence.steps.push_back(Step::DummyPc);
+        }
+    }
+
+    // Run a read instruction on the byte at the effective address, which is read now
+    fn read_data(&mut self, sequence: &Sequence) {
+        self.handle_opcode(Instruction {
+            mode: AbsoluteLong,
+            operand: sequence.address,
+            ..sequence.instruction
+        });
+    }
+
+    fn write_data(&mut self, sequence: &Sequence) {
+        let base = sequence.base as u16;
+        let address = sequence.address as u16;
+        match sequence.instruction.mnemonic {
+            SHA => self.store_high_and_at(base, address, self.a & self.x),
+            SHX => self.store_high_and_at(base, address, self.x),
+            SHY => self.store_high_and_at(base, address, self.y),
+            TAS => {
+                self.sp = self.a & self.x;
+                self.store_high_and_at(base, address, self.sp);
+            }
+            _ => {
+                self.handle_opcode(Instruction {
+                    mode: AbsoluteLong,
+                    operand: sequence.address,
+                    ..sequence.instruction
+                });
+            }
+        }
+    }
+
+    // The 16 bit register a store writes
+    fn stored_word(&self, mnemonic: Mnemonic) -> u16 {
+        match mnemonic {
+            STA => self.accumulator(),
+            STX => self.index_x(),
+            STY => self.index_y(),
+            STZ => 0,
+            _ => unreachable!("{} doesn't store a register", mnemonic),
+        }
+    }
+
+    fn pushed_word(&self, sequence: &Sequence) -> u16 {
+        match sequence.instruction.mnemonic {
+            PHA => self.accumulator(),
+            PHX => self.index_x(),
+            PHY => self.index_y(),
+            PHD => self.dp,
+            PEA | PEI => sequence.address as u16,
+            PER => self.pc.wrapping_add(sequence.address as u16),
+            mnemonic => unreachable!("{} doesn't push a word", mnemonic),
+        }
+    }
+
+    fn pulled_word(&mut self, mnemonic: Mnemonic, value: u16) {
+        match mnemonic {
+            PLA => self.set_accumulator(value),
+            PLX => self.set_index_x(value),
+            PLY => self.set_index_y(value),
+            PLD => self.dp = value,
+            _ => unreachable!("{} doesn't pull a word", mnemonic),
+        }
+        self.sr.set_zero_negative_word(value);
+    }
+
+    // The value a read-modify-write instruction writes back, with the ALU operation the
+    // undocumented ones do with it
+    fn modify_value(&mut self, mnemonic: Mnemonic, value: u8) -> u8 {
+        match mnemonic {
+            ASL => self.shift_left(value),
+            LSR => self.shift_right(value),
+            ROL => self.rotate_left(value),
+            ROR => self.rotate_right(value),
+            INC => self.increment(value),
+            DEC => self.decrement(value),
+            SLO => {
+                let result = self.shift_left(value);
+                self.a |= result;
+                self.sr.set_zero_negative(self.a);
+                result
+            }
+            RLA => {
+                let result = self.rotate_left(value);
+                self.a &= result;
+                self.sr.set_zero_negative(self.a);
+                result
+            }
+            SRE => {
+                let result = self.shift_right(value);
+                self.a ^= result;
+                self.sr.set_zero_negative(self.a);
+                result
+            }
+            RRA => {
+                let result = self.rotate_right(value);
+                self.add_with_carry(result);
+                result
+            }
+            DCP => {
+                let result = value.wrapping_sub(1);
+                self.compare(self.a, result);
+                result
+            }
+            ISC => {
+                let result = value.wrapping_add(1);
+                self.subtract_with_carry(result);
+                result
+            }
+            TSB => {
+                self.sr.set_zero(self.a & value == 0);
+                value | self.a
+            }
+            TRB => {
+                self.sr.set_zero(self.a & value == 0);
+                value & !self.a
+            }
+            RMB(bit) => value & !(1 << bit),
+            SMB(bit) => value | 1 << bit,
+            _ => unreachable!("{} isn't a read-modify-write instruction", mnemonic),
+        }
+    }
+
+    fn modify_word_value(&mut self, mnemonic: Mnemonic, value: u16) -> u16 {
+        match mnemonic {
+            ASL => self.shift_left_word(value),
+            LSR => self.shift_right_word(value),
+            ROL => self.rotate_left_word(value),
+            ROR => self.rotate_right_word(value),
+            INC => self.increment_word(value),
+            DEC => self.decrement_word(value),
+            TSB => {
+                self.sr.set_zero(self.accumulator() & value == 0);
+                value | self.accumulator()
+            }
+            TRB => {
+                self.sr.set_zero(self.accumulator() & value == 0);
+                value & !self.accumulator()
+            }
+            _ => unreachable!("{} isn't a read-modify-write instruction", mnemonic),
+        }
+    }
+
+    // BBR and BBS test the zero page byte they read
+    fn branch_taken(&self, mnemonic: Mnemonic, data: u8) -> bool {
+        match mnemonic {
+            BPL => !self.sr.negative(),
+            BMI => self.sr.negative(),
+            BVC => !self.sr.overflow(),
+            BVS => self.sr.overflow(),
+            BCC => !self.sr.carry(),
+            BCS => self.sr.carry(),
+            BNE => !self.sr.zero(),
+            BEQ => self.sr.zero(),
+            BRA => true,
+            BBR(bit) => data & 1 << bit == 0,
+            BBS(bit) => data & 1 << bit != 0,
+            _ => unreachable!("{} isn't a branch", mnemonic),
+        }
+    }
+}
+
+mod test {
+    #[allow(unused)]
+    use super::*;
+    #[allow(unused)]
+    use crate::bus::Ram;
+    #[allow(unused)]
+    use crate::operators::{NMOS_6502, WDC_65C02, WDC_65C816};
+    #[allow(unused)]
+    use crate::status::Status;
+    #[allow(unused)]
+    use crate::variant::Variant;
+
+    // RAM remembering every access: address, data and whether it was a write
+    #[allow(unused)]
+    struct Recorder {
+        ram: Ram,
+        accesses: Vec<(u32, u8, bool)>,
+    }
+
+    impl Bus for Recorder {
+        fn read(&mut self, address: u32) -> u8 {
+            let data = self.ram.read(address);
+            self.accesses.push((address, data, false));
+            data
+        }
+
+        fn write(&mut self, address: u32, data: u8) {
+            self.ram.write(address, data);
+            self.accesses.push((address, data, true));
+        }
+
+        fn peek(&self, address: u32) -> u8 {
+            self.ram.peek(address)
+        }
+    }
+
+    #[allow(unused)]
+    fn processor(variant: Variant) -> Processor<Recorder> {
+        let recorder = Recorder {
+            ram: Ram::new(0x1_0000),
+            accesses: Vec::new(),
+        };
+        let mut processor = Processor::with_bus(variant, recorder);
+        processor.set_undocumented_opcodes(true);
+        processor
+    }
+
+    // Tick until the instruction in progress is done, returns the cycles it took. A JAM is done
+    // once it locked up the CPU.
+    #[allow(unused)]
+    fn run(processor: &mut Processor<Recorder>) -> u64 {
+        let mut cycles = 1;
+        loop {
+            match processor.tick() {
+                Ok(false) => cycles += 1,
+                Ok(true) | Err(CpuError::Jammed { .. }) => return cycles,
+                Err(error) => panic!("{}", error),
+            }
+        }
+    }
+
+    // Every register, the 65816 ones included
+    #[allow(unused)]
+    fn registers(p: &Processor<Recorder>) -> ([u8; 11], u16, u16, bool) {
+        let bytes = [
+            p.a,
+            p.b,
+            p.x,
+            p.xh,
+            p.y,
+            p.yh,
+            p.sp,
+            p.sh,
+            p.pbr,
+            p.dbr,
+            p.sr.bits(),
+        ];
+        (bytes, p.pc, p.dp, p.emulation)
+    }
+
+    // Accesses since the last call, without the data read
+    #[allow(unused)]
+    fn accesses(processor: &mut Processor<Recorder>) -> Vec<(u32, Option<u8>)> {
+        let accesses = std::mem::take(&mut processor.bus.accesses);
+        accesses
+            .into_iter()
+            .map(|(address, data, write)| (address, write.then_some(data)))
+            .collect()
+    }
+
+    #[test]
+    pub fn matches_execute() {
+        let mut seed: u32 = 1;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as u8
+        };
+
+        let memory: Vec<u8> = (0..0x1_0000).map(|_| random()).collect();
+
+        let variants = [
+            (Variant::Nmos6502, &NMOS_6502),
+            (Variant::Wdc65C02, &WDC_65C02),
+        ];
+        for (opcode, (variant, opcodes)) in
+            (0..=255).flat_map(|opcode| variants.map(|v| (opcode, v)))
+        {
+            // Random operands and registers, so indexing crosses pages and branches go both ways
+            for _ in 0..64 {
+                let mut fast = processor(variant);
+                let mut slow = processor(variant);
+
+                let pc = 0x1000 | random() as u16;
+                let operand = [random(), random()];
+                for processor in [&mut fast, &mut slow] {
+                    processor.bus.ram.bytes_mut().copy_from_slice(&memory);
+                    processor.write_byte(pc, opcode);
+                    processor.write_byte(pc + 1, operand[0]);
+                    processor.write_byte(pc + 2, operand[1]);
+                    processor.bus.accesses.clear();
+                    processor.pc = pc;
+                }
+                let (a, x, y, sp, sr) = (random(), random(), random(), random(), random());
+                for processor in [&mut fast, &mut slow] {
+                    (processor.a, processor.x, processor.y) = (a, x, y);
+                    processor.sp = sp;
+                    processor.sr = Status::from_bits(sr);
+                }
+
+                let bytes = [0, 1, 2].map(|i| fast.peek(pc.wrapping_add(i) as u32));
+                let instruction = Instruction::decode(opcodes, &bytes).unwrap();
+                fast.pc = pc.wrapping_add(instruction.length());
+                let expected = fast.handle_opcode(instruction);
+                let cycles = run(&mut slow);
+
+                let name = format!(
+                    "{:?} {:02x} {} with {:?}",
+                    variant, opcode, instruction, bytes
+                );
+                assert_eq!(cycles, expected, "cycles of {}", name);
+                assert_eq!(
+                    slow.bus.accesses.len() as u64,
+                    cycles,
+                    "accesses of {}",
+                    name
+                );
+                assert_eq!(
+                    (slow.a, slow.x, slow.y),
+                    (fast.a, fast.x, fast.y),
+                    "{}",
+                    name
+                );
+                assert_eq!(
+                    (slow.pc, slow.sp, slow.sr),
+                    (fast.pc, fast.sp, fast.sr),
+                    "{}",
+                    name
+                );
+                assert!(
+                    slow.bus.ram.bytes() == fast.bus.ram.bytes(),
+                    "memory after {}",
+                    name
+                );
+            }
+        }
+    }
+
+    // Same for the 65816, in emulation and native mode with both register widths and a direct
+    // page that is sometimes off a page boundary
+    #[test]
+    pub fn native_matches_execute() {
+        let mut seed: u32 = 7;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12_345);
+            (seed >> 16) as u8
+        };
+
+        let memory: Vec<u8> = (0..0x1_0000).map(|_| random()).collect();
+
+        for opcode in 0..=255 {
+            for _ in 0..64 {
+                let mut fast = processor(Variant::Wdc65C816);
+                let mut slow = processor(Variant::Wdc65C816);
+
+                let pc = 0x1000 | random() as u16;
+                let (pbr, dbr) = (random(), random());
+                let dl = if random() & 1 == 0 { 0 } else { random() };
+                let dp = (random() as u16) << 8 | dl as u16;
+                let (a, b, x, xh, y, yh) =
+                    (random(), random(), random(), random(), random(), random());
+                let (sp, sh, sr) = (random(), random(), random());
+                let emulation = random() & 3 == 0;
+                let bytes = [opcode, random(), random(), random()];
+                for processor in [&mut fast, &mut slow] {
+                    processor.bus.ram.bytes_mut().copy_from_slice(&memory);
+                    for (i, byte) in bytes.iter().enumerate() {
+                        let address = processor.bank_address(pbr, pc.wrapping_add(i as u16));
+                        processor.write_long(address, *byte);
+                    }
+                    processor.bus.accesses.clear();
+                    (processor.a, processor.b) = (a, b);
+                    (processor.x, processor.xh, processor.y, processor.yh) = (x, xh, y, yh);
+                    (processor.sp, processor.sh) = (sp, sh);
+                    (processor.pc, processor.pbr, processor.dbr, processor.dp) = (pc, pbr, dbr, dp);
+                    processor.set_emulation(emulation);
+                    processor.load_status(sr);
+                }
+
+                let entry = fast.widen_immediate(WDC_65C816[opcode as usize]);
+                let mut operand = 0;
+                for i in 0..entry.mode.operand_bytes() {
+                    operand |= (bytes[1 + i as usize] as u32) << (8 * i);
+                }
+                let instruction = Instruction::new(opcode, entry, operand);
+                fast.pc = pc.wrapping_add(instruction.length());
+                let expected = fast.handle_opcode(instruction);
+                let cycles = run(&mut slow);
+
+                let name = format!("{:02x} {} with {:?}", opcode, instruction, bytes);
+                assert_eq!(cycles, expected, "cycles of {}", name);
+                assert_eq!(
+                    slow.bus.accesses.len() as u64,
+                    cycles,
+                    "accesses of {}",
+                    name
+                );
+                assert_eq!(registers(&slow), registers(&fast), "{}", name);
+                assert!(
+                    slow.bus.ram.bytes() == fast.bus.ram.bytes(),
+                    "memory after {}",
+                    name
+                );
+            }
+        }
+    }
+
+    #[test]
+    pub fn bus_order() {
+        let mut processor = processor(Variant::Nmos6502);
+        processor.x = 0x01;
+
+        // INC $10,X: the unindexed address is read, then the old value is written back
+        processor.write_program(&[0xf6, 0x10]).unwrap();
+        processor.write_byte(0x0011, 0x41);
+        processor.pc = 0x0200;
+        accesses(&mut processor);
+        run(&mut processor);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x0200, None),
+                (0x0201, None),
+                (0x0010, None),
+                (0x0011, None),
+                (0x0011, Some(0x41)),
+                (0x0011, Some(0x42)),
+            ]
+        );
+
+        // LDA $12ff,X reads $1200 before the carry gets to the high byte
+        processor.write_program(&[0xbd, 0xff, 0x12]).unwrap();
+        processor.pc = 0x0200;
+        accesses(&mut processor);
+        run(&mut processor);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x0200, None),
+                (0x0201, None),
+                (0x0202, None),
+                (0x1200, None),
+                (0x1300, None),
+            ]
+        );
+
+        // Stores always take the extra cycle
+        processor.write_program(&[0x9d, 0x00, 0x12]).unwrap();
+        processor.pc = 0x0200;
+        accesses(&mut processor);
+        run(&mut processor);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x0200, None),
+                (0x0201, None),
+                (0x0202, None),
+                (0x1201, None),
+                (0x1201, Some(0x00)),
+            ]
+        );
+
+        // JSR $1234 reads the stack before pushing, and fetches the high byte last
+        processor.write_program(&[0x20, 0x34, 0x12]).unwrap();
+        processor.pc = 0x0200;
+        processor.sp = 0xff;
+        accesses(&mut processor);
+        run(&mut processor);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x0200, None),
+                (0x0201, None),
+                (0x01ff, None),
+                (0x01ff, Some(0x02)),
+                (0x01fe, Some(0x02)),
+                (0x0202, None),
+            ]
+        );
+        assert_eq!(processor.pc, 0x1234);
+    }
+
+    #[test]
+    pub fn cmos_bus_order() {
+        let mut processor = processor(Variant::Wdc65C02);
+        processor.x = 0x01;
+
+        // INC $10,X reads the address again instead of writing the old value back
+        processor.write_program(&[0xf6, 0x10]).unwrap();
+        processor.write_byte(0x0011, 0x41);
+        processor.pc = 0x0200;
+        accesses(&mut processor);
+        run(&mut processor);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x0200, None),
+                (0x0201, None),
+                (0x0010, None),
+                (0x0011, None),
+                (0x0011, None),
+                (0x0011, Some(0x42)),
+            ]
+        );
+
+        // LDA $12ff,X reads the high byte of the address again while fixing it
+        processor.write_program(&[0xbd, 0xff, 0x12]).unwrap();
+        processor.pc = 0x0200;
+        accesses(&mut processor);
+        run(&mut processor);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x0200, None),
+                (0x0201, None),
+                (0x0202, None),
+                (0x0202, None),
+                (0x1300, None),
+            ]
+        );
+
+        // JMP ($12ff) takes a cycle more than the NMOS part, and carries into the next page
+        processor.write_program(&[0x6c, 0xff, 0x12]).unwrap();
+        processor.write_byte(0x12ff, 0x34);
+        processor.write_byte(0x1300, 0x56);
+        processor.pc = 0x0200;
+        accesses(&mut processor);
+        assert_eq!(run(&mut processor), 6);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x0200, None),
+                (0x0201, None),
+                (0x0202, None),
+                (0x0202, None),
+                (0x12ff, None),
+                (0x1300, None),
+            ]
+        );
+        assert_eq!(processor.pc, 0x5634);
+    }
+
+    #[test]
+    pub fn native_bus_order() {
+        let mut processor = processor(Variant::Wdc65C816);
+
+        // In emulation mode INC $1234 writes the old value back like the NMOS parts
+        processor.write_program(&[0xee, 0x34, 0x12]).unwrap();
+        processor.write_byte(0x1234, 0x41);
+        processor.pc = 0x0200;
+        accesses(&mut processor);
+        assert_eq!(run(&mut processor), 6);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x0200, None),
+                (0x0201, None),
+                (0x0202, None),
+                (0x1234, None),
+                (0x1234, Some(0x41)),
+                (0x1234, Some(0x42)),
+            ]
+        );
+
+        // In native mode it reads the address again instead
+        processor.set_emulation(false);
+        processor.pc = 0x0200;
+        accesses(&mut processor);
+        assert_eq!(run(&mut processor), 6);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x0200, None),
+                (0x0201, None),
+                (0x0202, None),
+                (0x1234, None),
+                (0x1234, None),
+                (0x1234, Some(0x43)),
+            ]
+        );
+
+        processor.sr.set_memory_8bit(false);
+        processor.dp = 0x0301;
+        processor.dbr = 0x7e;
+
+        // LDA $10 takes a cycle to add the low byte of D, then reads both bytes of A
+        processor.write_program(&[0xa5, 0x10]).unwrap();
+        processor.write_word(0x0311, 0x1234);
+        processor.pc = 0x0200;
+        accesses(&mut processor);
+        assert_eq!(run(&mut processor), 5);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x0200, None),
+                (0x0201, None),
+                (0x0202, None),
+                (0x0311, None),
+                (0x0312, None),
+            ]
+        );
+        assert_eq!((processor.b, processor.a), (0x12, 0x34));
+
+        // STA $1234 writes to the data bank, low byte first
+        processor.write_program(&[0x8d, 0x34, 0x12]).unwrap();
+        processor.pc = 0x0200;
+        accesses(&mut processor);
+        run(&mut processor);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x0200, None),
+                (0x0201, None),
+                (0x0202, None),
+                (0x7e1234, Some(0x34)),
+                (0x7e1235, Some(0x12)),
+            ]
+        );
+
+        // An IRQ pushes the program bank first and takes 8 cycles
+        processor.pbr = 0x12;
+        processor.pc = 0x0200;
+        (processor.sh, processor.sp) = (0x01, 0xff);
+        processor.sr.set_interrupt_disable(false);
+        processor.write_word(0xffee, 0x0300);
+        let status = processor.sr.bits();
+        processor.set_irq(true);
+        accesses(&mut processor);
+        assert_eq!(run(&mut processor), 8);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x120200, None),
+                (0x120200, None),
+                (0x01ff, Some(0x12)),
+                (0x01fe, Some(0x02)),
+                (0x01fd, Some(0x00)),
+                (0x01fc, Some(status)),
+                (0xffee, None),
+                (0xffef, None),
+            ]
+        );
+        assert_eq!((processor.pbr, processor.pc), (0x00, 0x0300));
+    }
+
+    #[test]
+    pub fn interrupts() {
+        let mut processor = processor(Variant::Nmos6502);
+
+        // CLI; NOP; JMP $0201
+        processor
+            .write_program(&[0x58, 0xea, 0x4c, 0x01, 0x02])
+            .unwrap();
+        processor.write_word(0xfffe, 0x0300);
+        processor.reset();
+        assert_eq!(run(&mut processor), 2);
+
+        // The IRQ is taken before the NOP, with B clear in the pushed status
+        processor.set_irq(true);
+        accesses(&mut processor);
+        assert_eq!(run(&mut processor), 7);
+        assert_eq!(
+            accesses(&mut processor),
+            [
+                (0x0201, None),
+                (0x0201, None),
+                (0x01fd, Some(0x02)),
+                (0x01fc, Some(0x01)),
+                (0x01fb, Some(0b0010_0000)),
+                (0xfffe, None),
+                (0xffff, None),
+            ]
+        );
+        assert_eq!(processor.pc, 0x0300);
+        assert!(processor.sr.interrupt_disable());
+    }
+
+    #[test]
+    pub fn errors() {
+        let mut processor = processor(Variant::Nmos6502);
+        processor.set_undocumented_opcodes(false);
+
+        // SLO $10 isn't run while undocumented opcodes are disabled, the CPU stays in front of it
+        processor.write_program(&[0x07, 0x10]).unwrap();
+        processor.reset();
+        for _ in 0..2 {
+            assert_eq!(
+                processor.tick(),
+                Err(CpuError::IllegalOpcode {
+                    address: 0x0200,
+                    opcode: 0x07
+                })
+            );
+            assert_eq!(processor.pc, 0x0200);
+        }
+
+        // JAM locks up on its second cycle, and stays locked up
+        processor.set_undocumented_opcodes(true);
+        processor.write_program(&[0x02]).unwrap();
+        processor.reset();
+        assert_eq!(processor.tick(), Ok(false));
+        for _ in 0..2 {
+            assert_eq!(
+                processor.tick(),
+                Err(CpuError::Jammed {
+                    address: 0x0200,
+                    opcode: 0x02
+                })
+            );
+        }
+    }
+
+    #[test]
+    pub fn mixed_with_execute() {
+        let mut processor = processor(Variant::Nmos6502);
+
+        // LDA #$42; STA $0300; JMP $0205
+        processor
+            .write_program(&[0xa9, 0x42, 0x8d, 0x00, 0x03, 0x4c, 0x05, 0x02])
+            .unwrap();
+        processor.reset();
+
+        // Stop in the middle of the STA, execute() finishes it
+        for _ in 0..3 {
+            processor.tick().unwrap();
+        }
+        assert_eq!(processor.peek(0x0300), 0x00);
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.peek(0x0300), 0x42);
+        assert_eq!(processor.pc, 0x0205);
+
+        // Same on the 65C02: LDA $12ff,X; JMP $0203, stopped before the data read
+        let mut processor = Processor::with_variant(Variant::Wdc65C02);
+        processor
+            .write_program(&[0xbd, 0xff, 0x12, 0x4c, 0x03, 0x02])
+            .unwrap();
+        processor.write_byte(0x1300, 0x42);
+        processor.reset();
+        processor.x = 0x01;
+        for _ in 0..4 {
+            assert_eq!(processor.tick(), Ok(false));
+        }
+        assert_eq!(processor.a, 0x00);
+        processor.execute(0xffff).unwrap();
+        assert_eq!(processor.a, 0x42);
+        assert_eq!(processor.pc, 0x0203);
+    }
+}
@@ -0,0 +1,911 @@
+// 65816 support: 16 bit registers, banks and the instructions only the 65816 has.
+// Everything else runs through the same code as the 8 bit variants.
+use super::{Interrupt, Processor};
+use crate::bus::Bus;
+use crate::operators::AddressingMode::{self, *};
+use crate::operators::Instruction;
+use crate::operators::Mnemonic::{self, *};
+use crate::operators::Opcode;
+use crate::status::Status;
+
+impl<B: Bus> Processor<B> {
+    // Native mode with M clear: A, memory accesses and immediates are 16 bits
+    pub(super) fn wide_accumulator(&self) -> bool {
+        !self.emulation && !self.sr.memory_8bit()
+    }
+
+    // Native mode with X clear: X and Y are 16 bits
+    pub(super) fn wide_index(&self) -> bool {
+        !self.emulation && !self.sr.index_8bit()
+    }
+
+    // Immediates follow the width of the register they are used with
+    pub(super) fn widen_immediate(&self, entry: Opcode) -> Opcode {
+        let wide = entry.mode == Immediate
+            && match entry.mnemonic {
+                ADC | AND | BIT | CMP | EOR | LDA | ORA | SBC => self.wide_accumulator(),
+                CPX | CPY | LDX | LDY => self.wide_index(),
+                _ => false,
+            };
+
+        if wide {
+            Opcode {
+                mode: ImmediateWord,
+                ..entry
+            }
+        } else {
+            entry
+        }
+    }
+
+    // Move between emulation and native mode. Emulation mode forces 8 bit registers and
+    // puts the stack back in page 0x01, native mode starts out with 8 bit registers.
+    pub(super) fn set_emulation(&mut self, emulation: bool) {
+        self.emulation = emulation;
+        if emulation {
+            self.sr = Status::from_bits(self.sr.bits());
+            self.xh = 0;
+            self.yh = 0;
+            self.sh = 0x01;
+        } else {
+            self.sr.set_memory_8bit(true);
+            self.sr.set_index_8bit(true);
+        }
+    }
+
+    // Load the status register from PLP, RTI, REP or SEP.
+    // Going to 8 bit index registers clears their high bytes.
+    pub(super) fn load_status(&mut self, bits: u8) {
+        if self.emulation {
+            self.sr = Status::from_bits(bits);
+            return;
+        }
+
+        self.sr = Status::from_native_bits(bits);
+        if self.sr.index_8bit() {
+            self.xh = 0;
+            self.yh = 0;
+        }
+    }
+
+    // The status register as pushed by PHP. There is no B flag in native mode
+    pub(super) fn pushed_status(&self) -> u8 {
+        if self.emulation {
+            self.sr.pushed(true)
+        } else {
+            self.sr.bits()
+        }
+    }
+
+    // C, the full 16 bit accumulator made of B and A
+    pub(super) fn accumulator(&self) -> u16 {
+        (self.b as u16) << 8 | self.a as u16
+    }
+
+    pub(super) fn set_accumulator(&mut self, value: u16) {
+        self.a = value as u8;
+        self.b = (value >> 8) as u8;
+    }
+
+    pub(super) fn set_index_x(&mut self, value: u16) {
+        self.x = value as u8;
+        self.xh = (value >> 8) as u8;
+    }
+
+    pub(super) fn set_index_y(&mut self, value: u16) {
+        self.y = value as u8;
+        self.yh = (value >> 8) as u8;
+    }
+
+    fn read_long_word(&mut self, address: u32) -> u16 {
+        self.read_long(address) as u16 | (self.read_long(address.wrapping_add(1)) as u16) << 8
+    }
+
+    fn write_long_word(&mut self, address: u32, value: u16) {
+        self.write_long(address, value as u8);
+        self.write_long(address.wrapping_add(1), (value >> 8) as u8);
+    }
+
+    // Fetch a 16 bit operand. Returns the value and the page crossing penalty
+    fn read_operand_word(&mut self, mode: AddressingMode, operand: u32) -> (u16, u64) {
+        if mode == ImmediateWord {
+            return (operand as u16, 0);
+        }
+
+        let (address, page_crossed) = self.effective_address(mode, operand);
+        (self.read_long_word(address), page_crossed as u64)
+    }
+
+    fn store_word(&mut self, mode: AddressingMode, operand: u32, value: u16) {
+        let (address, _) = self.effective_address(mode, operand);
+        self.write_long_word(address, value);
+    }
+
+    // Execute an instruction working on a 16 bit register.
+    // Returns the cycles taken on top of the opcode table ones,
+    // or None when the instruction runs with 8 bit registers.
+    pub(super) fn handle_wide(&mut self, instruction: Instruction) -> Option<u64> {
+        let mode = instruction.mode;
+        let operand = instruction.operand;
+        let wide_accumulator = self.wide_accumulator();
+        let wide_index = self.wide_index();
+
+        let extra = match instruction.mnemonic {
+            //////////////////////////////////////// Loads //////////////////////////////////////////
+            LDA if wide_accumulator => {
+                let (value, extra) = self.read_operand_word(mode, operand);
+                self.set_accumulator(value);
+                self.sr.set_zero_negative_word(value);
+                1 + extra
+            }
+            LDX if wide_index => {
+                let (value, extra) = self.read_operand_word(mode, operand);
+                self.set_index_x(value);
+                self.sr.set_zero_negative_word(value);
+                1 + extra
+            }
+            LDY if wide_index => {
+                let (value, extra) = self.read_operand_word(mode, operand);
+                self.set_index_y(value);
+                self.sr.set_zero_negative_word(value);
+                1 + extra
+            }
+
+            /////////////////////////////////////// Stores //////////////////////////////////////////
+            STA if wide_accumulator => {
+                self.store_word(mode, operand, self.accumulator());
+                1
+            }
+            STZ if wide_accumulator => {
+                self.store_word(mode, operand, 0);
+                1
+            }
+            STX if wide_index => {
+                self.store_word(mode, operand, self.index_x());
+                1
+            }
+            STY if wide_index => {
+                self.store_word(mode, operand, self.index_y());
+                1
+            }
+
+            ////////////////////////////////// Stack operations /////////////////////////////////////
+            PHA if wide_accumulator => {
+                self.push_word(self.accumulator());
+                1
+            }
+            PHX if wide_index => {
+                self.push_word(self.index_x());
+                1
+            }
+            PHY if wide_index => {
+                self.push_word(self.index_y());
+                1
+            }
+            PLA if wide_accumulator => {
+                let value = self.pull_word();
+                self.set_accumulator(value);
+                self.sr.set_zero_negative_word(value);
+                1
+            }
+            PLX if wide_index => {
+                let value = self.pull_word();
+                self.set_index_x(value);
+                self.sr.set_zero_negative_word(value);
+                1
+            }
+            PLY if wide_index => {
+                let value = self.pull_word();
+                self.set_index_y(value);
+                self.sr.set_zero_negative_word(value);
+                1
+            }
+
+            ////////////////////////////////////// Transfers ////////////////////////////////////////
+
+            // The destination decides the width. Index registers are copied whole into C,
+            // with a zero high byte when they are 8 bits.
+            TAX if wide_index => {
+                self.set_index_x(self.accumulator());
+                self.sr.set_zero_negative_word(self.index_x());
+                0
+            }
+            TAY if wide_index => {
+                self.set_index_y(self.accumulator());
+                self.sr.set_zero_negative_word(self.index_y());
+                0
+            }
+            TSX if wide_index => {
+                self.set_index_x(self.stack_pointer());
+                self.sr.set_zero_negative_word(self.index_x());
+                0
+            }
+            TXY if wide_index => {
+                self.set_index_y(self.index_x());
+                self.sr.set_zero_negative_word(self.index_y());
+                0
+            }
+            TYX if wide_index => {
+                self.set_index_x(self.index_y());
+                self.sr.set_zero_negative_word(self.index_x());
+                0
+            }
+            TXA if wide_accumulator => {
+                self.set_accumulator(self.index_x());
+                self.sr.set_zero_negative_word(self.accumulator());
+                0
+            }
+            TYA if wide_accumulator => {
+                self.set_accumulator(self.index_y());
+                self.sr.set_zero_negative_word(self.accumulator());
+                0
+            }
+            // In native mode the whole of X goes to the 16 bit stack pointer
+            TXS if !self.emulation => {
+                self.set_stack_pointer(self.index_x());
+                0
+            }
+
+            ////////////////////////////////////// Logic and arithmetic /////////////////////////////
+            ORA if wide_accumulator => {
+                let (value, extra) = self.read_operand_word(mode, operand);
+                self.set_accumulator(self.accumulator() | value);
+                self.sr.set_zero_negative_word(self.accumulator());
+                1 + extra
+            }
+            AND if wide_accumulator => {
+                let (value, extra) = self.read_operand_word(mode, operand);
+                self.set_accumulator(self.accumulator() & value);
+                self.sr.set_zero_negative_word(self.accumulator());
+                1 + extra
+            }
+            EOR if wide_accumulator => {
+                let (value, extra) = self.read_operand_word(mode, operand);
+                self.set_accumulator(self.accumulator() ^ value);
+                self.sr.set_zero_negative_word(self.accumulator());
+                1 + extra
+            }
+            ADC if wide_accumulator => {
+                let (value, extra) = self.read_operand_word(mode, operand);
+                self.add_with_carry_word(value);
+                1 + extra
+            }
+            SBC if wide_accumulator => {
+                let (value, extra) = self.read_operand_word(mode, operand);
+                self.subtract_with_carry_word(value);
+                1 + extra
+            }
+            CMP if wide_accumulator => {
+                let (value, extra) = self.read_operand_word(mode, operand);
+                self.compare_word(self.accumulator(), value);
+                1 + extra
+            }
+            CPX if wide_index => {
+                let (value, _) = self.read_operand_word(mode, operand);
+                self.compare_word(self.index_x(), value);
+                1
+            }
+            CPY if wide_index => {
+                let (value, _) = self.read_operand_word(mode, operand);
+                self.compare_word(self.index_y(), value);
+                1
+            }
+            BIT if wide_accumulator => {
+                let (value, extra) = self.read_operand_word(mode, operand);
+                self.sr.set_zero(self.accumulator() & value == 0);
+                if mode != ImmediateWord {
+                    self.sr.set_overflow(value & 0x4000 != 0);
+                    self.sr.set_negative(value & 0x8000 != 0);
+                }
+                1 + extra
+            }
+            TSB if wide_accumulator => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.read_modify_write_word(address, |cpu, value| {
+                    cpu.sr.set_zero(cpu.accumulator() & value == 0);
+                    value | cpu.accumulator()
+                });
+                2
+            }
+            TRB if wide_accumulator => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.read_modify_write_word(address, |cpu, value| {
+                    cpu.sr.set_zero(cpu.accumulator() & value == 0);
+                    value & !cpu.accumulator()
+                });
+                2
+            }
+
+            /////////////////////////// Shifts, rotates, increments /////////////////////////////////
+            ASL if wide_accumulator => self.modify_word(mode, operand, Self::shift_left_word),
+            LSR if wide_accumulator => self.modify_word(mode, operand, Self::shift_right_word),
+            ROL if wide_accumulator => self.modify_word(mode, operand, Self::rotate_left_word),
+            ROR if wide_accumulator => self.modify_word(mode, operand, Self::rotate_right_word),
+            INC if wide_accumulator => self.modify_word(mode, operand, Self::increment_word),
+            DEC if wide_accumulator => self.modify_word(mode, operand, Self::decrement_word),
+
+            INX if wide_index => {
+                let value = self.increment_word(self.index_x());
+                self.set_index_x(value);
+                0
+            }
+            INY if wide_index => {
+                let value = self.increment_word(self.index_y());
+                self.set_index_y(value);
+                0
+            }
+            DEX if wide_index => {
+                let value = self.decrement_word(self.index_x());
+                self.set_index_x(value);
+                0
+            }
+            DEY if wide_index => {
+                let value = self.decrement_word(self.index_y());
+                self.set_index_y(value);
+                0
+            }
+
+            _ => return None,
+        };
+
+        Some(extra)
+    }
+
+    // Execute an instruction that only exists on the 65816, with 8 bit registers where
+    // the width matters. Returns the cycles taken on top of the opcode table ones.
+    pub(super) fn handle_native(&mut self, instruction: Instruction) -> u64 {
+        let mode = instruction.mode;
+        let operand = instruction.operand;
+
+        match instruction.mnemonic {
+            ////////////////////////////////////// Mode and flags ///////////////////////////////////
+
+            // Exchange carry and emulation flags
+            XCE => {
+                let carry = self.sr.carry();
+                self.sr.set_carry(self.emulation);
+                self.set_emulation(carry);
+            }
+            // Reset and set status bits. M and X can't be changed in emulation mode
+            REP => self.load_status(self.sr.bits() & !(operand as u8)),
+            SEP => self.load_status(self.sr.bits() | operand as u8),
+
+            //////////////////////////////////////// Jumps //////////////////////////////////////////
+
+            // Branch always, 16 bit offset
+            BRL => self.pc = self.pc.wrapping_add(operand as u16),
+            // Jump to any bank
+            JML => {
+                let (address, _) = self.effective_address(mode, operand);
+                self.pbr = (address >> 16) as u8;
+                self.pc = address as u16;
+            }
+            // Long subroutine call and return, going through the program bank as well
+            JSL => {
+                self.push_byte(self.pbr);
+                self.push_word(self.pc.wrapping_sub(1));
+                self.pbr = (operand >> 16) as u8;
+                self.pc = operand as u16;
+            }
+            RTL => {
+                self.pc = self.pull_word().wrapping_add(1);
+                self.pbr = self.pull_byte();
+            }
+            // Coprocessor interrupt, followed by a signature byte like BRK
+            COP => return self.interrupt(Interrupt::Cop),
+
+            ////////////////////////////////// Stack operations /////////////////////////////////////
+
+            // Push a word: the operand, the word in the direct page, or PC relative
+            PEA => self.push_word(operand as u16),
+            PEI => {
+                let (address, _) = self.effective_address(ZeroPage, operand);
+                let value = self.read_direct_word(address as u16);
+                self.push_word(value);
+            }
+            PER => self.push_word(self.pc.wrapping_add(operand as u16)),
+
+            PHB => self.push_byte(self.dbr),
+            PHK => self.push_byte(self.pbr),
+            PHD => self.push_word(self.dp),
+            PLB => {
+                self.dbr = self.pull_byte();
+                self.sr.set_zero_negative(self.dbr);
+            }
+            PLD => {
+                self.dp = self.pull_word();
+                self.sr.set_zero_negative_word(self.dp);
+            }
+
+            ////////////////////////////////////// Transfers ////////////////////////////////////////
+
+            // C to and from the direct page and the stack pointer, always 16 bits
+            TCD => {
+                self.dp = self.accumulator();
+                self.sr.set_zero_negative_word(self.dp);
+            }
+            TDC => {
+                self.set_accumulator(self.dp);
+                self.sr.set_zero_negative_word(self.dp);
+            }
+            TCS => self.set_stack_pointer(self.accumulator()),
+            TSC => {
+                self.set_accumulator(self.stack_pointer());
+                self.sr.set_zero_negative_word(self.accumulator());
+            }
+            TXY => {
+                self.y = self.x;
+                self.sr.set_zero_negative(self.y);
+            }
+            TYX => {
+                self.x = self.y;
+                self.sr.set_zero_negative(self.x);
+            }
+            // Swap A and B, flags come from the new A
+            XBA => {
+                std::mem::swap(&mut self.a, &mut self.b);
+                self.sr.set_zero_negative(self.a);
+            }
+
+            // Move one byte from source bank:X to destination bank:Y and count C down,
+            // repeating the instruction until C wraps around to 0xffff.
+            // MVN walks upwards and MVP downwards. The data bank ends up on the destination.
+            MVN | MVP => {
+                let destination = operand as u8;
+                let source = (operand >> 8) as u8;
+                let value = self.read_long(self.bank_address(source, self.index_x()));
+                self.move_byte(instruction.mnemonic, destination, value);
+            }
+
+            // Reserved for future expansion, a 2 byte NOP
+            WDM => {}
+
+            _ => unreachable!("{:?} isn't a 65816 instruction", instruction.mnemonic),
+        }
+
+        0
+    }
+
+    // Write the byte MVN or MVP read to destination bank:Y, step X and Y and count C down.
+    // Until C wraps around the instruction runs again.
+    pub(super) fn move_byte(&mut self, mnemonic: Mnemonic, destination: u8, value: u8) {
+        self.write_long(self.bank_address(destination, self.index_y()), value);
+        self.dbr = destination;
+
+        let step = if mnemonic == MVN { 1 } else { 0xffff };
+        let x = self.index_x().wrapping_add(step);
+        let y = self.index_y().wrapping_add(step);
+        if self.wide_index() {
+            self.set_index_x(x);
+            self.set_index_y(y);
+        } else {
+            self.x = x as u8;
+            self.y = y as u8;
+        }
+
+        let count = self.accumulator().wrapping_sub(1);
+        self.set_accumulator(count);
+        if count != 0xffff {
+            self.pc = self.pc.wrapping_sub(3);
+        }
+    }
+
+    // 16 bit A + M + C
+    fn add_with_carry_word(&mut self, value: u16) {
+        if self.decimal_mode() {
+            self.decimal_add_word(value);
+            return;
+        }
+
+        let accumulator = self.accumulator();
+        let sum = accumulator as u32 + value as u32 + self.sr.carry() as u32;
+        let result = sum as u16;
+
+        self.sr.set_overflow((accumulator ^ result) & (value ^ result) & 0x8000 != 0);
+        self.sr.set_carry(sum > 0xffff);
+        self.set_accumulator(result);
+        self.sr.set_zero_negative_word(result);
+    }
+
+    // 16 bit A - M - !C
+    fn subtract_with_carry_word(&mut self, value: u16) {
+        if self.decimal_mode() {
+            self.decimal_subtract_word(value);
+            return;
+        }
+
+        self.add_with_carry_word(!value);
+    }
+
+    // BCD addition of four digits. N and Z reflect the decimal result like on the 65C02.
+    // WDC only documents V for binary mode, so it is modelled on the 8 bit addition: it comes
+    // from the sum with the low three digits adjusted but not the top one.
+    fn decimal_add_word(&mut self, value: u16) {
+        let accumulator = self.accumulator();
+        let mut carry = self.sr.carry() as u16;
+        let mut result = 0;
+
+        for shift in (0..16).step_by(4) {
+            // V looks at the half adjusted sum using signed arithmetic
+            if shift == 12 {
+                let high = (accumulator & 0xf000) as i16 as i32 + (value & 0xf000) as i16 as i32;
+                let signed = high + (result | carry << 12) as i32;
+                self.sr.set_overflow(!(-0x8000..=0x7fff).contains(&signed));
+            }
+
+            let mut digit = (accumulator >> shift & 0x0f) + (value >> shift & 0x0f) + carry;
+            carry = (digit > 0x09) as u16;
+            if carry != 0 {
+                digit = (digit + 0x06) & 0x0f;
+            }
+            result |= digit << shift;
+        }
+
+        self.sr.set_carry(carry != 0);
+        self.set_accumulator(result);
+        self.sr.set_zero_negative_word(result);
+    }
+
+    // BCD subtraction of four digits. C and V are the binary ones like on the 65C02
+    fn decimal_subtract_word(&mut self, value: u16) {
+        let accumulator = self.accumulator();
+        let mut borrow = 1 - self.sr.carry() as i16;
+        let mut result = 0;
+
+        for shift in (0..16).step_by(4) {
+            let mut digit = (accumulator >> shift & 0x0f) as i16 - (value >> shift & 0x0f) as i16;
+            digit -= borrow;
+            borrow = (digit < 0) as i16;
+            if borrow != 0 {
+                digit = (digit + 0x0a) & 0x0f;
+            }
+            result |= (digit as u16) << shift;
+        }
+
+        let binary = accumulator.wrapping_sub(value).wrapping_sub(1 - self.sr.carry() as u16);
+        self.sr.set_overflow((accumulator ^ value) & (accumulator ^ binary) & 0x8000 != 0);
+        self.sr.set_carry(borrow == 0);
+        self.set_accumulator(result);
+        self.sr.set_zero_negative_word(result);
+    }
+
+    fn compare_word(&mut self, register: u16, value: u16) {
+        self.sr.set_carry(register >= value);
+        self.sr.set_zero_negative_word(register.wrapping_sub(value));
+    }
+
+    // 16 bit read-modify-write. Returns the extra cycles: one per extra byte read and written
+    fn modify_word(
+        &mut self,
+        mode: AddressingMode,
+        operand: u32,
+        operation: fn(&mut Self, u16) -> u16,
+    ) -> u64 {
+        if mode == Accumulator {
+            let value = operation(self, self.accumulator());
+            self.set_accumulator(value);
+            return 0;
+        }
+
+        let (address, _) = self.effective_address(mode, operand);
+        self.read_modify_write_word(address, operation);
+        2
+    }
+
+    fn read_modify_write_word(&mut self, address: u32, operation: fn(&mut Self, u16) -> u16) {
+        let value = self.read_long_word(address);
+        let result = operation(self, value);
+        self.write_long_word(address, result);
+    }
+
+    pub(super) fn shift_left_word(&mut self, value: u16) -> u16 {
+        let result = value << 1;
+        self.sr.set_carry(value & 0x8000 != 0);
+        self.sr.set_zero_negative_word(result);
+        result
+    }
+
+    pub(super) fn shift_right_word(&mut self, value: u16) -> u16 {
+        let result = value >> 1;
+        self.sr.set_carry(value & 0x0001 != 0);
+        self.sr.set_zero_negative_word(result);
+        result
+    }
+
+    pub(super) fn rotate_left_word(&mut self, value: u16) -> u16 {
+        let result = value << 1 | self.sr.carry() as u16;
+        self.sr.set_carry(value & 0x8000 != 0);
+        self.sr.set_zero_negative_word(result);
+        result
+    }
+
+    pub(super) fn rotate_right_word(&mut self, value: u16) -> u16 {
+        let result = value >> 1 | (self.sr.carry() as u16) << 15;
+        self.sr.set_carry(value & 0x0001 != 0);
+        self.sr.set_zero_negative_word(result);
+        result
+    }
+
+    pub(super) fn increment_word(&mut self, value: u16) -> u16 {
+        let result = value.wrapping_add(1);
+        self.sr.set_zero_negative_word(result);
+        result
+    }
+
+    pub(super) fn decrement_word(&mut self, value: u16) -> u16 {
+        let result = value.wrapping_sub(1);
+        self.sr.set_zero_negative_word(result);
+        result
+    }
+}
+
+mod test {
+    #[allow(unused)]
+    use super::*;
+    #[allow(unused)]
+    use crate::operators::{Mnemonic, WDC_65C816};
+    #[allow(unused)]
+    use crate::variant::Variant;
+
+    // Build an instruction from the 65816 opcode table
+    #[allow(unused)]
+    fn native(mnemonic: Mnemonic, mode: AddressingMode, operand: u32) -> Instruction {
+        Instruction::assemble(&WDC_65C816, mnemonic, mode, operand).unwrap()
+    }
+
+    // Same with a 16 bit immediate, as execute() decodes it with wide registers
+    #[allow(unused)]
+    fn native_word(mnemonic: Mnemonic, operand: u32) -> Instruction {
+        Instruction {
+            mode: ImmediateWord,
+            ..native(mnemonic, Immediate, operand)
+        }
+    }
+
+    // A 65816 already switched to native mode with 16 bit registers
+    #[allow(unused)]
+    fn native_processor() -> Processor {
+        let mut processor = Processor::with_variant(Variant::Wdc65C816);
+        processor.set_emulation(false);
+        processor.load_status(0b0000_0000);
+        processor
+    }
+
+    #[test]
+    pub fn emulation_mode() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C816);
+
+        // LDA #$42; STA $10; LDX #$ff; TXS; BRA *
+        processor
+            .write_program(&[0xa9, 0x42, 0x85, 0x10, 0xa2, 0xff, 0x9a, 0x80, 0xfe])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert!(processor.emulation);
+        assert_eq!(processor.read_byte_at_address(0x0010), 0x42);
+        assert_eq!(processor.stack_pointer(), 0x01ff);
+    }
+
+    #[test]
+    pub fn mode_switch() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C816);
+
+        // CLC; XCE; REP #$30; LDA #$1234; LDX #$5678; TAY; XBA; BRA *
+        processor
+            .write_program(&[
+                0x18, 0xfb, 0xc2, 0x30, 0xa9, 0x34, 0x12, 0xa2, 0x78, 0x56, 0xa8, 0xeb, 0x80, 0xfe,
+            ])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert!(!processor.emulation);
+        assert!(processor.sr.carry());
+        assert_eq!(processor.accumulator(), 0x3412);
+        assert_eq!(processor.index_x(), 0x5678);
+        assert_eq!(processor.index_y(), 0x1234);
+
+        // 8 bit index registers lose their high byte, B survives 8 bit A
+        processor.handle_opcode(native(SEP, Immediate, 0x30));
+        assert_eq!(processor.index_x(), 0x0078);
+        assert_eq!(processor.accumulator(), 0x3412);
+
+        // Back to emulation mode: the stack goes back to page 0x01
+        processor.set_stack_pointer(0x1fff);
+        processor.handle_opcode(native(SEC, Implied, 0));
+        processor.handle_opcode(native(XCE, Implied, 0));
+        assert!(processor.emulation);
+        assert_eq!(processor.stack_pointer(), 0x01ff);
+
+        // REP can't make registers 16 bits in emulation mode
+        processor.handle_opcode(native(REP, Immediate, 0x30));
+        assert!(!processor.wide_accumulator());
+        assert!(!processor.wide_index());
+    }
+
+    #[test]
+    pub fn wide_arithmetic() {
+        let mut processor = native_processor();
+
+        // LDA #$7fff; CLC; ADC #$0001
+        processor
+            .write_program(&[0xa9, 0xff, 0x7f, 0x18, 0x69, 0x01, 0x00, 0x80, 0xfe])
+            .unwrap();
+        processor.pc = 0x0200;
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.accumulator(), 0x8000);
+        assert_eq!(processor.sr.bits() & 0b1100_0011, 0b1100_0000);
+
+        // Decimal mode works on four digits
+        processor.sr.set_decimal(true);
+        processor.set_accumulator(0x1999);
+        processor.sr.set_carry(false);
+        processor.handle_opcode(native_word(ADC, 0x0001));
+        assert_eq!(processor.accumulator(), 0x2000);
+
+        processor.sr.set_carry(true);
+        processor.handle_opcode(native_word(SBC, 0x0001));
+        assert_eq!(processor.accumulator(), 0x1999);
+        assert!(processor.sr.carry());
+
+        // Memory is read and written 16 bits at a time
+        processor.sr.set_decimal(false);
+        processor.write_word(0x0300, 0x8001);
+        assert_eq!(processor.handle_opcode(native(ASL, Absolute, 0x0300)), 8);
+        assert_eq!(processor.read_word_at_address(0x0300), 0x0002);
+        assert!(processor.sr.carry());
+    }
+
+    #[test]
+    pub fn wide_decimal_flags() {
+        let mut processor = native_processor();
+        processor.sr.set_decimal(true);
+
+        // 5000 + 5000 carries out of the top digit, V comes from the half adjusted $a000
+        processor.set_accumulator(0x5000);
+        processor.sr.set_carry(false);
+        processor.handle_opcode(native_word(ADC, 0x5000));
+        assert_eq!(processor.accumulator(), 0x0000);
+        assert!(processor.sr.carry() && processor.sr.overflow() && processor.sr.zero());
+
+        // 7999 + 0001 carries up to the top digit, which turns negative
+        processor.set_accumulator(0x7999);
+        processor.sr.set_carry(false);
+        processor.handle_opcode(native_word(ADC, 0x0001));
+        assert_eq!(processor.accumulator(), 0x8000);
+        assert!(!processor.sr.carry() && processor.sr.overflow() && processor.sr.negative());
+
+        processor.set_accumulator(0x1234);
+        processor.sr.set_carry(true);
+        processor.handle_opcode(native_word(ADC, 0x4320));
+        assert_eq!(processor.accumulator(), 0x5555);
+        assert!(!processor.sr.carry() && !processor.sr.overflow());
+
+        // SBC takes V from the binary subtraction, N and Z from the decimal result
+        processor.set_accumulator(0x8000);
+        processor.sr.set_carry(true);
+        processor.handle_opcode(native_word(SBC, 0x0001));
+        assert_eq!(processor.accumulator(), 0x7999);
+        assert!(processor.sr.carry() && processor.sr.overflow() && !processor.sr.negative());
+
+        processor.set_accumulator(0x0000);
+        processor.handle_opcode(native_word(SBC, 0x0001));
+        assert_eq!(processor.accumulator(), 0x9999);
+        assert!(!processor.sr.carry() && !processor.sr.overflow() && processor.sr.negative());
+    }
+
+    #[test]
+    pub fn long_addressing() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C816);
+
+        // LDA #$42; STA $7e1234; LDA #$7e; PHA; PLB; LDA #$00; LDA $1234; JSL $018000; BRA *
+        processor
+            .write_program(&[
+                0xa9, 0x42, 0x8f, 0x34, 0x12, 0x7e, 0xa9, 0x7e, 0x48, 0xab, 0xa9, 0x00, 0xad, 0x34,
+                0x12, 0x22, 0x00, 0x80, 0x01, 0x80, 0xfe,
+            ])
+            .unwrap();
+        // $018000: LDX #$07; RTL
+        for (i, byte) in [0xa2, 0x07, 0x6b].iter().enumerate() {
+            processor.write_long(0x018000 + i as u32, *byte);
+        }
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        assert_eq!(processor.read_long(0x7e1234), 0x42);
+        assert_eq!(processor.read_byte_at_address(0x1234), 0x00);
+        assert_eq!(processor.a, 0x42);
+        assert_eq!(processor.x, 0x07);
+        assert_eq!((processor.dbr, processor.pbr), (0x7e, 0x00));
+
+        // [dp],Y takes a 24 bit pointer from the direct page
+        processor.write_word(0x0020, 0x0000);
+        processor.write_byte(0x0022, 0x7e);
+        processor.y = 0x34;
+        processor.write_long(0x7e0034, 0x99);
+        processor.handle_opcode(native(LDA, ZeroPageIndirectLongY, 0x20));
+        assert_eq!(processor.a, 0x99);
+    }
+
+    #[test]
+    pub fn direct_page_and_stack_relative() {
+        let mut processor = native_processor();
+        processor.handle_opcode(native(SEP, Immediate, 0x30));
+
+        // The direct page can be anywhere in bank 0. Not being page aligned costs a cycle
+        processor.dp = 0x1001;
+        processor.write_byte(0x1011, 0x55);
+        assert_eq!(processor.handle_opcode(native(LDA, ZeroPage, 0x10)), 4);
+        assert_eq!(processor.a, 0x55);
+
+        // Indexing doesn't wrap inside the page in native mode
+        processor.dp = 0x1000;
+        processor.x = 0xff;
+        processor.write_byte(0x1100, 0x56);
+        processor.handle_opcode(native(LDA, ZeroPageX, 0x01));
+        assert_eq!(processor.a, 0x56);
+
+        processor.set_stack_pointer(0x1ff0);
+        processor.write_byte(0x1ff3, 0x66);
+        processor.handle_opcode(native(LDA, StackRelative, 0x03));
+        assert_eq!(processor.a, 0x66);
+
+        // PEA and PLD
+        processor.handle_opcode(native(PEA, Absolute, 0x2000));
+        processor.handle_opcode(native(PLD, Implied, 0));
+        assert_eq!(processor.dp, 0x2000);
+        assert_eq!(processor.stack_pointer(), 0x1ff0);
+    }
+
+    #[test]
+    pub fn block_move() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C816);
+        for (i, byte) in [1, 2, 3, 4].iter().enumerate() {
+            processor.write_long(0x011000 + i as u32, *byte);
+        }
+
+        // CLC; XCE; REP #$30; LDA #$0003; LDX #$1000; LDY #$2000; MVN $01,$02; BRA *
+        processor
+            .write_program(&[
+                0x18, 0xfb, 0xc2, 0x30, 0xa9, 0x03, 0x00, 0xa2, 0x00, 0x10, 0xa0, 0x00, 0x20, 0x54,
+                0x02, 0x01, 0x80, 0xfe,
+            ])
+            .unwrap();
+        processor.reset();
+        processor.execute(0xffff).unwrap();
+
+        for (i, byte) in [1, 2, 3, 4].iter().enumerate() {
+            assert_eq!(processor.read_long(0x022000 + i as u32), *byte);
+        }
+        assert_eq!(processor.accumulator(), 0xffff);
+        assert_eq!((processor.index_x(), processor.index_y()), (0x1004, 0x2004));
+        assert_eq!(processor.dbr, 0x02);
+    }
+
+    #[test]
+    pub fn native_interrupts() {
+        let mut processor = native_processor();
+        processor.write_word(0xffe6, 0x3000);
+        processor.write_word(0xffe4, 0x4000);
+        processor.set_stack_pointer(0x1fff);
+        processor.pbr = 0x01;
+        processor.pc = 0x8001;
+
+        // BRK pushes the program bank as well and goes through the native vector
+        assert_eq!(processor.handle_opcode(native(BRK, Implied, 0)), 8);
+        assert_eq!((processor.pbr, processor.pc), (0x00, 0x3000));
+        assert_eq!(processor.read_byte_at_address(0x1fff), 0x01);
+        assert_eq!(processor.read_word_at_address(0x1ffd), 0x8002);
+        assert_eq!(processor.read_byte_at_address(0x1ffc), 0b0000_0000);
+
+        assert_eq!(processor.handle_opcode(native(RTI, Implied, 0)), 7);
+        assert_eq!((processor.pbr, processor.pc), (0x01, 0x8002));
+        assert_eq!(processor.stack_pointer(), 0x1fff);
+
+        // COP has its own vector
+        processor.handle_opcode(native(COP, Immediate, 0x00));
+        assert_eq!(processor.pc, 0x4000);
+    }
+}
@@ -0,0 +1,163 @@
+// Running one instruction at a time and reporting everything it did, for debuggers, tracers
+// and comparing runs against other emulators.
+use super::{CpuError, Executed, Processor, Registers};
+use crate::bus::Bus;
+use crate::operators::Instruction;
+
+// A read or write the CPU put on the bus
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryAccess {
+    pub address: u32,
+    pub data: u8,
+    pub write: bool,
+}
+
+// What one step() did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepReport {
+    // Where the instruction is, bank included on the 65816. When an interrupt was serviced
+    // first it's the start of the handler, `before.pc` is where the CPU got interrupted.
+    pub address: u32,
+    // Opcode and operand bytes
+    pub bytes: Vec<u8>,
+    // None when no instruction started: the CPU is stopped or waiting for an interrupt, or the
+    // step only finished the instruction tick() was in the middle of
+    pub instruction: Option<Instruction>,
+    pub interrupt: bool,
+    pub cycles: u64,
+    pub before: Registers,
+    pub after: Registers,
+    // In the order they happened
+    pub accesses: Vec<MemoryAccess>,
+}
+
+impl<B: Bus> Processor<B> {
+    // Run the next instruction the way execute() would, servicing a pending interrupt first.
+    // Breakpoints don't stop it.
+    pub fn step(&mut self) -> Result<StepReport, CpuError> {
+        let before = self.registers();
+        self.accesses = Some(Vec::new());
+        let result = self.next_instruction();
+        let accesses = self.accesses.take().unwrap_or_default();
+        let Executed {
+            cycles,
+            interrupt,
+            instruction,
+        } = result?;
+
+        let address = match instruction {
+            Some((address, _)) => address,
+            None => self.bank_address(self.pbr, self.pc),
+        };
+        let instruction = instruction.map(|(_, instruction)| instruction);
+        Ok(StepReport {
+            address,
+            bytes: instruction.map_or_else(Vec::new, |instruction| instruction.bytes()),
+            instruction,
+            interrupt,
+            cycles,
+            before,
+            after: self.registers(),
+            accesses,
+        })
+    }
+}
+
+mod test {
+    #[allow(unused)]
+    use super::*;
+    #[allow(unused)]
+    use crate::operators::Mnemonic::*;
+    #[allow(unused)]
+    use crate::processor::State;
+    #[allow(unused)]
+    use crate::variant::Variant;
+
+    #[allow(unused)]
+    fn read(address: u32, data: u8) -> MemoryAccess {
+        MemoryAccess {
+            address,
+            data,
+            write: false,
+        }
+    }
+
+    #[allow(unused)]
+    fn write(address: u32, data: u8) -> MemoryAccess {
+        MemoryAccess {
+            address,
+            data,
+            write: true,
+        }
+    }
+
+    #[test]
+    pub fn report() {
+        let mut processor = Processor::new();
+
+        // LDX #$05; INC $10,X; JMP $0200
+        processor
+            .write_program(&[0xa2, 0x05, 0xf6, 0x10, 0x4c, 0x00, 0x02])
+            .unwrap();
+        processor.write_byte(0x0015, 0x41);
+        processor.reset();
+
+        let report = processor.step().unwrap();
+        assert_eq!(report.address, 0x0200);
+        assert_eq!(report.bytes, vec![0xa2, 0x05]);
+        assert_eq!(
+            report.instruction.map(|instruction| instruction.mnemonic),
+            Some(LDX)
+        );
+        assert_eq!(report.cycles, 2);
+        assert_eq!((report.before.x, report.after.x), (0x00, 0x05));
+        assert_eq!((report.before.pc, report.after.pc), (0x0200, 0x0202));
+
+        // The NMOS read-modify-write writes the old value back before the new one
+        let report = processor.step().unwrap();
+        assert_eq!(report.instruction.unwrap().to_string(), "INC $10,X");
+        assert_eq!(report.cycles, 6);
+        assert_eq!(
+            report.accesses,
+            vec![
+                read(0x0202, 0xf6),
+                read(0x0203, 0x10),
+                read(0x0015, 0x41),
+                write(0x0015, 0x41),
+                write(0x0015, 0x42),
+            ]
+        );
+        assert_eq!(report.after.sr, report.before.sr);
+    }
+
+    #[test]
+    pub fn interrupt_and_wait() {
+        let mut processor = Processor::with_variant(Variant::Wdc65C02);
+
+        // CLI; WAI; handler at $0300: NOP
+        processor.write_program(&[0x58, 0xcb]).unwrap();
+        processor.write_byte(0x0300, 0xea);
+        processor.write_word(0xfffe, 0x0300);
+        processor.reset();
+        processor.step().unwrap();
+        processor.step().unwrap();
+
+        // Nothing happens while waiting
+        let report = processor.step().unwrap();
+        assert_eq!(processor.state(), State::Waiting);
+        assert_eq!((report.instruction, report.cycles), (None, 0));
+        assert!(report.accesses.is_empty());
+
+        // The IRQ is serviced, then the first instruction of the handler runs
+        processor.set_irq(true);
+        let report = processor.step().unwrap();
+        assert!(report.interrupt);
+        assert_eq!((report.before.pc, report.address), (0x0202, 0x0300));
+        assert_eq!(report.bytes, vec![0xea]);
+        assert_eq!(report.cycles, 7 + 2);
+        assert_eq!(
+            report.accesses.iter().filter(|access| access.write).count(),
+            3
+        );
+    }
+}
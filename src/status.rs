@@ -0,0 +1,193 @@
+// Status register (SR) of the 6502
+// Bits meaning:
+//   - 7th: Negative
+//   - 6th: Overflow
+//   - 5th: - (not a real flag, always reads as 1)
+//   - 4th: Break (only exists in the copy pushed on the stack)
+//   - 3rd: Decimal
+//   - 2nd: Interrupt disable
+//   - 1st: Zero
+//   - 0th: Carry
+// In 65816 native mode bits 5 and 4 become real flags:
+//   - 5th: Memory/accumulator width (M), 8 bits when set
+//   - 4th: Index registers width (X), 8 bits when set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Status(u8);
+
+impl Status {
+    pub const CARRY: u8 = 0b0000_0001;
+    pub const ZERO: u8 = 0b0000_0010;
+    pub const INTERRUPT_DISABLE: u8 = 0b0000_0100;
+    pub const DECIMAL: u8 = 0b0000_1000;
+    pub const BREAK: u8 = 0b0001_0000;
+    pub const UNUSED: u8 = 0b0010_0000;
+    pub const OVERFLOW: u8 = 0b0100_0000;
+    pub const NEGATIVE: u8 = 0b1000_0000;
+
+    // 65816 native mode names of bits 5 and 4
+    pub const MEMORY_8BIT: u8 = 0b0010_0000;
+    pub const INDEX_8BIT: u8 = 0b0001_0000;
+
+    // Power on state: interrupts disabled, everything else clear
+    pub fn new() -> Self {
+        Self(Self::UNUSED | Self::INTERRUPT_DISABLE)
+    }
+
+    // Build a status register from a byte. B and bit 5 aren't stored in the register,
+    // so they are normalised the same way PLP and RTI do it.
+    pub fn from_bits(bits: u8) -> Self {
+        Self((bits & !Self::BREAK) | Self::UNUSED)
+    }
+
+    // Build a 65816 native mode status register, where every bit is a flag
+    pub fn from_native_bits(bits: u8) -> Self {
+        Self(bits)
+    }
+
+    pub fn bits(&self) -> u8 {
+        self.0
+    }
+
+    // The byte pushed on the stack. B tells BRK/PHP (set) apart from IRQ/NMI (clear)
+    pub fn pushed(&self, brk: bool) -> u8 {
+        if brk {
+            self.0 | Self::BREAK
+        } else {
+            self.0
+        }
+    }
+
+    fn get(&self, flag: u8) -> bool {
+        self.0 & flag != 0
+    }
+
+    fn set(&mut self, flag: u8, value: bool) {
+        if value {
+            self.0 |= flag;
+        } else {
+            self.0 &= !flag;
+        }
+    }
+
+    pub fn carry(&self) -> bool {
+        self.get(Self::CARRY)
+    }
+
+    pub fn set_carry(&mut self, value: bool) {
+        self.set(Self::CARRY, value);
+    }
+
+    pub fn zero(&self) -> bool {
+        self.get(Self::ZERO)
+    }
+
+    pub fn set_zero(&mut self, value: bool) {
+        self.set(Self::ZERO, value);
+    }
+
+    pub fn interrupt_disable(&self) -> bool {
+        self.get(Self::INTERRUPT_DISABLE)
+    }
+
+    pub fn set_interrupt_disable(&mut self, value: bool) {
+        self.set(Self::INTERRUPT_DISABLE, value);
+    }
+
+    pub fn decimal(&self) -> bool {
+        self.get(Self::DECIMAL)
+    }
+
+    pub fn set_decimal(&mut self, value: bool) {
+        self.set(Self::DECIMAL, value);
+    }
+
+    pub fn overflow(&self) -> bool {
+        self.get(Self::OVERFLOW)
+    }
+
+    pub fn set_overflow(&mut self, value: bool) {
+        self.set(Self::OVERFLOW, value);
+    }
+
+    pub fn negative(&self) -> bool {
+        self.get(Self::NEGATIVE)
+    }
+
+    pub fn set_negative(&mut self, value: bool) {
+        self.set(Self::NEGATIVE, value);
+    }
+
+    pub fn memory_8bit(&self) -> bool {
+        self.get(Self::MEMORY_8BIT)
+    }
+
+    pub fn set_memory_8bit(&mut self, value: bool) {
+        self.set(Self::MEMORY_8BIT, value);
+    }
+
+    pub fn index_8bit(&self) -> bool {
+        self.get(Self::INDEX_8BIT)
+    }
+
+    pub fn set_index_8bit(&mut self, value: bool) {
+        self.set(Self::INDEX_8BIT, value);
+    }
+
+    // Set Z and N from a result, the way loads, transfers and the ALU do it
+    pub fn set_zero_negative(&mut self, value: u8) {
+        self.set_zero(value == 0);
+        self.set_negative(value & 0b1000_0000 != 0);
+    }
+
+    // Same for 16 bit results of the 65816
+    pub fn set_zero_negative_word(&mut self, value: u16) {
+        self.set_zero(value == 0);
+        self.set_negative(value & 0x8000 != 0);
+    }
+}
+
+impl Default for Status {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+mod test {
+    #[allow(unused)]
+    use super::*;
+
+    #[test]
+    pub fn power_on_state() {
+        let status = Status::new();
+        assert_eq!(status.bits(), 0b0010_0100);
+        assert!(status.interrupt_disable());
+        assert!(!status.decimal());
+    }
+
+    #[test]
+    pub fn set_and_clear() {
+        let mut status = Status::new();
+
+        status.set_zero_negative(0x80);
+        assert!(status.negative());
+        assert!(!status.zero());
+
+        // Flags are cleared as well as set
+        status.set_zero_negative(0x00);
+        assert!(!status.negative());
+        assert!(status.zero());
+
+        status.set_carry(true);
+        status.set_overflow(true);
+        status.set_carry(false);
+        assert_eq!(status.bits(), 0b0110_0110);
+    }
+
+    #[test]
+    pub fn break_and_unused_bits() {
+        let status = Status::from_bits(0b1101_1001);
+        assert_eq!(status.bits(), 0b1110_1001);
+        assert_eq!(status.pushed(true), 0b1111_1001);
+        assert_eq!(status.pushed(false), 0b1110_1001);
+    }
+}
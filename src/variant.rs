@@ -0,0 +1,77 @@
+use crate::operators::{Opcode, NMOS_6502, WDC_65C02, WDC_65C816};
+
+// CPU variants built on top of the 6502 core.
+// Anything that differs between the physical parts (decimal mode, bugs, extra opcodes)
+// is decided by looking at the variant the Processor was created with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Variant {
+    // Original MOS Technology NMOS 6502
+    Nmos6502,
+
+    // MOS 6510 of the C64. An NMOS 6502 with an I/O port at $0000 and $0001
+    Mos6510,
+
+    // Ricoh 2A03/2A07 in the NES. An NMOS 6502 with the decimal mode circuitry cut off:
+    // D can be set and cleared but ADC and SBC always work in binary
+    Ricoh2A03,
+
+    // WDC 65C02 CMOS redesign, with the Rockwell bit operations and WAI/STP
+    Wdc65C02,
+
+    // WDC 65C816. Starts in emulation mode, which behaves like a 65C02 without the bit
+    // operations, and switches to native mode with 16 bit registers and 24 bit addresses.
+    Wdc65C816,
+}
+
+impl Variant {
+    // Opcode table used to decode instructions
+    pub fn opcodes(&self) -> &'static [Opcode; 256] {
+        match self {
+            Variant::Nmos6502 | Variant::Mos6510 | Variant::Ricoh2A03 => &NMOS_6502,
+            Variant::Wdc65C02 => &WDC_65C02,
+            Variant::Wdc65C816 => &WDC_65C816,
+        }
+    }
+
+    // Size of the address space in bytes
+    pub fn memory_size(&self) -> usize {
+        match self {
+            Variant::Wdc65C816 => 0x100_0000,
+            _ => 0x1_0000,
+        }
+    }
+
+    // Whether XCE can switch the CPU to 16 bit native mode
+    pub fn has_native_mode(&self) -> bool {
+        matches!(self, Variant::Wdc65C816)
+    }
+
+    // JMP ($xxFF) on the NMOS part fetches the high byte of the target from $xx00 instead of
+    // the next page, because the pointer increment doesn't carry into the high byte.
+    pub fn has_jmp_indirect_bug(&self) -> bool {
+        matches!(self, Variant::Nmos6502 | Variant::Mos6510 | Variant::Ricoh2A03)
+    }
+
+    // Whether the first two bytes of memory are the on-chip I/O port
+    pub fn has_io_port(&self) -> bool {
+        matches!(self, Variant::Mos6510)
+    }
+
+    // Whether the D flag changes how ADC and SBC (and ARR) compute
+    pub fn has_decimal_mode(&self) -> bool {
+        !matches!(self, Variant::Ricoh2A03)
+    }
+
+    // The 65C02 guarantees its undefined opcodes are NOPs, so they always run.
+    // The NMOS ones only run when undocumented opcodes are enabled.
+    pub fn has_undefined_nops(&self) -> bool {
+        matches!(self, Variant::Wdc65C02)
+    }
+
+    // CMOS parts compute valid N and Z in decimal mode, clear D on interrupts and
+    // re-read instead of re-write during read-modify-write. The 65816 only re-reads in native
+    // mode, in emulation mode it writes back like the NMOS parts.
+    pub fn is_cmos(&self) -> bool {
+        matches!(self, Variant::Wdc65C02 | Variant::Wdc65C816)
+    }
+}